@@ -0,0 +1,87 @@
+/* Exercises the C interface: an insert/normal round trip through callbacks. */
+
+#include <assert.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "vixi.h"
+
+typedef struct {
+  char text[256];
+  size_t len;
+  int fail_backspace;
+} Buffer;
+
+static int insert_char(void *user_data, uint64_t view_id, uint32_t ch) {
+  Buffer *buffer = user_data;
+  assert(view_id == 0);
+  assert(ch < 128);
+  buffer->text[buffer->len++] = (char)ch;
+  buffer->text[buffer->len] = '\0';
+  return 0;
+}
+
+/* Appends the `chars` of an `insert`, e.g. text typed in one go. */
+static int edit(void *user_data, uint64_t view_id, const char *method,
+                const char *params) {
+  Buffer *buffer = user_data;
+  const char *key = "{\"chars\":\"";
+  assert(view_id == 0);
+  if (strcmp(method, "insert") != 0) {
+    return 0;
+  }
+  assert(strncmp(params, key, strlen(key)) == 0);
+  for (params += strlen(key); *params != '"'; params++) {
+    buffer->text[buffer->len++] = *params;
+  }
+  buffer->text[buffer->len] = '\0';
+  return 0;
+}
+
+static int backspace(void *user_data, uint64_t view_id) {
+  Buffer *buffer = user_data;
+  (void)view_id;
+  if (buffer->fail_backspace) {
+    return 1;
+  }
+  if (buffer->len > 0) {
+    buffer->text[--buffer->len] = '\0';
+  }
+  return 0;
+}
+
+int main(void) {
+  Buffer buffer = {{0}, 0, 0};
+  VixiCallbacks callbacks;
+  memset(&callbacks, 0, sizeof callbacks);
+  callbacks.user_data = &buffer;
+  callbacks.insert_char = insert_char;
+  callbacks.edit = edit;
+  callbacks.backspace = backspace;
+
+  Vixi *vixi = vixi_new_with_callbacks(callbacks);
+  assert(strcmp(vixi_mode(vixi), "Normal") == 0);
+
+  assert(vixi_process(vixi, "ihellox<BS>") == VIXI_STATUS_OK);
+  assert(strcmp(vixi_mode(vixi), "Insert") == 0);
+  assert(strcmp(buffer.text, "hello") == 0);
+
+  VixiKey esc = {VIXI_MODIFIER_NONE, VIXI_KEY_CODE_ESC, 0};
+  assert(vixi_process_key(vixi, esc) == VIXI_STATUS_OK);
+  assert(strcmp(vixi_mode(vixi), "Normal") == 0);
+
+  VixiKey i = {VIXI_MODIFIER_NONE, VIXI_KEY_CODE_CHAR, 'i'};
+  assert(vixi_process_key(vixi, i) == VIXI_STATUS_OK);
+  assert(strcmp(vixi_mode(vixi), "Insert") == 0);
+
+  buffer.fail_backspace = 1;
+  assert(vixi_process(vixi, "<BS>") == VIXI_STATUS_CLIENT_FAILED);
+  assert(strcmp(vixi_mode(vixi), "Insert") == 0);
+
+  VixiKey bad = {VIXI_MODIFIER_NONE, VIXI_KEY_CODE_KEYPAD, 42};
+  assert(vixi_process_key(vixi, bad) == VIXI_STATUS_INVALID_ARGUMENT);
+
+  vixi_free(vixi);
+  printf("ok\n");
+  return 0;
+}
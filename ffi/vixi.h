@@ -0,0 +1,211 @@
+#ifndef VIXI_H
+#define VIXI_H
+
+/* Generated from src/ffi.rs by cbindgen; see cbindgen.toml. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/*
+ * Mirrors `Key`. `F`, `Char` and `Keypad` take `VixiKey::value`.
+ */
+typedef enum {
+  VIXI_KEY_CODE_BACKSPACE,
+  VIXI_KEY_CODE_LEFT,
+  VIXI_KEY_CODE_RIGHT,
+  VIXI_KEY_CODE_UP,
+  VIXI_KEY_CODE_DOWN,
+  VIXI_KEY_CODE_HOME,
+  VIXI_KEY_CODE_END,
+  VIXI_KEY_CODE_PAGE_UP,
+  VIXI_KEY_CODE_PAGE_DOWN,
+  VIXI_KEY_CODE_DELETE,
+  VIXI_KEY_CODE_INSERT,
+  VIXI_KEY_CODE_F,
+  VIXI_KEY_CODE_CHAR,
+  VIXI_KEY_CODE_NULL,
+  VIXI_KEY_CODE_ESC,
+  VIXI_KEY_CODE_SPACE,
+  VIXI_KEY_CODE_TAB,
+  VIXI_KEY_CODE_CSI,
+  VIXI_KEY_CODE_X_CSI,
+  VIXI_KEY_CODE_EOL,
+  VIXI_KEY_CODE_HELP,
+  VIXI_KEY_CODE_UNDO,
+  VIXI_KEY_CODE_KEYPAD,
+  VIXI_KEY_CODE_KEYPAD_HOME,
+  VIXI_KEY_CODE_KEYPAD_END,
+  VIXI_KEY_CODE_KEYPAD_PAGE_UP,
+  VIXI_KEY_CODE_KEYPAD_PAGE_DOWN,
+  VIXI_KEY_CODE_KEYPAD_PLUS,
+  VIXI_KEY_CODE_KEYPAD_MINUS,
+  VIXI_KEY_CODE_KEYPAD_MULTIPLY,
+  VIXI_KEY_CODE_KEYPAD_DIVIDE,
+  VIXI_KEY_CODE_KEYPAD_ENTER,
+  VIXI_KEY_CODE_KEYPAD_DOT,
+} VixiKeyCode;
+
+typedef enum {
+  VIXI_MODIFIER_NONE,
+  VIXI_MODIFIER_SHIFT,
+  VIXI_MODIFIER_CTRL,
+  VIXI_MODIFIER_ALT,
+  VIXI_MODIFIER_CMD,
+} VixiModifier;
+
+typedef enum {
+  VIXI_STATUS_OK,
+  VIXI_STATUS_INVALID_ARGUMENT,
+  VIXI_STATUS_CLIENT_FAILED,
+} VixiStatus;
+
+typedef struct Vixi Vixi;
+
+typedef int (*VixiPositionFn)(void*, uint64_t, uint64_t, uint64_t);
+
+typedef int (*VixiViewFn)(void*, uint64_t);
+
+typedef int (*VixiViewStrFn)(void*, uint64_t, const char*);
+
+typedef int (*VixiStrFn)(void*, const char*);
+
+/*
+ * Callbacks mirroring the methods of `Client`, each passed `user_data`
+ * first. Callbacks return zero on success; null ones do nothing. They
+ * may be called from any thread, one at a time.
+ *
+ * `Client::char` and `Client::delete` are called `insert_char` and
+ * `delete_forward` here, being keywords in C and C++. JSON parameters are
+ * passed as strings, and `edit` receives null when there are none.
+ *
+ * Inserted text, e.g. a paste, arrives in one call to `edit`, as xi-core's
+ * `insert` with the text in `chars`. Without `edit`, it arrives one
+ * character at a time through `insert_char` and `insert_newline`.
+ */
+typedef struct {
+  void *user_data;
+  int (*notify)(void*, const char*, const char*);
+  /*
+   * Writes a NUL-terminated JSON result of at most `len` bytes.
+   */
+  int (*request)(void*, const char*, const char*, char*, size_t);
+  int (*edit)(void*, uint64_t, const char*, const char*);
+  VixiPositionFn scroll;
+  VixiViewFn left;
+  VixiViewFn left_sel;
+  VixiViewFn right;
+  VixiViewFn right_sel;
+  VixiViewFn up;
+  VixiViewFn up_sel;
+  VixiViewFn down;
+  VixiViewFn down_sel;
+  VixiViewFn delete_forward;
+  VixiViewFn backspace;
+  VixiViewFn del;
+  VixiViewFn page_up;
+  VixiViewFn page_up_sel;
+  VixiViewFn page_down;
+  VixiViewFn page_down_sel;
+  VixiViewFn line_start;
+  VixiViewFn line_start_sel;
+  VixiViewFn line_end;
+  VixiViewFn line_end_sel;
+  VixiViewFn insert_newline;
+  VixiViewFn f1;
+  VixiViewFn f2;
+  int (*insert_char)(void*, uint64_t, uint32_t);
+  VixiPositionFn click;
+  VixiPositionFn drag;
+  /*
+   * Receives the file path, or null, and writes the new view's id.
+   */
+  int (*new_view)(void*, const char*, uint64_t*);
+  VixiViewFn close_view;
+  VixiViewStrFn save;
+  VixiStrFn set_theme;
+  VixiViewStrFn start_plugin;
+  VixiViewStrFn stop_plugin;
+  int (*notify_plugin)(void*, uint64_t, const char*, const char*, const char*);
+  VixiViewFn start_undo_group;
+  VixiViewFn end_undo_group;
+} VixiCallbacks;
+
+/*
+ * A decoded keystroke.
+ */
+typedef struct {
+  VixiModifier modifier;
+  VixiKeyCode code;
+  uint32_t value;
+} VixiKey;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ * Free a `Vixi`.
+ *
+ * # Safety
+ *
+ * `vixi` must be null or have come from `vixi_new_with_callbacks`, and
+ * must not be used again.
+ */
+void vixi_free(Vixi *vixi);
+
+/*
+ * Returns true if vixi is waiting for more keys to complete a command.
+ *
+ * # Safety
+ *
+ * `vixi` must be null or a `Vixi` not yet freed, which no other thread is
+ * using.
+ */
+bool vixi_is_pending(const Vixi *vixi);
+
+/*
+ * The name of the current mode, e.g. "Normal", as a static string.
+ *
+ * # Safety
+ *
+ * `vixi` must be null or a `Vixi` not yet freed, which no other thread is
+ * using.
+ */
+const char *vixi_mode(const Vixi *vixi);
+
+/*
+ * Create a `Vixi` which applies effects through `callbacks`. Free it with
+ * `vixi_free`. The `Vixi` may be used from any thread, one at a time, and
+ * calls back on whichever thread is using it, so `user_data` and every
+ * callback must be safe to use from any thread. Effects are only seen
+ * through callbacks; with all of them null, keys change nothing but the
+ * mode.
+ */
+Vixi *vixi_new_with_callbacks(VixiCallbacks callbacks);
+
+/*
+ * Process a NUL-terminated string of keys in Vim's key notation.
+ *
+ * # Safety
+ *
+ * `vixi` must be null or a `Vixi` not yet freed, which no other thread is
+ * using. `keys` must be null or point to a NUL-terminated string.
+ */
+VixiStatus vixi_process(Vixi *vixi, const char *keys);
+
+/*
+ * Process a single decoded key.
+ *
+ * # Safety
+ *
+ * `vixi` must be null or a `Vixi` not yet freed, which no other thread is
+ * using.
+ */
+VixiStatus vixi_process_key(Vixi *vixi, VixiKey key);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* VIXI_H */
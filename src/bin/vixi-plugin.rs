@@ -0,0 +1,34 @@
+//! Runs vixi as an experimental xi-core plugin, speaking xi's plugin protocol
+//! on stdin and stdout. See `vixi::plugin::Plugin` for what it can't do.
+
+extern crate vixi;
+
+use std::io::{self, BufRead, Write};
+use vixi::plugin::Plugin;
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut plugin = Plugin::new();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                eprintln!("vixi-plugin: failed to read stdin: {}", err);
+                return;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        for message in plugin.handle(&line) {
+            if writeln!(stdout, "{}", message).is_err() {
+                return;
+            }
+        }
+        if stdout.flush().is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Replays a trace written by `Vixi::start_trace`, reporting where vixi now
+//! behaves differently. Exits with 1 on a divergence, 2 on an error.
+
+extern crate serde_json;
+extern crate vixi;
+
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::process;
+use vixi::trace::{TraceEvent, replay};
+
+fn describe(event: &Option<TraceEvent>) -> String {
+    match *event {
+        Some(ref event) => {
+            serde_json::to_string(event).unwrap_or(format!("{:?}", event))
+        }
+        None => String::from("nothing"),
+    }
+}
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: vixi-replay TRACE");
+            process::exit(2);
+        }
+    };
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(err) => {
+            eprintln!("vixi-replay: {}: {}", path, err);
+            process::exit(2);
+        }
+    };
+    match replay(BufReader::new(file)) {
+        Ok(None) => println!("{}: replayed without divergence", path),
+        Ok(Some(divergence)) => {
+            println!("{}:{}: diverged", path, divergence.line);
+            println!("  expected: {}", describe(&divergence.expected));
+            println!("  actual:   {}", describe(&divergence.actual));
+            process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("vixi-replay: {}: {}", path, err);
+            process::exit(2);
+        }
+    }
+}
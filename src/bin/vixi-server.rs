@@ -0,0 +1,34 @@
+//! Serves vixi over JSON-RPC on stdin and stdout, one message per line. See
+//! `vixi::server::Server` for the protocol.
+
+extern crate vixi;
+
+use std::io::{self, BufRead, Write};
+use vixi::server::Server;
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut server = Server::new();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                eprintln!("vixi-server: failed to read stdin: {}", err);
+                return;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        for message in server.handle(&line) {
+            if writeln!(stdout, "{}", message).is_err() {
+                return;
+            }
+        }
+        if stdout.flush().is_err() {
+            return;
+        }
+    }
+}
@@ -1,7 +1,49 @@
-use xrl;
-use xrl::{ClientResult, ViewId};
+use effect::Effect;
+use op::MotionOp;
 use serde_json::Value;
+use futures::Future;
 use futures::future::ok;
+use std::error::Error;
+use std::fmt;
+use view::{Position, ViewId};
+
+/// An error reported by a `Client`.
+#[derive(Debug)]
+pub enum ClientError {
+    NotifyFailed, // A notification could not be sent.
+    RequestFailed, // A request could not be sent.
+    ErrorReturned(Value), // The editor answered with an error.
+    UnexpectedResponse(Value), // The editor's answer could not be understood.
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ClientError::NotifyFailed => write!(f, "notification failed"),
+            ClientError::RequestFailed => write!(f, "request failed"),
+            ClientError::ErrorReturned(ref value) => {
+                write!(f, "editor returned an error: {}", value)
+            }
+            ClientError::UnexpectedResponse(ref value) => {
+                write!(f, "unexpected response: {}", value)
+            }
+        }
+    }
+}
+
+impl Error for ClientError {
+    fn description(&self) -> &str {
+        match *self {
+            ClientError::NotifyFailed => "notification failed",
+            ClientError::RequestFailed => "request failed",
+            ClientError::ErrorReturned(_) => "editor returned an error",
+            ClientError::UnexpectedResponse(_) => "unexpected response",
+        }
+    }
+}
+
+/// The pending result of a `Client` call.
+pub type ClientResult<T> = Box<Future<Item = T, Error = ClientError>>;
 
 pub trait Client {
     fn notify(&mut self, method: &str, params: Value) -> ClientResult<()>;
@@ -46,136 +88,309 @@ pub trait Client {
         method: &str,
         params: Value,
     ) -> ClientResult<()>;
-}
 
-#[derive(Clone)]
-pub struct XrlClient {
-    client: xrl::Client,
-}
-
-impl XrlClient {
-    pub fn new(client: xrl::Client) -> Self {
-        XrlClient { client: client }
+    /// Insert `text` at the cursor in a single request, by default with
+    /// xi-core's `insert` edit command.
+    fn insert(&mut self, view_id: ViewId, text: &str) -> ClientResult<()> {
+        let params = json!({ "chars": text });
+        self.edit(view_id, "insert", Some(params))
     }
-}
 
-impl Client for XrlClient {
-    fn notify(&mut self, method: &str, params: Value) -> ClientResult<()> {
-        self.client.notify(method, params)
-    }
-    fn request(&mut self, method: &str, params: Value) -> ClientResult<Value> {
-        self.client.request(method, params)
-    }
-    fn edit(&mut self, view_id: ViewId, method: &str, params: Option<Value>) -> ClientResult<()> {
-        self.client.edit(view_id, method, params)
-    }
-    fn scroll(&mut self, view_id: ViewId, first_line: u64, last_line: u64) -> ClientResult<()> {
-        self.client.scroll(view_id, first_line, last_line)
-    }
-    fn left(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.left(view_id)
-    }
-    fn left_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.left_sel(view_id)
-    }
-    fn right(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.right(view_id)
-    }
-    fn right_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.right_sel(view_id)
-    }
-    fn up(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.up(view_id)
-    }
-    fn up_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.up_sel(view_id)
-    }
-    fn down(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.down(view_id)
-    }
-    fn down_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.down_sel(view_id)
-    }
-    fn delete(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.delete(view_id)
-    }
-    fn backspace(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.backspace(view_id)
-    }
-    fn del(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.del(view_id)
-    }
-    fn page_up(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.page_up(view_id)
-    }
-    fn page_up_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.page_up_sel(view_id)
-    }
-    fn page_down(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.page_down(view_id)
-    }
-    fn page_down_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.page_down_sel(view_id)
-    }
-    fn line_start(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.line_start(view_id)
-    }
-    fn line_start_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.line_start_sel(view_id)
-    }
-    fn line_end(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.line_end(view_id)
-    }
-    fn line_end_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.line_end_sel(view_id)
+    /// Whether edits between `start_undo_group` and `end_undo_group` are
+    /// undone as one. xi-core's protocol has no way to group edits, so by
+    /// default they aren't, and `Applier` works around it where it can.
+    fn groups_undo(&self) -> bool {
+        false
     }
-    fn insert_newline(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.insert_newline(view_id)
-    }
-    fn f1(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.f1(view_id)
+
+    /// Start grouping edits, to be undone as one. By default this does
+    /// nothing, as for xi-core.
+    fn start_undo_group(&mut self, _view_id: ViewId) -> ClientResult<()> {
+        Box::new(ok(()))
     }
-    fn f2(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.f2(view_id)
+
+    /// End the group of edits started last.
+    fn end_undo_group(&mut self, _view_id: ViewId) -> ClientResult<()> {
+        Box::new(ok(()))
     }
-    fn char(&mut self, view_id: ViewId, ch: char) -> ClientResult<()> {
-        self.client.char(view_id, ch)
+
+    /// Move the cursor by `motion` `count` times in a single request, if
+    /// the editor can. By default it can't, and `apply` moves once per
+    /// count instead.
+    fn move_count(
+        &mut self,
+        _view_id: ViewId,
+        _motion: MotionOp,
+        _count: i32,
+    ) -> Option<ClientResult<()>> {
+        None
     }
-    fn click(&mut self, view_id: ViewId, line: u64, column: u64) -> ClientResult<()> {
-        self.client.click(view_id, line, column)
+}
+
+/// Synchronous read access to the text of an editor's views, the companion
+/// of `Client`. Columns are byte offsets into a line.
+pub trait TextQuery {
+    fn line_count(&self, view_id: ViewId) -> u64;
+    /// The text of `line`, without its trailing newline.
+    fn line(&self, view_id: ViewId, line: u64) -> Option<String>;
+    fn cursor(&self, view_id: ViewId) -> Option<Position>;
+    /// Selected spans, as (start, end) pairs.
+    fn selections(&self, view_id: ViewId) -> Vec<(Position, Position)>;
+
+    fn char_at(&self, view_id: ViewId, position: Position) -> Option<char> {
+        let line = self.line(view_id, position.line)?;
+        line.get(position.column as usize..)
+            .and_then(|rest| rest.chars().next())
     }
-    fn drag(&mut self, view_id: ViewId, line: u64, column: u64) -> ClientResult<()> {
-        self.client.drag(view_id, line, column)
+
+    /// Width of a tab, in columns. Defaults to xi-core's `tab_size`.
+    fn tab_size(&self, _view_id: ViewId) -> u64 {
+        4
     }
-    fn new_view(&mut self, file_path: Option<String>) -> ClientResult<ViewId> {
-        self.client.new_view(file_path)
+
+    /// Whether tabs are inserted as spaces, as with Vim's 'expandtab'.
+    /// Defaults to Vim's default, off.
+    fn expand_tab(&self, _view_id: ViewId) -> bool {
+        false
     }
-    fn close_view(&mut self, view_id: ViewId) -> ClientResult<()> {
-        self.client.close_view(view_id)
+}
+
+/// Forward `effect` to `client`, returning the issued requests in order.
+/// Effects with no editor-side meaning, such as mode changes, issue nothing.
+pub fn apply(
+    client: &mut Client,
+    view_id: ViewId,
+    effect: &Effect,
+) -> Vec<ClientResult<()>> {
+    match *effect {
+        Effect::InsertChar(c) => vec![client.char(view_id, c)],
+        Effect::InsertNewline => vec![client.insert_newline(view_id)],
+        Effect::Insert(ref text) => vec![client.insert(view_id, text)],
+        Effect::Move(m) => vec![move_cursor(client, view_id, m)],
+        Effect::MoveCount(m, count) => repeat_motion(client, view_id, m, count),
+        Effect::Delete(m) => delete(client, view_id, m),
+        Effect::MoveTo(p) => vec![client.click(view_id, p.line, p.column)],
+        Effect::Replace(start, end, ref text) => {
+            replace(client, view_id, start, end, text)
+        }
+        Effect::Scroll(first, last) => vec![client.scroll(view_id, first, last)],
+        Effect::Save(ref path) => vec![client.save(view_id, path)],
+        Effect::Undo => vec![client.edit(view_id, "undo", None)],
+        Effect::Redo => vec![client.edit(view_id, "redo", None)],
+        Effect::StartUndoGroup => vec![client.start_undo_group(view_id)],
+        Effect::EndUndoGroup => vec![client.end_undo_group(view_id)],
+        // Commands and extensions are run by `Vixi`.
+        Effect::ModeChange(_) |
+        Effect::Message(_) |
+        Effect::Command(..) |
+        Effect::Operate(..) => Vec::new(),
     }
-    fn save(&mut self, view_id: ViewId, file_path: &str) -> ClientResult<()> {
-        self.client.save(view_id, file_path)
+}
+
+/// Forwards effects to a client as `apply` does, for a client which can't
+/// group edits, e.g. xi-core, keeping a deletion made within an undo group
+/// selected until the next request. Text inserted next then replaces it in
+/// a single `insert` edit, which xi-core undoes as one step along with the
+/// typing that follows, e.g. for `cw`.
+pub struct Applier {
+    in_group: bool, // Between `StartUndoGroup` and `EndUndoGroup`.
+    held: Option<ViewId>, // Where a selection waits to be deleted.
+}
+
+impl Applier {
+    pub fn new() -> Self {
+        Applier {
+            in_group: false,
+            held: None,
+        }
     }
-    fn set_theme(&mut self, theme: &str) -> ClientResult<()> {
-        self.client.set_theme(theme)
+
+    /// Forward `effect` to `client`, returning the issued requests in order.
+    pub fn apply(
+        &mut self,
+        client: &mut Client,
+        view_id: ViewId,
+        effect: &Effect,
+    ) -> Vec<ClientResult<()>> {
+        if client.groups_undo() {
+            return apply(client, view_id, effect);
+        }
+        let mut requests = Vec::new();
+        match *effect {
+            Effect::ModeChange(_) | Effect::Message(_) => {
+                return requests;
+            }
+            // Inserting replaces the selection.
+            Effect::InsertChar(_) |
+            Effect::InsertNewline |
+            Effect::Insert(_) => {
+                self.held = None;
+            }
+            _ => {
+                if let Some(held) = self.held.take() {
+                    requests.push(client.delete(held));
+                }
+            }
+        }
+        match *effect {
+            Effect::StartUndoGroup => self.in_group = true,
+            Effect::EndUndoGroup => self.in_group = false,
+            Effect::Delete(motion) if self.in_group => {
+                if let Some(select) = select(client, view_id, motion) {
+                    requests.push(select);
+                    self.held = Some(view_id);
+                    return requests;
+                }
+            }
+            Effect::Replace(start, end, ref text)
+                if self.in_group && text.is_empty() && start != end =>
+            {
+                requests.push(client.click(view_id, start.line, start.column));
+                requests.push(client.drag(view_id, end.line, end.column));
+                self.held = Some(view_id);
+                return requests;
+            }
+            _ => {}
+        }
+        requests.extend(apply(client, view_id, effect));
+        requests
     }
-    fn start_plugin(&mut self, view_id: ViewId, name: &str) -> ClientResult<()> {
-        self.client.start_plugin(view_id, name)
+}
+
+fn move_cursor(
+    client: &mut Client,
+    view_id: ViewId,
+    motion: MotionOp,
+) -> ClientResult<()> {
+    match motion {
+        MotionOp::Left | MotionOp::Backspace => client.left(view_id),
+        MotionOp::Right => client.right(view_id),
+        MotionOp::Up => client.up(view_id),
+        MotionOp::Down => client.down(view_id),
+        MotionOp::Top => {
+            client.edit(view_id, "move_to_beginning_of_document", None)
+        }
+        MotionOp::Bottom => {
+            client.edit(view_id, "move_to_end_of_document", None)
+        }
+        MotionOp::Word => client.edit(view_id, "move_word_right", None),
+        MotionOp::WordBackward => client.edit(view_id, "move_word_left", None),
+        MotionOp::PageUp => client.page_up(view_id),
+        MotionOp::PageDown => client.page_down(view_id),
+        MotionOp::Home => client.line_start(view_id),
+        MotionOp::End => client.line_end(view_id),
+        MotionOp::Custom(_) => Box::new(ok(())), // Resolved by `Vixi`.
     }
-    fn stop_plugin(&mut self, view_id: ViewId, name: &str) -> ClientResult<()> {
-        self.client.stop_plugin(view_id, name)
+}
+
+fn repeat_motion(
+    client: &mut Client,
+    view_id: ViewId,
+    motion: MotionOp,
+    count: i32,
+) -> Vec<ClientResult<()>> {
+    if let Some(request) = client.move_count(view_id, motion, count) {
+        return vec![request];
+    }
+    (0..count).map(|_| move_cursor(client, view_id, motion)).collect()
+}
+
+fn delete(
+    client: &mut Client,
+    view_id: ViewId,
+    motion: MotionOp,
+) -> Vec<ClientResult<()>> {
+    // Xi has dedicated commands for the most common deletions. Anything
+    // else is deleted by extending the selection over the motion.
+    match select(client, view_id, motion) {
+        Some(select) => vec![select, client.delete(view_id)],
+        None => delete_directly(client, view_id, motion),
     }
-    fn notify_plugin(
-        &mut self,
-        view_id: ViewId,
-        plugin: &str,
-        method: &str,
-        params: Value,
-    ) -> ClientResult<()> {
-        self.client.notify_plugin(view_id, plugin, method, &params)
+}
+
+/// Extend the selection over `motion`, for deleting it, unless xi has a
+/// dedicated command for the deletion.
+fn select(
+    client: &mut Client,
+    view_id: ViewId,
+    motion: MotionOp,
+) -> Option<ClientResult<()>> {
+    let request = match motion {
+        MotionOp::Left |
+        MotionOp::Backspace |
+        MotionOp::Right |
+        MotionOp::WordBackward |
+        MotionOp::Home |
+        MotionOp::Custom(_) => {
+            return None;
+        }
+        // Linewise `dj` and `dk` are resolved by `Vixi` instead.
+        MotionOp::Up => client.up_sel(view_id),
+        MotionOp::Down => client.down_sel(view_id),
+        MotionOp::Top => {
+            client.edit(
+                view_id,
+                "move_to_beginning_of_document_and_modify_selection",
+                None,
+            )
+        }
+        MotionOp::Bottom => {
+            client.edit(
+                view_id,
+                "move_to_end_of_document_and_modify_selection",
+                None,
+            )
+        }
+        MotionOp::Word => {
+            client.edit(view_id, "move_word_right_and_modify_selection", None)
+        }
+        MotionOp::PageUp => client.page_up_sel(view_id),
+        MotionOp::PageDown => client.page_down_sel(view_id),
+        MotionOp::End => client.line_end_sel(view_id),
+    };
+    Some(request)
+}
+
+/// Delete over `motion` with xi's dedicated command for it, if any.
+fn delete_directly(
+    client: &mut Client,
+    view_id: ViewId,
+    motion: MotionOp,
+) -> Vec<ClientResult<()>> {
+    match motion {
+        MotionOp::Left | MotionOp::Backspace => vec![client.backspace(view_id)],
+        MotionOp::Right => vec![client.delete(view_id)],
+        MotionOp::WordBackward => {
+            vec![client.edit(view_id, "delete_word_backward", None)]
+        }
+        MotionOp::Home => {
+            vec![client.edit(view_id, "delete_to_beginning_of_line", None)]
+        }
+        _ => Vec::new(), // User motions are resolved by `Vixi`.
     }
 }
 
+fn replace(
+    client: &mut Client,
+    view_id: ViewId,
+    start: Position,
+    end: Position,
+    text: &str,
+) -> Vec<ClientResult<()>> {
+    let mut requests = vec![client.click(view_id, start.line, start.column)];
+    if start != end {
+        requests.push(client.drag(view_id, end.line, end.column));
+        if text.is_empty() {
+            requests.push(client.delete(view_id));
+        }
+    }
+    if !text.is_empty() {
+        // Inserting replaces the selection.
+        requests.push(client.insert(view_id, text));
+    }
+    requests
+}
+
 #[derive(Clone)]
 pub struct DummyClient;
 
@@ -0,0 +1,94 @@
+use client::Client;
+use op::CommandId;
+use view::ViewId;
+
+/// What a command is run with.
+pub struct Context<'a> {
+    pub count: i32, // [count] typed before the command, 1 if none.
+    pub register: Option<char>, // Register named with `"`, if any.
+    pub view_id: ViewId, // The focused view.
+    pub client: Option<&'a mut Client>, // The client, if vixi has one.
+}
+
+/// A command's failure, as a message to show the user.
+pub type CommandResult = Result<(), String>;
+
+/// The body of a command.
+pub type Command = Box<FnMut(&mut Context) -> CommandResult + Send>;
+
+/// Commands defined by the embedder, e.g. to open a file finder or run
+/// tests, for binding to keys alongside the built-in commands.
+pub struct Commands {
+    commands: Vec<(String, Command)>, // Indexed by `CommandId`.
+}
+
+impl Commands {
+    pub fn new() -> Self {
+        Commands { commands: Vec::new() }
+    }
+
+    /// Define a command, replacing any other of the same name. Bindings
+    /// to the replaced command run the new one.
+    pub fn define(&mut self, name: &str, command: Command) -> CommandId {
+        if let Some(id) = self.id(name) {
+            self.commands[id.0 as usize].1 = command;
+            return id;
+        }
+        self.commands.push((String::from(name), command));
+        CommandId(self.commands.len() as u32 - 1)
+    }
+
+    /// The id of the command called `name`.
+    pub fn id(&self, name: &str) -> Option<CommandId> {
+        self.commands
+            .iter()
+            .position(|&(ref n, _)| n == name)
+            .map(|index| CommandId(index as u32))
+    }
+
+    pub fn name(&self, id: CommandId) -> Option<&str> {
+        self.commands.get(id.0 as usize).map(|&(ref n, _)| n.as_str())
+    }
+
+    /// Run a command, returning `None` if it is not defined.
+    pub fn run(
+        &mut self,
+        id: CommandId,
+        context: &mut Context,
+    ) -> Option<CommandResult> {
+        let command = &mut self.commands.get_mut(id.0 as usize)?.1;
+        Some(command(context))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn define_and_run() {
+        let mut commands = Commands::new();
+        let first = commands.define("first", Box::new(|_| Ok(())));
+        let second = commands.define(
+            "second",
+            Box::new(|context| Err(format!("count {}", context.count))),
+        );
+        assert_eq!(Some(second), commands.id("second"));
+        assert_eq!(Some("first"), commands.name(first));
+        assert_eq!(None, commands.id("third"));
+
+        let mut context = Context {
+            count: 3,
+            register: None,
+            view_id: ViewId(0),
+            client: None,
+        };
+        let result = commands.run(second, &mut context);
+        assert_eq!(Some(Err(String::from("count 3"))), result);
+
+        // Redefining keeps the id.
+        assert_eq!(second, commands.define("second", Box::new(|_| Ok(()))));
+        assert_eq!(Some(Ok(())), commands.run(second, &mut context));
+        assert_eq!(None, commands.run(CommandId(7), &mut context));
+    }
+}
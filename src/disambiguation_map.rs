@@ -1,11 +1,12 @@
 use ordered_vec_map::{InsertionResult, OrderedVecMap, RemovalResult};
 use std::cmp::{min, max, Ord, Ordering};
+use std::slice::Iter;
 use typeahead::{Parse, RemapType, Typeahead};
 
 // TODO Handle noremap (key,value) by surrounding value with non-input-able
 // keys, so if it gets put in the typeahead, it cannot possibly be remapped.
 // This would also mean such values would be ignored by the op-map.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct DisambiguationMap<K, T>
 where
     K: Ord,
@@ -140,22 +141,62 @@ where
         result
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.vec_map.is_empty()
+    }
+
+    /// Iterates over every entry, in key order.
+    pub fn iter(&self) -> Iter<(Vec<K>, T)> {
+        self.vec_map.iter()
+    }
+
+    /// Returns the value mapped from exactly `key`.
+    pub fn get(&self, key: &Vec<K>) -> Option<&T> {
+        self.vec_map.find(key).map(|kv| &kv.1)
+    }
+
+    /// Returns every entry whose key strictly extends `prefix`, in key order.
+    pub fn continuations(&self, prefix: &Vec<K>) -> Vec<&(Vec<K>, T)> {
+        // Keys sharing a prefix are contiguous, starting just after it.
+        let mut index = match self.vec_map.find_idx(prefix) {
+            Ok(idx) => idx + 1,
+            Err(idx) => idx,
+        };
+        let mut result = Vec::new();
+        while let Some(kv) = self.vec_map.get(index) {
+            if !kv.0.starts_with(prefix) {
+                break;
+            }
+            result.push(kv);
+            index += 1;
+        }
+        result
+    }
+
+    /// Collects the keys at the front of `typeahead` to look up. When
+    /// remapping, only the leading keys which may be remapped are taken,
+    /// and the query is closed if a key which may not be ends it.
     fn fill_query(
         &self,
         typeahead: &Typeahead<K>,
         remap_type: RemapType,
-    ) -> Vec<K> {
+    ) -> (Vec<K>, bool) {
         // Optimization:
         // Limit query length to no more than longer than longest key.
         let capacity = min(typeahead.len(), self.max_key_len + 1);
         let mut query = Vec::<K>::with_capacity(capacity);
-        for k in typeahead.value_iter() {
+        for &(k, key_remap_type) in typeahead.iter() {
+            if remap_type == RemapType::Remap &&
+                key_remap_type != RemapType::Remap
+            {
+                return (query, true);
+            }
             query.push(k);
             if query.len() >= query.capacity() {
                 break;
             }
         }
-        query
+        (query, false)
     }
 
     pub fn process(
@@ -163,8 +204,21 @@ where
         typeahead: &Typeahead<K>,
         remap_type: RemapType,
     ) -> Match<&(Vec<K>, T)> {
-        let query = self.fill_query(typeahead, remap_type);
-        find_match(&self.vec_map, &query)
+        let (query, closed) = self.fill_query(typeahead, remap_type);
+        match find_match(&self.vec_map, &query) {
+            Match::PartialMatch if closed => {
+                // No key could complete the match, so take the longest key
+                // the query starts with, if any.
+                (1..query.len() + 1)
+                    .rev()
+                    .filter_map(|len| {
+                        self.vec_map.find(&query[..len].to_vec())
+                    })
+                    .next()
+                    .map_or(Match::NoMatch, Match::FullMatch)
+            }
+            result => result,
+        }
     }
 }
 
@@ -254,6 +308,32 @@ mod find_match {
     }
 }
 
+#[cfg(test)]
+mod continuations {
+    use super::*;
+
+    #[test]
+    fn lists_longer_keys_only() {
+        let mut map = DisambiguationMap::<u8, u8>::new();
+        map.insert((vec![1u8], 1u8));
+        map.insert((vec![1u8, 2u8], 2u8));
+        map.insert((vec![1u8, 3u8, 4u8], 3u8));
+        map.insert((vec![2u8, 1u8], 4u8));
+        assert_eq!(
+            vec![&(vec![1u8, 2u8], 2u8), &(vec![1u8, 3u8, 4u8], 3u8)],
+            map.continuations(&vec![1u8])
+        );
+    }
+
+    #[test]
+    fn empty_prefix_lists_everything() {
+        let mut map = DisambiguationMap::<u8, u8>::new();
+        map.insert((vec![1u8], 1u8));
+        map.insert((vec![2u8], 2u8));
+        assert_eq!(2, map.continuations(&vec![]).len());
+    }
+}
+
 #[cfg(test)]
 mod match_length {
     use super::*;
@@ -293,3 +373,39 @@ mod match_length {
         assert_eq!(0, match_length(&q, &p));
     }
 }
+
+#[cfg(test)]
+mod process {
+    use super::*;
+
+    fn typeahead(keys: &[(u8, RemapType)]) -> Typeahead<u8> {
+        let mut typeahead = Typeahead::new();
+        for &(key, remap_type) in keys {
+            typeahead.push_back(key, remap_type);
+        }
+        typeahead
+    }
+
+    #[test]
+    fn noremap_keys_are_not_remapped() {
+        let mut map = DisambiguationMap::<u8, u8>::new();
+        map.insert((vec![1u8], 4u8));
+        map.insert((vec![1u8, 2u8], 5u8));
+        map.insert((vec![2u8], 6u8));
+
+        let keys = typeahead(&[(2u8, RemapType::Noremap)]);
+        assert_eq!(Match::NoMatch, map.process(&keys, RemapType::Remap));
+        assert_eq!(
+            Match::FullMatch(&(vec![2u8], 6u8)),
+            map.process(&keys, RemapType::NotRelavant)
+        );
+
+        // A noremap key can't complete a remap, so there's nothing to wait on.
+        let keys =
+            typeahead(&[(1u8, RemapType::Remap), (2u8, RemapType::Noremap)]);
+        assert_eq!(
+            Match::FullMatch(&(vec![1u8], 4u8)),
+            map.process(&keys, RemapType::Remap)
+        );
+    }
+}
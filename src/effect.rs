@@ -0,0 +1,142 @@
+use message::Message;
+use mode_info::ModeInfo;
+use op::{CommandId, MotionOp, OperatorOp, Span};
+use view::Position;
+
+/// An action requested by the state machine.
+///
+/// The state machine never talks to an editor directly. Instead, processing
+/// keys yields an ordered list of `Effect`s which the frontend applies however
+/// it sees fit (see `client::apply` for xi frontends).
+#[derive(Clone, Debug, PartialEq)]
+pub enum Effect {
+    InsertChar(char), // Insert a character at the cursor.
+    InsertNewline, // Break the line at the cursor.
+    Insert(String), // Insert text, newlines included, at the cursor.
+    Move(MotionOp), // Move the cursor.
+    MoveCount(MotionOp, i32), // Move the cursor by a motion [count] times.
+    Delete(MotionOp), // Delete the text between the cursor and a motion.
+    MoveTo(Position), // Put the cursor at a position.
+    Replace(Position, Position, String), // Replace [start, end) with text.
+    Scroll(u64, u64), // Scroll so lines [first, last] are visible.
+    Save(String), // Save the buffer to the given path.
+    Undo, // Undo the last change, or group of changes.
+    Redo, // Redo the last change undone.
+    StartUndoGroup, // Undo the edits from here on together.
+    EndUndoGroup, // End the group started last.
+    ModeChange(ModeInfo), // The machine has entered a new mode.
+    Message(Message), // Text to be shown to the user.
+    Command(CommandId, i32, Option<char>), // Run a command, count, register.
+    // Apply an operator over a span [count] times, or just move the cursor
+    // if there is none. `Vixi` turns this into the effects above.
+    Operate(Option<OperatorOp>, Span, i32),
+}
+
+impl Effect {
+    /// Fold `next` into this effect if applying the result is the same as
+    /// applying both, e.g. consecutive characters become a single insert.
+    /// Returns false, leaving the effect as it was, otherwise.
+    pub fn merge(&mut self, next: &Effect) -> bool {
+        if let Some(more) = next.inserted() {
+            if let Effect::Insert(ref mut text) = *self {
+                text.push_str(&more);
+                return true;
+            }
+            if let Some(mut text) = self.inserted() {
+                text.push_str(&more);
+                *self = Effect::Insert(text);
+                return true;
+            }
+        }
+        match (self.moves(), next.moves()) {
+            (Some((motion, count)), Some((next_motion, more)))
+                if motion == next_motion => {
+                *self = Effect::MoveCount(motion, count + more);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the effect may change the text, and so belongs in an undo
+    /// group.
+    pub fn is_edit(&self) -> bool {
+        match *self {
+            Effect::InsertChar(_) |
+            Effect::InsertNewline |
+            Effect::Insert(_) |
+            Effect::Delete(_) |
+            Effect::Replace(..) |
+            Effect::Command(..) |
+            Effect::Operate(Some(_), ..) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether `merge` could fold another effect into this one.
+    pub fn is_mergeable(&self) -> bool {
+        self.inserted().is_some() || self.moves().is_some()
+    }
+
+    /// The text inserted by a literal insertion.
+    fn inserted(&self) -> Option<String> {
+        match *self {
+            Effect::InsertChar(c) => Some(c.to_string()),
+            Effect::InsertNewline => Some(String::from("\n")),
+            Effect::Insert(ref text) => Some(text.clone()),
+            _ => None,
+        }
+    }
+
+    /// The motion of a cursor movement, and how many times it is made.
+    fn moves(&self) -> Option<(MotionOp, i32)> {
+        match *self {
+            Effect::Move(MotionOp::Custom(_)) => None,
+            Effect::Move(motion) => Some((motion, 1)),
+            Effect::MoveCount(motion, count) => Some((motion, count)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn merged(effects: Vec<Effect>) -> Vec<Effect> {
+        let mut merged: Vec<Effect> = Vec::new();
+        for effect in effects {
+            let done = match merged.last_mut() {
+                Some(last) => last.merge(&effect),
+                None => false,
+            };
+            if !done {
+                merged.push(effect);
+            }
+        }
+        merged
+    }
+
+    #[test]
+    fn merges_inserts_and_motions() {
+        assert_eq!(
+            vec![
+                Effect::Insert(String::from("a\nb")),
+                Effect::MoveCount(MotionOp::Down, 3),
+                Effect::Move(MotionOp::Up),
+                Effect::Delete(MotionOp::Right),
+                Effect::InsertChar('c'),
+            ],
+            merged(vec![
+                Effect::InsertChar('a'),
+                Effect::InsertNewline,
+                Effect::InsertChar('b'),
+                Effect::Move(MotionOp::Down),
+                Effect::MoveCount(MotionOp::Down, 2),
+                Effect::Move(MotionOp::Up),
+                Effect::Delete(MotionOp::Right),
+                Effect::InsertChar('c'),
+            ])
+        );
+    }
+}
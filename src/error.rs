@@ -0,0 +1,96 @@
+use client::ClientError;
+use effect::Effect;
+use op::CommandId;
+use options::OptionError;
+use serde_json;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use view::ViewId;
+
+#[derive(Debug)]
+pub enum VixiError {
+    Client(Effect, ClientError), // The client failed to apply an effect.
+    NewView(ClientError), // The client failed to open a view.
+    CloseView(ViewId, ClientError), // The client failed to close a view.
+    UnknownView(ViewId), // No such view is being tracked.
+    LastView(ViewId), // The only view can't be closed.
+    NoClient, // The operation needs a client.
+    Option(OptionError), // An option could not be set.
+    Snapshot(serde_json::Error), // A snapshot could not be (de)serialized.
+    SnapshotVersion(Option<u64>), // A snapshot is from an unknown version.
+    Trace(io::Error), // A trace could not be written.
+    Command(String, String), // A command failed, with its name and message.
+    UnknownCommand(CommandId), // A key is bound to an undefined command.
+    Operator(String, String), // An operator failed, with its name and message.
+}
+
+impl fmt::Display for VixiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VixiError::Client(ref effect, ref err) => {
+                write!(f, "failed to apply {:?}: {:?}", effect, err)
+            }
+            VixiError::NewView(ref err) => {
+                write!(f, "failed to open a view: {:?}", err)
+            }
+            VixiError::CloseView(view_id, ref err) => {
+                write!(f, "failed to close {:?}: {:?}", view_id, err)
+            }
+            VixiError::UnknownView(view_id) => {
+                write!(f, "unknown view {:?}", view_id)
+            }
+            VixiError::LastView(view_id) => {
+                write!(f, "can't close the last view, {:?}", view_id)
+            }
+            VixiError::NoClient => write!(f, "no client"),
+            VixiError::Option(OptionError::Unknown(ref name)) => {
+                write!(f, "unknown option: {}", name)
+            }
+            VixiError::Option(OptionError::InvalidValue(ref name, value)) => {
+                write!(f, "invalid argument: {}={}", name, value)
+            }
+            VixiError::Snapshot(ref err) => {
+                write!(f, "invalid snapshot: {}", err)
+            }
+            VixiError::SnapshotVersion(Some(version)) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+            VixiError::SnapshotVersion(None) => {
+                write!(f, "snapshot has no version")
+            }
+            VixiError::Trace(ref err) => {
+                write!(f, "failed to write trace: {}", err)
+            }
+            VixiError::Command(ref name, ref message) => {
+                write!(f, "{}: {}", name, message)
+            }
+            VixiError::UnknownCommand(id) => {
+                write!(f, "unknown command {:?}", id)
+            }
+            VixiError::Operator(ref name, ref message) => {
+                write!(f, "{}: {}", name, message)
+            }
+        }
+    }
+}
+
+impl Error for VixiError {
+    fn description(&self) -> &str {
+        match *self {
+            VixiError::Client(..) => "client failed to apply an effect",
+            VixiError::NewView(..) => "client failed to open a view",
+            VixiError::CloseView(..) => "client failed to close a view",
+            VixiError::UnknownView(..) => "unknown view",
+            VixiError::LastView(..) => "can't close the last view",
+            VixiError::NoClient => "no client",
+            VixiError::Option(..) => "an option could not be set",
+            VixiError::Snapshot(..) => "invalid snapshot",
+            VixiError::SnapshotVersion(..) => "unsupported snapshot version",
+            VixiError::Trace(..) => "failed to write trace",
+            VixiError::Command(..) => "a command failed",
+            VixiError::UnknownCommand(..) => "unknown command",
+            VixiError::Operator(..) => "an operator failed",
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use mode_info::ModeInfo;
+use op::{MotionOp, OperatorOp};
+
+/// Notifications delivered synchronously to observers while keys are
+/// processed, in the order they occur.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    ModeChanged(ModeInfo, ModeInfo), // From the old mode to the new one.
+    InsertEnter, // Entered Insert or Replace.
+    InsertLeave, // Left Insert or Replace.
+    OperatorStarted(OperatorOp), // An operator is awaiting a motion.
+    TextYanked(char, OperatorOp, MotionOp), // Register, operator and motion.
+    RecordingStarted(char), // Started recording into a register.
+    RecordingStopped(char), // Finished recording into a register.
+    MappingRecursionDetected, // A remap loop was found and abandoned.
+}
+
+/// Identifies a subscription so it can be cancelled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SubscriptionId(usize);
+
+/// A list of event observers.
+pub struct Observers {
+    observers: Vec<(SubscriptionId, Box<FnMut(&Event) + Send>)>,
+    next_id: usize,
+}
+
+impl Observers {
+    pub fn new() -> Self {
+        Observers {
+            observers: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn subscribe(
+        &mut self,
+        observer: Box<FnMut(&Event) + Send>,
+    ) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.observers.push((id, observer));
+        id
+    }
+
+    /// Returns false if `id` was not subscribed.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let len = self.observers.len();
+        self.observers.retain(|o| o.0 != id);
+        self.observers.len() != len
+    }
+
+    /// Deliver `event` to every observer, in subscription order.
+    pub fn notify(&mut self, event: &Event) {
+        for observer in self.observers.iter_mut() {
+            (observer.1)(event);
+        }
+    }
+}
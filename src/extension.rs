@@ -0,0 +1,473 @@
+use client::TextQuery;
+use op::MotionOp;
+use view::{Position, ViewId};
+
+/// Where a motion or text object is applied.
+pub struct Origin<'a> {
+    pub text: &'a TextQuery, // The text of the view.
+    pub view_id: ViewId, // The focused view.
+    pub cursor: Position, // The cursor, as `text` reports it.
+    pub count: i32, // [count] typed with the command, 1 if none.
+}
+
+/// How much of the text an operator over a motion covers, see
+/// `:help exclusive`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Exclusivity {
+    Exclusive, // Up to the target, e.g. `w`.
+    Inclusive, // Including the character at the target, e.g. `e`.
+    Linewise, // Whole lines, e.g. `j`.
+}
+
+/// Where a motion takes the cursor.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Target {
+    pub position: Position,
+    pub exclusivity: Exclusivity,
+}
+
+/// A span of text, `end` excluded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+/// A cursor motion, usable on its own or after an operator.
+pub trait Motion {
+    /// Where the motion goes, or `None` if it fails, e.g. at the end of
+    /// the text.
+    fn target(&self, origin: &Origin) -> Option<Target>;
+}
+
+/// A text object, usable after an operator, e.g. `ia` for an argument.
+pub trait TextObject {
+    /// The text the object covers, or `None` if there is none around the
+    /// cursor.
+    fn range(&self, origin: &Origin) -> Option<Range>;
+}
+
+/// An operator's failure, as a message to show the user.
+pub type OperatorResult = Result<String, String>;
+
+/// An operator, which replaces the text of a range, e.g. to comment it.
+pub trait Operator {
+    /// The replacement for `text`, the contents of `range`.
+    fn transform(&mut self, range: Range, text: &str) -> OperatorResult;
+}
+
+impl<F> Motion for F
+where
+    F: Fn(&Origin) -> Option<Target>,
+{
+    fn target(&self, origin: &Origin) -> Option<Target> {
+        self(origin)
+    }
+}
+
+impl<F> TextObject for F
+where
+    F: Fn(&Origin) -> Option<Range>,
+{
+    fn range(&self, origin: &Origin) -> Option<Range> {
+        self(origin)
+    }
+}
+
+impl<F> Operator for F
+where
+    F: FnMut(Range, &str) -> OperatorResult,
+{
+    fn transform(&mut self, range: Range, text: &str) -> OperatorResult {
+        self(range, text)
+    }
+}
+
+/// Extensions of one kind, by name. Ids are indices, as with
+/// `command::Commands`.
+pub struct Registry<T: ?Sized> {
+    entries: Vec<(String, Box<T>)>,
+}
+
+impl<T: ?Sized> Registry<T> {
+    pub fn new() -> Self {
+        Registry { entries: Vec::new() }
+    }
+
+    /// Define an extension, replacing any other of the same name. Bindings
+    /// to the replaced one use the new one.
+    pub fn define(&mut self, name: &str, extension: Box<T>) -> u32 {
+        if let Some(id) = self.id(name) {
+            self.entries[id as usize].1 = extension;
+            return id;
+        }
+        self.entries.push((String::from(name), extension));
+        self.entries.len() as u32 - 1
+    }
+
+    pub fn id(&self, name: &str) -> Option<u32> {
+        self.entries
+            .iter()
+            .position(|&(ref n, _)| n == name)
+            .map(|index| index as u32)
+    }
+
+    pub fn name(&self, id: u32) -> Option<&str> {
+        self.entries.get(id as usize).map(|&(ref n, _)| n.as_str())
+    }
+
+    pub fn get(&self, id: u32) -> Option<&T> {
+        self.entries.get(id as usize).map(|&(_, ref e)| &**e)
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut T> {
+        self.entries.get_mut(id as usize).map(|&mut (_, ref mut e)| &mut **e)
+    }
+}
+
+/// Motions, text objects and operators defined by the embedder, for
+/// binding to keys alongside the built-in ones.
+pub struct Extensions {
+    pub motions: Registry<Motion + Send>,
+    pub objects: Registry<TextObject + Send>,
+    pub operators: Registry<Operator + Send>,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Extensions {
+            motions: Registry::new(),
+            objects: Registry::new(),
+            operators: Registry::new(),
+        }
+    }
+}
+
+/// Built-in motions, as far as they can be worked out from the text, so
+/// that user operators can follow them. Paging and user motions fail, as
+/// do motions which can't move at all; a count stops at the buffer's edge.
+impl Motion for MotionOp {
+    fn target(&self, origin: &Origin) -> Option<Target> {
+        let (text, view_id) = (origin.text, origin.view_id);
+        let mut position = step(*self, text, view_id, origin.cursor)?;
+        for _ in 1..origin.count {
+            match step(*self, text, view_id, position) {
+                Some(next) => position = next,
+                None => break,
+            }
+        }
+        let exclusivity = match *self {
+            MotionOp::Up |
+            MotionOp::Down |
+            MotionOp::Top |
+            MotionOp::Bottom => Exclusivity::Linewise,
+            MotionOp::End => Exclusivity::Inclusive,
+            _ => Exclusivity::Exclusive,
+        };
+        Some(Target {
+            position: position,
+            exclusivity: exclusivity,
+        })
+    }
+}
+
+/// Where one repetition of a built-in motion goes from `from`.
+fn step(
+    motion: MotionOp,
+    text: &TextQuery,
+    view_id: ViewId,
+    from: Position,
+) -> Option<Position> {
+    let last_line = text.line_count(view_id).saturating_sub(1);
+    let line = text.line(view_id, from.line).unwrap_or_default();
+    let at = |line: u64, column: u64| Position {
+        line: line,
+        column: column,
+    };
+    let position = match motion {
+        MotionOp::Left | MotionOp::Backspace if from.column > 0 => {
+            let before = line.get(..from.column as usize)?;
+            let width = before.chars().next_back()?.len_utf8() as u64;
+            at(from.line, from.column - width)
+        }
+        MotionOp::Backspace if from.line > 0 => {
+            let above = text.line(view_id, from.line - 1)?;
+            at(from.line - 1, above.len() as u64)
+        }
+        MotionOp::Right => {
+            let c = line.get(from.column as usize..)?.chars().next()?;
+            at(from.line, from.column + c.len_utf8() as u64)
+        }
+        MotionOp::Up if from.line > 0 => at(from.line - 1, from.column),
+        MotionOp::Down if from.line < last_line => {
+            at(from.line + 1, from.column)
+        }
+        MotionOp::Top => at(0, 0),
+        MotionOp::Bottom => at(last_line, 0),
+        MotionOp::Home => at(from.line, 0),
+        MotionOp::End => {
+            let width = line.chars().next_back().map_or(0, char::len_utf8);
+            at(from.line, (line.len() - width) as u64)
+        }
+        MotionOp::Word => {
+            let rest = line.get(from.column as usize..)?;
+            match next_word(rest) {
+                Some(offset) => at(from.line, from.column + offset as u64),
+                None if from.line < last_line => {
+                    let below = text.line(view_id, from.line + 1)?;
+                    let indent = below.len() - below.trim_left().len();
+                    at(from.line + 1, indent as u64)
+                }
+                None => at(from.line, line.len() as u64),
+            }
+        }
+        MotionOp::WordBackward => {
+            let before = line.get(..from.column as usize)?;
+            match previous_word(before) {
+                Some(offset) => at(from.line, offset as u64),
+                None if from.line > 0 => {
+                    let above = text.line(view_id, from.line - 1)?;
+                    let start = previous_word(&above).unwrap_or(0);
+                    at(from.line - 1, start as u64)
+                }
+                None => return None,
+            }
+        }
+        _ => return None,
+    };
+    if position == from {
+        None
+    } else {
+        Some(position)
+    }
+}
+
+/// Words are runs of keyword characters or of other non-blank ones.
+fn class(c: char) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if c.is_alphanumeric() || c == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+/// The offset of the word after the one `text` starts in, if any.
+fn next_word(text: &str) -> Option<usize> {
+    let mut chars = text.char_indices().peekable();
+    let first = class(chars.peek()?.1);
+    while chars.peek().map_or(false, |&(_, c)| class(c) == first) {
+        chars.next();
+    }
+    while chars.peek().map_or(false, |&(_, c)| class(c) == 0) {
+        chars.next();
+    }
+    chars.peek().map(|&(offset, _)| offset)
+}
+
+/// The offset of the start of the last word in `text`, if any.
+pub fn previous_word(text: &str) -> Option<usize> {
+    let trimmed = text.trim_right();
+    let last = class(trimmed.chars().next_back()?);
+    trimmed
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| class(c) == last)
+        .last()
+        .map(|(offset, _)| offset)
+}
+
+/// The text an operator over `target` covers, starting from `cursor`.
+pub fn motion_range(
+    text: &TextQuery,
+    view_id: ViewId,
+    cursor: Position,
+    target: Target,
+) -> Range {
+    let (start, end) = if target.position < cursor {
+        (target.position, cursor)
+    } else {
+        (cursor, target.position)
+    };
+    let line_end = |line: u64| Position {
+        line: line,
+        column: text.line(view_id, line).map_or(0, |l| l.len() as u64),
+    };
+    match target.exclusivity {
+        Exclusivity::Exclusive => Range {
+            start: start,
+            end: end,
+        },
+        Exclusivity::Inclusive => {
+            let width = text.char_at(view_id, end).map_or(0, char::len_utf8);
+            Range {
+                start: start,
+                end: Position {
+                    line: end.line,
+                    column: end.column + width as u64,
+                },
+            }
+        }
+        Exclusivity::Linewise => {
+            if end.line + 1 < text.line_count(view_id) {
+                Range {
+                    start: Position {
+                        line: start.line,
+                        column: 0,
+                    },
+                    end: Position {
+                        line: end.line + 1,
+                        column: 0,
+                    },
+                }
+            } else if start.line > 0 {
+                // Take the line break before, as there is none after.
+                Range {
+                    start: line_end(start.line - 1),
+                    end: line_end(end.line),
+                }
+            } else {
+                Range {
+                    start: Position { line: 0, column: 0 },
+                    end: line_end(end.line),
+                }
+            }
+        }
+    }
+}
+
+/// The contents of `range`, lines joined with `\n`.
+pub fn text_in(text: &TextQuery, view_id: ViewId, range: Range) -> String {
+    let mut lines = Vec::new();
+    for number in range.start.line..range.end.line + 1 {
+        let line = text.line(view_id, number).unwrap_or_default();
+        let from = if number == range.start.line {
+            range.start.column as usize
+        } else {
+            0
+        };
+        let to = if number == range.end.line {
+            range.end.column as usize
+        } else {
+            line.len()
+        };
+        lines.push(String::from(line.get(from..to.max(from)).unwrap_or("")));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use client::Client;
+    use futures::Future;
+    use memory_client::MemoryClient;
+
+    fn typed(text: &str) -> MemoryClient {
+        let mut client = MemoryClient::new();
+        for c in text.chars() {
+            let request = match c {
+                '\n' => client.insert_newline(ViewId(0)),
+                _ => client.char(ViewId(0), c),
+            };
+            request.wait().unwrap();
+        }
+        client
+    }
+
+    fn position(line: u64, column: u64) -> Position {
+        Position {
+            line: line,
+            column: column,
+        }
+    }
+
+    fn origin<'a>(text: &'a TextQuery, cursor: Position) -> Origin<'a> {
+        Origin {
+            text: text,
+            view_id: ViewId(0),
+            cursor: cursor,
+            count: 1,
+        }
+    }
+
+    #[test]
+    fn builtin_motions() {
+        let text = typed("foo.bar baz\n  qux");
+        let mut from = origin(&text, position(0, 0));
+        let target = |motion: MotionOp, from: &Origin| {
+            motion.target(from).map(|target| target.position)
+        };
+        assert_eq!(Some(position(0, 3)), target(MotionOp::Word, &from));
+        assert_eq!(Some(position(0, 10)), target(MotionOp::End, &from));
+        assert_eq!(None, target(MotionOp::Left, &from));
+        from.count = 3;
+        assert_eq!(Some(position(0, 8)), target(MotionOp::Word, &from));
+        from.count = 4;
+        assert_eq!(Some(position(1, 2)), target(MotionOp::Word, &from));
+        from.cursor = position(1, 2);
+        from.count = 1;
+        assert_eq!(
+            Some(position(0, 8)),
+            target(MotionOp::WordBackward, &from)
+        );
+    }
+
+    #[test]
+    fn counts_stop_at_the_edge() {
+        let text = typed("one\ntwo\nthree");
+        let mut from = origin(&text, position(1, 1));
+        let target = |motion: MotionOp, from: &Origin| {
+            motion.target(from).map(|target| target.position)
+        };
+        from.count = 5;
+        assert_eq!(Some(position(2, 1)), target(MotionOp::Down, &from));
+        assert_eq!(Some(position(0, 1)), target(MotionOp::Up, &from));
+        from.cursor = position(0, 1);
+        assert_eq!(None, target(MotionOp::Up, &from));
+        from.count = 1;
+        assert_eq!(None, target(MotionOp::Up, &from));
+    }
+
+    #[test]
+    fn ranges() {
+        let text = typed("one\ntwo\nthree");
+        let target = |line, column, exclusivity| Target {
+            position: position(line, column),
+            exclusivity: exclusivity,
+        };
+        let range = |start, end| Range {
+            start: start,
+            end: end,
+        };
+        let cursor = position(1, 1);
+        let inclusive = target(0, 1, Exclusivity::Inclusive);
+        let r = motion_range(&text, ViewId(0), cursor, inclusive);
+        assert_eq!(range(position(0, 1), position(1, 2)), r);
+        assert_eq!("ne\ntw", text_in(&text, ViewId(0), r));
+
+        let linewise = target(2, 0, Exclusivity::Linewise);
+        let r = motion_range(&text, ViewId(0), cursor, linewise);
+        assert_eq!(range(position(0, 3), position(2, 5)), r);
+        assert_eq!("\ntwo\nthree", text_in(&text, ViewId(0), r));
+    }
+
+    #[test]
+    fn registry() {
+        let mut extensions = Extensions::new();
+        let first = extensions.operators.define(
+            "upper",
+            Box::new(|_, text: &str| Ok(text.to_uppercase())),
+        );
+        assert_eq!(Some(first), extensions.operators.id("upper"));
+        assert_eq!(Some("upper"), extensions.operators.name(first));
+        let range = Range {
+            start: position(0, 0),
+            end: position(0, 2),
+        };
+        let upper = extensions.operators.get_mut(first).unwrap();
+        assert_eq!(Ok(String::from("AB")), upper.transform(range, "ab"));
+        assert!(extensions.motions.get(0).is_none());
+    }
+}
@@ -0,0 +1,744 @@
+//! A C interface to `Vixi`, declared in `ffi/vixi.h`.
+//!
+//! Regenerate the header with `cbindgen --config cbindgen.toml --output
+//! ffi/vixi.h` after changing anything here.
+
+use client::{Client, ClientError, ClientResult};
+use futures::Future;
+use futures::future::{err, ok};
+use key::{Key, MultiKey};
+use serde_json;
+use serde_json::Value;
+use std::char;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+use view::ViewId;
+use vixi::Vixi;
+
+/// Size of the buffer a `request` callback writes its JSON result into.
+const RESPONSE_LEN: usize = 4096;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VixiStatus {
+    Ok,
+    InvalidArgument, // A null pointer, bad UTF-8 or an invalid key.
+    ClientFailed, // A callback failed; the command was abandoned.
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VixiModifier {
+    None,
+    Shift,
+    Ctrl,
+    Alt,
+    Cmd,
+}
+
+/// Mirrors `Key`. `F`, `Char` and `Keypad` take `VixiKey::value`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum VixiKeyCode {
+    Backspace,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Delete,
+    Insert,
+    F,
+    Char,
+    Null,
+    Esc,
+    Space,
+    Tab,
+    Csi,
+    XCsi,
+    Eol,
+    Help,
+    Undo,
+    Keypad,
+    KeypadHome,
+    KeypadEnd,
+    KeypadPageUp,
+    KeypadPageDown,
+    KeypadPlus,
+    KeypadMinus,
+    KeypadMultiply,
+    KeypadDivide,
+    KeypadEnter,
+    KeypadDot,
+}
+
+/// A decoded keystroke.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VixiKey {
+    pub modifier: VixiModifier,
+    pub code: VixiKeyCode,
+    pub value: u32, // Code point, function key or keypad digit.
+}
+
+impl VixiKey {
+    fn to_multi_key(&self) -> Option<MultiKey> {
+        let key = match self.code {
+            VixiKeyCode::Backspace => Key::Backspace,
+            VixiKeyCode::Left => Key::Left,
+            VixiKeyCode::Right => Key::Right,
+            VixiKeyCode::Up => Key::Up,
+            VixiKeyCode::Down => Key::Down,
+            VixiKeyCode::Home => Key::Home,
+            VixiKeyCode::End => Key::End,
+            VixiKeyCode::PageUp => Key::PageUp,
+            VixiKeyCode::PageDown => Key::PageDown,
+            VixiKeyCode::Delete => Key::Delete,
+            VixiKeyCode::Insert => Key::Insert,
+            VixiKeyCode::F if self.value < 256 => Key::F(self.value as u8),
+            VixiKeyCode::F => {
+                return None;
+            }
+            VixiKeyCode::Char => Key::Char(char::from_u32(self.value)?),
+            VixiKeyCode::Null => Key::Null,
+            VixiKeyCode::Esc => Key::Esc,
+            VixiKeyCode::Space => Key::Space,
+            VixiKeyCode::Tab => Key::Tab,
+            VixiKeyCode::Csi => Key::Csi,
+            VixiKeyCode::XCsi => Key::XCsi,
+            VixiKeyCode::Eol => Key::Eol,
+            VixiKeyCode::Help => Key::Help,
+            VixiKeyCode::Undo => Key::Undo,
+            VixiKeyCode::Keypad if self.value < 10 => {
+                Key::Keypad(self.value as u8)
+            }
+            VixiKeyCode::Keypad => {
+                return None;
+            }
+            VixiKeyCode::KeypadHome => Key::KeypadHome,
+            VixiKeyCode::KeypadEnd => Key::KeypadEnd,
+            VixiKeyCode::KeypadPageUp => Key::KeypadPageUp,
+            VixiKeyCode::KeypadPageDown => Key::KeypadPageDown,
+            VixiKeyCode::KeypadPlus => Key::KeypadPlus,
+            VixiKeyCode::KeypadMinus => Key::KeypadMinus,
+            VixiKeyCode::KeypadMultiply => Key::KeypadMultiply,
+            VixiKeyCode::KeypadDivide => Key::KeypadDivide,
+            VixiKeyCode::KeypadEnter => Key::KeypadEnter,
+            VixiKeyCode::KeypadDot => Key::KeypadDot,
+        };
+        Some(match self.modifier {
+            VixiModifier::None => MultiKey::A(key),
+            VixiModifier::Shift => MultiKey::Shift(key),
+            VixiModifier::Ctrl => MultiKey::Ctrl(key),
+            VixiModifier::Alt => MultiKey::Alt(key),
+            VixiModifier::Cmd => MultiKey::Cmd(key),
+        })
+    }
+}
+
+pub type VixiViewFn = Option<unsafe extern "C" fn(*mut c_void, u64) -> c_int>;
+pub type VixiStrFn =
+    Option<unsafe extern "C" fn(*mut c_void, *const c_char) -> c_int>;
+pub type VixiViewStrFn =
+    Option<unsafe extern "C" fn(*mut c_void, u64, *const c_char) -> c_int>;
+pub type VixiPositionFn =
+    Option<unsafe extern "C" fn(*mut c_void, u64, u64, u64) -> c_int>;
+
+/// Callbacks mirroring the methods of `Client`, each passed `user_data`
+/// first. Callbacks return zero on success; null ones do nothing. They
+/// may be called from any thread, one at a time.
+///
+/// `Client::char` and `Client::delete` are called `insert_char` and
+/// `delete_forward` here, being keywords in C and C++. JSON parameters are
+/// passed as strings, and `edit` receives null when there are none.
+///
+/// Inserted text, e.g. a paste, arrives in one call to `edit`, as xi-core's
+/// `insert` with the text in `chars`. Without `edit`, it arrives one
+/// character at a time through `insert_char` and `insert_newline`.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct VixiCallbacks {
+    pub user_data: *mut c_void,
+    pub notify: Option<
+        unsafe extern "C" fn(*mut c_void, *const c_char, *const c_char)
+            -> c_int,
+    >,
+    /// Writes a NUL-terminated JSON result of at most `len` bytes.
+    pub request: Option<
+        unsafe extern "C" fn(
+            *mut c_void,
+            *const c_char,
+            *const c_char,
+            *mut c_char,
+            usize,
+        ) -> c_int,
+    >,
+    pub edit: Option<
+        unsafe extern "C" fn(*mut c_void, u64, *const c_char, *const c_char)
+            -> c_int,
+    >,
+    pub scroll: VixiPositionFn,
+    pub left: VixiViewFn,
+    pub left_sel: VixiViewFn,
+    pub right: VixiViewFn,
+    pub right_sel: VixiViewFn,
+    pub up: VixiViewFn,
+    pub up_sel: VixiViewFn,
+    pub down: VixiViewFn,
+    pub down_sel: VixiViewFn,
+    pub delete_forward: VixiViewFn,
+    pub backspace: VixiViewFn,
+    pub del: VixiViewFn,
+    pub page_up: VixiViewFn,
+    pub page_up_sel: VixiViewFn,
+    pub page_down: VixiViewFn,
+    pub page_down_sel: VixiViewFn,
+    pub line_start: VixiViewFn,
+    pub line_start_sel: VixiViewFn,
+    pub line_end: VixiViewFn,
+    pub line_end_sel: VixiViewFn,
+    pub insert_newline: VixiViewFn,
+    pub f1: VixiViewFn,
+    pub f2: VixiViewFn,
+    pub insert_char:
+        Option<unsafe extern "C" fn(*mut c_void, u64, u32) -> c_int>,
+    pub click: VixiPositionFn,
+    pub drag: VixiPositionFn,
+    /// Receives the file path, or null, and writes the new view's id.
+    pub new_view: Option<
+        unsafe extern "C" fn(*mut c_void, *const c_char, *mut u64) -> c_int,
+    >,
+    pub close_view: VixiViewFn,
+    pub save: VixiViewStrFn,
+    pub set_theme: VixiStrFn,
+    pub start_plugin: VixiViewStrFn,
+    pub stop_plugin: VixiViewStrFn,
+    pub notify_plugin: Option<
+        unsafe extern "C" fn(
+            *mut c_void,
+            u64,
+            *const c_char,
+            *const c_char,
+            *const c_char,
+        ) -> c_int,
+    >,
+    pub start_undo_group: VixiViewFn,
+    pub end_undo_group: VixiViewFn,
+}
+
+/// A `Client` which calls back into C.
+struct CallbackClient {
+    callbacks: VixiCallbacks,
+}
+
+// A `Vixi` may be moved to another thread, taking its callbacks along; C
+// callers must make `user_data` and the callbacks safe to use from any
+// thread, as `vixi_new_with_callbacks` requires.
+unsafe impl Send for CallbackClient {}
+
+fn status(code: c_int) -> ClientResult<()> {
+    if code == 0 {
+        Box::new(ok(()))
+    } else {
+        Box::new(err(ClientError::ErrorReturned(Value::from(code))))
+    }
+}
+
+/// Converts `s` for C, failing on interior NULs.
+fn c_string(s: &str) -> Result<CString, ClientError> {
+    CString::new(s).map_err(|_| ClientError::RequestFailed)
+}
+
+/// Runs `call` with C strings for `strings`, or fails if one can't be made.
+fn with_strings<F>(strings: &[&str], call: F) -> ClientResult<()>
+where
+    F: FnOnce(&[CString]) -> c_int,
+{
+    let mut converted = Vec::new();
+    for s in strings {
+        match c_string(s) {
+            Ok(s) => converted.push(s),
+            Err(e) => {
+                return Box::new(err(e));
+            }
+        }
+    }
+    status(call(&converted))
+}
+
+impl CallbackClient {
+    fn view_call(&self, f: VixiViewFn, view_id: ViewId) -> ClientResult<()> {
+        match f {
+            Some(f) => status(unsafe { f(self.callbacks.user_data, view_id.0) }),
+            None => Box::new(ok(())),
+        }
+    }
+
+    fn position_call(
+        &self,
+        f: VixiPositionFn,
+        view_id: ViewId,
+        a: u64,
+        b: u64,
+    ) -> ClientResult<()> {
+        match f {
+            Some(f) => {
+                status(unsafe { f(self.callbacks.user_data, view_id.0, a, b) })
+            }
+            None => Box::new(ok(())),
+        }
+    }
+
+    fn view_str_call(
+        &self,
+        f: VixiViewStrFn,
+        view_id: ViewId,
+        s: &str,
+    ) -> ClientResult<()> {
+        let user_data = self.callbacks.user_data;
+        match f {
+            Some(f) => with_strings(&[s], |c| unsafe {
+                f(user_data, view_id.0, c[0].as_ptr())
+            }),
+            None => Box::new(ok(())),
+        }
+    }
+}
+
+impl Client for CallbackClient {
+    fn notify(&mut self, method: &str, params: Value) -> ClientResult<()> {
+        let user_data = self.callbacks.user_data;
+        match self.callbacks.notify {
+            Some(f) => with_strings(&[method, &params.to_string()], |c| unsafe {
+                f(user_data, c[0].as_ptr(), c[1].as_ptr())
+            }),
+            None => Box::new(ok(())),
+        }
+    }
+    fn request(&mut self, method: &str, params: Value) -> ClientResult<Value> {
+        let f = match self.callbacks.request {
+            Some(f) => f,
+            None => {
+                return Box::new(ok(Value::Null));
+            }
+        };
+        let (method, params) = match (
+            c_string(method),
+            c_string(&params.to_string()),
+        ) {
+            (Ok(method), Ok(params)) => (method, params),
+            _ => {
+                return Box::new(err(ClientError::RequestFailed));
+            }
+        };
+        let mut response = vec![0 as c_char; RESPONSE_LEN];
+        let code = unsafe {
+            f(
+                self.callbacks.user_data,
+                method.as_ptr(),
+                params.as_ptr(),
+                response.as_mut_ptr(),
+                RESPONSE_LEN,
+            )
+        };
+        if code != 0 {
+            return Box::new(err(ClientError::ErrorReturned(Value::from(code))));
+        }
+        response[RESPONSE_LEN - 1] = 0;
+        let response = unsafe { CStr::from_ptr(response.as_ptr()) };
+        let response = response.to_string_lossy();
+        match serde_json::from_str(&response) {
+            Ok(value) => Box::new(ok(value)),
+            Err(_) => Box::new(err(ClientError::UnexpectedResponse(
+                Value::String(response.into_owned()),
+            ))),
+        }
+    }
+    fn edit(&mut self, view_id: ViewId, method: &str, params: Option<Value>) -> ClientResult<()> {
+        let user_data = self.callbacks.user_data;
+        let f = match self.callbacks.edit {
+            Some(f) => f,
+            None => {
+                return Box::new(ok(()));
+            }
+        };
+        match params {
+            Some(params) => {
+                with_strings(&[method, &params.to_string()], |c| unsafe {
+                    f(user_data, view_id.0, c[0].as_ptr(), c[1].as_ptr())
+                })
+            }
+            None => with_strings(&[method], |c| unsafe {
+                f(user_data, view_id.0, c[0].as_ptr(), ptr::null())
+            }),
+        }
+    }
+    fn insert(&mut self, view_id: ViewId, text: &str) -> ClientResult<()> {
+        if self.callbacks.edit.is_some() {
+            let params = json!({ "chars": text });
+            return self.edit(view_id, "insert", Some(params));
+        }
+        for ch in text.chars() {
+            let result = if ch == '\n' {
+                self.insert_newline(view_id)
+            } else {
+                self.char(view_id, ch)
+            };
+            if let Err(e) = result.wait() {
+                return Box::new(err(e));
+            }
+        }
+        Box::new(ok(()))
+    }
+    fn scroll(&mut self, view_id: ViewId, first_line: u64, last_line: u64) -> ClientResult<()> {
+        self.position_call(self.callbacks.scroll, view_id, first_line, last_line)
+    }
+    fn left(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.left, view_id)
+    }
+    fn left_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.left_sel, view_id)
+    }
+    fn right(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.right, view_id)
+    }
+    fn right_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.right_sel, view_id)
+    }
+    fn up(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.up, view_id)
+    }
+    fn up_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.up_sel, view_id)
+    }
+    fn down(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.down, view_id)
+    }
+    fn down_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.down_sel, view_id)
+    }
+    fn delete(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.delete_forward, view_id)
+    }
+    fn backspace(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.backspace, view_id)
+    }
+    fn del(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.del, view_id)
+    }
+    fn page_up(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.page_up, view_id)
+    }
+    fn page_up_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.page_up_sel, view_id)
+    }
+    fn page_down(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.page_down, view_id)
+    }
+    fn page_down_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.page_down_sel, view_id)
+    }
+    fn line_start(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.line_start, view_id)
+    }
+    fn line_start_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.line_start_sel, view_id)
+    }
+    fn line_end(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.line_end, view_id)
+    }
+    fn line_end_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.line_end_sel, view_id)
+    }
+    fn insert_newline(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.insert_newline, view_id)
+    }
+    fn f1(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.f1, view_id)
+    }
+    fn f2(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.f2, view_id)
+    }
+    fn char(&mut self, view_id: ViewId, ch: char) -> ClientResult<()> {
+        match self.callbacks.insert_char {
+            Some(f) => status(unsafe {
+                f(self.callbacks.user_data, view_id.0, ch as u32)
+            }),
+            None => Box::new(ok(())),
+        }
+    }
+    fn click(&mut self, view_id: ViewId, line: u64, column: u64) -> ClientResult<()> {
+        self.position_call(self.callbacks.click, view_id, line, column)
+    }
+    fn drag(&mut self, view_id: ViewId, line: u64, column: u64) -> ClientResult<()> {
+        self.position_call(self.callbacks.drag, view_id, line, column)
+    }
+    fn new_view(&mut self, file_path: Option<String>) -> ClientResult<ViewId> {
+        let f = match self.callbacks.new_view {
+            Some(f) => f,
+            None => {
+                return Box::new(err(ClientError::RequestFailed));
+            }
+        };
+        let file_path = match file_path.map(|path| c_string(&path)) {
+            Some(Ok(path)) => Some(path),
+            Some(Err(e)) => {
+                return Box::new(err(e));
+            }
+            None => None,
+        };
+        let path = file_path.as_ref().map_or(ptr::null(), |path| path.as_ptr());
+        let mut view_id = 0;
+        let code = unsafe { f(self.callbacks.user_data, path, &mut view_id) };
+        if code == 0 {
+            Box::new(ok(ViewId(view_id)))
+        } else {
+            Box::new(err(ClientError::ErrorReturned(Value::from(code))))
+        }
+    }
+    fn close_view(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.close_view, view_id)
+    }
+    fn save(&mut self, view_id: ViewId, file_path: &str) -> ClientResult<()> {
+        self.view_str_call(self.callbacks.save, view_id, file_path)
+    }
+    fn set_theme(&mut self, theme: &str) -> ClientResult<()> {
+        let user_data = self.callbacks.user_data;
+        match self.callbacks.set_theme {
+            Some(f) => {
+                with_strings(&[theme], |c| unsafe { f(user_data, c[0].as_ptr()) })
+            }
+            None => Box::new(ok(())),
+        }
+    }
+    fn start_plugin(&mut self, view_id: ViewId, name: &str) -> ClientResult<()> {
+        self.view_str_call(self.callbacks.start_plugin, view_id, name)
+    }
+    fn stop_plugin(&mut self, view_id: ViewId, name: &str) -> ClientResult<()> {
+        self.view_str_call(self.callbacks.stop_plugin, view_id, name)
+    }
+    fn notify_plugin(
+        &mut self,
+        view_id: ViewId,
+        plugin: &str,
+        method: &str,
+        params: Value,
+    ) -> ClientResult<()> {
+        let user_data = self.callbacks.user_data;
+        let f = match self.callbacks.notify_plugin {
+            Some(f) => f,
+            None => {
+                return Box::new(ok(()));
+            }
+        };
+        with_strings(&[plugin, method, &params.to_string()], |c| unsafe {
+            f(
+                user_data,
+                view_id.0,
+                c[0].as_ptr(),
+                c[1].as_ptr(),
+                c[2].as_ptr(),
+            )
+        })
+    }
+    fn groups_undo(&self) -> bool {
+        self.callbacks.start_undo_group.is_some()
+    }
+    fn start_undo_group(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.start_undo_group, view_id)
+    }
+    fn end_undo_group(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.view_call(self.callbacks.end_undo_group, view_id)
+    }
+}
+
+/// Create a `Vixi` which applies effects through `callbacks`. Free it with
+/// `vixi_free`. The `Vixi` may be used from any thread, one at a time, and
+/// calls back on whichever thread is using it, so `user_data` and every
+/// callback must be safe to use from any thread. Effects are only seen
+/// through callbacks; with all of them null, keys change nothing but the
+/// mode.
+#[no_mangle]
+pub extern "C" fn vixi_new_with_callbacks(
+    callbacks: VixiCallbacks,
+) -> *mut Vixi {
+    let client = CallbackClient {
+        callbacks: callbacks,
+    };
+    Box::into_raw(Box::new(Vixi::with_client(Box::new(client))))
+}
+
+/// Free a `Vixi`.
+///
+/// # Safety
+///
+/// `vixi` must be null or have come from `vixi_new_with_callbacks`, and
+/// must not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn vixi_free(vixi: *mut Vixi) {
+    if !vixi.is_null() {
+        drop(Box::from_raw(vixi));
+    }
+}
+
+/// Process a NUL-terminated string of keys in Vim's key notation.
+///
+/// # Safety
+///
+/// `vixi` must be null or a `Vixi` not yet freed, which no other thread is
+/// using. `keys` must be null or point to a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn vixi_process(
+    vixi: *mut Vixi,
+    keys: *const c_char,
+) -> VixiStatus {
+    if vixi.is_null() || keys.is_null() {
+        return VixiStatus::InvalidArgument;
+    }
+    let keys = match CStr::from_ptr(keys).to_str() {
+        Ok(keys) => keys,
+        Err(_) => {
+            return VixiStatus::InvalidArgument;
+        }
+    };
+    match (*vixi).process(keys) {
+        Ok(_) => VixiStatus::Ok,
+        Err(_) => VixiStatus::ClientFailed,
+    }
+}
+
+/// Process a single decoded key.
+///
+/// # Safety
+///
+/// `vixi` must be null or a `Vixi` not yet freed, which no other thread is
+/// using.
+#[no_mangle]
+pub unsafe extern "C" fn vixi_process_key(
+    vixi: *mut Vixi,
+    key: VixiKey,
+) -> VixiStatus {
+    let key = match key.to_multi_key() {
+        Some(key) => key,
+        None => {
+            return VixiStatus::InvalidArgument;
+        }
+    };
+    if vixi.is_null() {
+        return VixiStatus::InvalidArgument;
+    }
+    match (*vixi).process_key(key) {
+        Ok(_) => VixiStatus::Ok,
+        Err(_) => VixiStatus::ClientFailed,
+    }
+}
+
+/// The name of the current mode, e.g. "Normal", as a static string.
+///
+/// # Safety
+///
+/// `vixi` must be null or a `Vixi` not yet freed, which no other thread is
+/// using.
+#[no_mangle]
+pub unsafe extern "C" fn vixi_mode(vixi: *const Vixi) -> *const c_char {
+    if vixi.is_null() {
+        return ptr::null();
+    }
+    let name: &'static [u8] = match (*vixi).mode() {
+        "Normal" => b"Normal\0",
+        "Pending" => b"Pending\0",
+        "Insert" => b"Insert\0",
+        _ => b"\0",
+    };
+    name.as_ptr() as *const c_char
+}
+
+/// Returns true if vixi is waiting for more keys to complete a command.
+///
+/// # Safety
+///
+/// `vixi` must be null or a `Vixi` not yet freed, which no other thread is
+/// using.
+#[no_mangle]
+pub unsafe extern "C" fn vixi_is_pending(vixi: *const Vixi) -> bool {
+    !vixi.is_null() && (*vixi).is_pending()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::mem;
+
+    #[test]
+    fn decode_keys() {
+        let key = VixiKey {
+            modifier: VixiModifier::Ctrl,
+            code: VixiKeyCode::Char,
+            value: 'o' as u32,
+        };
+        assert_eq!(Some(MultiKey::Ctrl(Key::Char('o'))), key.to_multi_key());
+        let key = VixiKey {
+            modifier: VixiModifier::None,
+            code: VixiKeyCode::Keypad,
+            value: 10,
+        };
+        assert_eq!(None, key.to_multi_key());
+        let key = VixiKey {
+            modifier: VixiModifier::None,
+            code: VixiKeyCode::Char,
+            value: 0xd800, // A surrogate.
+        };
+        assert_eq!(None, key.to_multi_key());
+    }
+
+    unsafe extern "C" fn push_char(
+        user_data: *mut c_void,
+        _view_id: u64,
+        ch: u32,
+    ) -> c_int {
+        (*(user_data as *mut String)).push(char::from_u32(ch).unwrap());
+        0
+    }
+
+    unsafe extern "C" fn push_newline(user_data: *mut c_void, _: u64) -> c_int {
+        (*(user_data as *mut String)).push('\n');
+        0
+    }
+
+    #[test]
+    fn insert_without_edit() {
+        let mut typed = String::new();
+        let keys = CString::new("ihi<CR>there<Esc>").unwrap();
+        unsafe {
+            let mut callbacks: VixiCallbacks = mem::zeroed();
+            callbacks.user_data = &mut typed as *mut String as *mut c_void;
+            callbacks.insert_char = Some(push_char);
+            callbacks.insert_newline = Some(push_newline);
+            let vixi = vixi_new_with_callbacks(callbacks);
+            assert_eq!(VixiStatus::Ok, vixi_process(vixi, keys.as_ptr()));
+            vixi_free(vixi);
+        }
+        assert_eq!("hi\nthere", typed);
+    }
+
+    #[test]
+    fn mode_names() {
+        let keys = CString::new("i").unwrap();
+        unsafe {
+            // Null callbacks do nothing.
+            let vixi = vixi_new_with_callbacks(mem::zeroed());
+            assert_eq!(VixiStatus::Ok, vixi_process(vixi, keys.as_ptr()));
+            let mode = CStr::from_ptr(vixi_mode(vixi));
+            assert_eq!("Insert", mode.to_str().unwrap());
+            assert_eq!(
+                VixiStatus::InvalidArgument,
+                vixi_process(vixi, ptr::null())
+            );
+            vixi_free(vixi);
+        }
+    }
+}
@@ -1,6 +1,12 @@
-use mode::{insert, normal, InsertMode, Mode, Transition};
+use effect::Effect;
+use extension::previous_word;
+use event::Event;
+use message::Message;
+use mode::{insert_normal, normal, recast_insert, InsertMode, Mode, NextMode,
+           Transition};
+use mode_info::{BaseMode, ModeInfo, SubMode};
 use mode_map::MapErr;
-use op::InsertOp;
+use op::{InsertOp, MotionOp};
 use state::State;
 use typeahead::Parse;
 
@@ -14,75 +20,141 @@ where
         "Insert"
     }
 
+    fn info(&self, state: &State<K>) -> ModeInfo {
+        let submode = if self.replace_mode {
+            SubMode::Replace
+        } else {
+            SubMode::None
+        };
+        ModeInfo::new(BaseMode::Insert, submode, state.recording)
+    }
+
     fn transition(&self, state: &mut State<K>) -> Mode<K> {
-        match state.insert_mode_map.process(&mut state.typeahead) {
+        let result = if state.timed_out {
+            state.insert_mode_map.resolve(&mut state.typeahead)
+        } else {
+            state.insert_mode_map.process(&mut state.typeahead)
+        };
+        match result {
             Err(MapErr::NoMatch) => {
-                // In Insert mode, unmatched typeahead gets inserted.
+                let map = &state.insert_mode_map;
+                if map.waits(&state.typeahead, state.timed_out) {
+                    // Wait for more keys, or for the mapping to time out.
+                    return recast_insert(self);
+                }
+                // In Insert mode, an unmatched key gets inserted. The keys
+                // after it may yet be mapped.
                 // TODO respect self.replace_mode
-                let string = state.typeahead.parse_string();
-                for c in string.chars() {
-                    // TODO handle failure
-                    let result = match c {
-                        '\n' | '\r' => state.client.insert_newline(state.view_id),
-                        _ => state.client.char(state.view_id, c),
+                let c = state.typeahead.value_iter().next().and_then(|key| {
+                    key.character()
+                });
+                if let Some(c) = c {
+                    state.typeahead.pop_front();
+                    match c {
+                        '\n' | '\r' => {
+                            state.emit(Effect::InsertNewline);
+                            state.inserted.push('\n');
+                            state.typed_on_line = 0;
+                        }
+                        _ => {
+                            state.emit(Effect::InsertChar(c));
+                            state.inserted.push(c);
+                            state.typed_on_line += 1;
+                        }
                     };
                 }
-            } 
+            }
             Err(MapErr::InfiniteRecursion) => {
-                // TODO Tell the user they've created an infinite remap loop.
+                state.emit(Effect::Message(Message::recursive_mapping()));
+                state.notify(Event::MappingRecursionDetected);
                 state.typeahead.clear();
-            } 
+            }
             Ok(op) => {
                 match op {
                     InsertOp::Cancel => {
+                        state.leave_insert();
                         return normal();
                     }
                     InsertOp::Quit => {
+                        state.leave_insert();
                         state.cancel();
                         return normal();
                     }
-                    // TODO handle errors
+                    InsertOp::InsertNormal => {
+                        let next_mode = if self.replace_mode {
+                            NextMode::Replace
+                        } else {
+                            NextMode::Insert
+                        };
+                        // The command may move the cursor anywhere.
+                        state.typed_on_line = 0;
+                        return insert_normal(next_mode);
+                    }
                     InsertOp::Up => {
-                        state.client.up(state.view_id);
+                        state.emit(Effect::Move(MotionOp::Up));
+                        state.typed_on_line = 0;
                     }
                     InsertOp::Down => {
-                        state.client.down(state.view_id);
+                        state.emit(Effect::Move(MotionOp::Down));
+                        state.typed_on_line = 0;
                     }
                     InsertOp::Left => {
-                        state.client.left(state.view_id);
+                        state.emit(Effect::Move(MotionOp::Left));
+                        state.typed_on_line =
+                            state.typed_on_line.saturating_sub(1);
                     }
                     InsertOp::Right => {
-                        state.client.right(state.view_id);
+                        state.emit(Effect::Move(MotionOp::Right));
+                        state.typed_on_line = 0;
                     }
                     InsertOp::Home => {
-                        state.client.line_start(state.view_id);
+                        state.emit(Effect::Move(MotionOp::Home));
+                        state.typed_on_line = 0;
                     }
                     InsertOp::End => {
-                        state.client.line_end(state.view_id);
+                        state.emit(Effect::Move(MotionOp::End));
+                        state.typed_on_line = 0;
                     }
                     InsertOp::PageUp => {
-                        state.client.page_up(state.view_id);
+                        state.emit(Effect::Move(MotionOp::PageUp));
+                        state.typed_on_line = 0;
                     }
                     InsertOp::PageDown => {
-                        state.client.page_down(state.view_id);
+                        state.emit(Effect::Move(MotionOp::PageDown));
+                        state.typed_on_line = 0;
                     }
                     InsertOp::Backspace => {
-                        state.client.backspace(state.view_id);
+                        state.emit(Effect::Delete(MotionOp::Backspace));
+                        state.inserted.pop();
+                        state.typed_on_line =
+                            state.typed_on_line.saturating_sub(1);
                     }
                     InsertOp::Delete => {
-                        state.client.delete(state.view_id);
+                        state.emit(Effect::Delete(MotionOp::Right));
                     }
                     InsertOp::DeleteWord => {
-                        // TODO Delete backwards till whitespace or beginning of
-                        // line.
-                        state.client.down(state.view_id);
+                        // Delete backwards till the start of the word.
+                        state.emit(Effect::Delete(MotionOp::WordBackward));
+                        let start = typed_start(state);
+                        let kept = previous_word(&state.inserted[start..]);
+                        let kept = kept.unwrap_or(0);
+                        let typed = state.inserted[start..start + kept]
+                            .chars()
+                            .count();
+                        state.inserted.truncate(start + kept);
+                        state.typed_on_line = typed as u64;
                     }
                     InsertOp::DeleteLine => {
-                        // TODO Delete backwards till beginning of line.
-                        state.client.down(state.view_id);
+                        // Delete backwards till beginning of line.
+                        state.emit(Effect::Delete(MotionOp::Home));
+                        let start = typed_start(state);
+                        state.inserted.truncate(start);
+                        state.typed_on_line = 0;
                     }
                     InsertOp::Tab => {
-                        state.client.char(state.view_id, '\t');
+                        state.emit(Effect::InsertChar('\t'));
+                        state.inserted.push('\t');
+                        state.typed_on_line += 1;
                     }
                     InsertOp::Digraph => {
                         // TODO
@@ -93,10 +165,35 @@ where
                     InsertOp::InsertRegisterContents => {
                         // TODO
                     }
+                    InsertOp::BreakUndo => {
+                        // The next edit starts a new group.
+                        state.end_undo_group();
+                    }
+                    InsertOp::Command(id) => {
+                        state.emit(Effect::Command(id, 1, None));
+                    }
                 }
             }
         }
         // Stay in insert mode.
-        insert()
+        recast_insert(self)
     }
 }
+
+/// The offset in `state.inserted` of the characters typed just before the
+/// cursor on its line.
+fn typed_start<K>(state: &State<K>) -> usize
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    let typed = state.typed_on_line as usize;
+    state
+        .inserted
+        .char_indices()
+        .rev()
+        .take(typed)
+        .last()
+        .map_or(state.inserted.len(), |(offset, _)| offset)
+}
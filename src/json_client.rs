@@ -0,0 +1,323 @@
+use client::{Client, ClientError, ClientResult};
+use futures::future::{err, ok};
+use serde_json::Value;
+use view::ViewId;
+
+/// A `Client` which records each call as a JSON-RPC notification in the
+/// shape of xi-core's protocol, for an out-of-process frontend to apply.
+///
+/// Nothing sent can be answered, so `request` and `new_view` fail.
+#[derive(Clone, Debug, Default)]
+pub struct JsonClient {
+    sent: Vec<Value>,
+    xi_view_ids: bool, // Send view ids as "view-id-1" rather than 1.
+}
+
+impl JsonClient {
+    /// Create a client which sends view ids as numbers.
+    pub fn new() -> Self {
+        JsonClient {
+            sent: Vec::new(),
+            xi_view_ids: false,
+        }
+    }
+
+    /// Create a client which sends view ids as xi-core does, e.g.
+    /// `"view-id-1"`.
+    pub fn xi() -> Self {
+        JsonClient {
+            sent: Vec::new(),
+            xi_view_ids: true,
+        }
+    }
+
+    /// Returns the notifications recorded since the last call, oldest first.
+    pub fn drain(&mut self) -> Vec<Value> {
+        self.sent.drain(..).collect()
+    }
+
+    fn view(&self, view_id: ViewId) -> Value {
+        if self.xi_view_ids {
+            json!(format!("view-id-{}", view_id.0))
+        } else {
+            json!(view_id.0)
+        }
+    }
+
+    fn send(&mut self, method: &str, params: Value) -> ClientResult<()> {
+        self.sent.push(json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        }));
+        Box::new(ok(()))
+    }
+
+    fn send_edit(
+        &mut self,
+        view_id: ViewId,
+        method: &str,
+        params: Option<Value>,
+    ) -> ClientResult<()> {
+        let view = self.view(view_id);
+        let mut edit = json!({"view_id": view, "method": method});
+        if let Some(params) = params {
+            edit["params"] = params;
+        }
+        self.send("edit", edit)
+    }
+
+    fn send_plugin(
+        &mut self,
+        view_id: ViewId,
+        command: &str,
+        name: &str,
+    ) -> ClientResult<()> {
+        self.send(
+            "plugin",
+            json!({
+                "command": command,
+                "view_id": self.view(view_id),
+                "plugin_name": name,
+            }),
+        )
+    }
+}
+
+impl Client for JsonClient {
+    fn notify(&mut self, method: &str, params: Value) -> ClientResult<()> {
+        self.send(method, params)
+    }
+    fn request(&mut self, _method: &str, _params: Value) -> ClientResult<Value> {
+        Box::new(err(ClientError::RequestFailed))
+    }
+    fn edit(&mut self, view_id: ViewId, method: &str, params: Option<Value>) -> ClientResult<()> {
+        self.send_edit(view_id, method, params)
+    }
+    fn scroll(&mut self, view_id: ViewId, first_line: u64, last_line: u64) -> ClientResult<()> {
+        self.send_edit(view_id, "scroll", Some(json!([first_line, last_line])))
+    }
+    fn left(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "move_left", None)
+    }
+    fn left_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "move_left_and_modify_selection", None)
+    }
+    fn right(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "move_right", None)
+    }
+    fn right_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "move_right_and_modify_selection", None)
+    }
+    fn up(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "move_up", None)
+    }
+    fn up_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "move_up_and_modify_selection", None)
+    }
+    fn down(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "move_down", None)
+    }
+    fn down_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "move_down_and_modify_selection", None)
+    }
+    fn delete(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "delete_forward", None)
+    }
+    fn backspace(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "delete_backward", None)
+    }
+    fn del(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "delete_backward", None)
+    }
+    fn page_up(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "scroll_page_up", None)
+    }
+    fn page_up_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "page_up_and_modify_selection", None)
+    }
+    fn page_down(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "scroll_page_down", None)
+    }
+    fn page_down_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "page_down_and_modify_selection", None)
+    }
+    fn line_start(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "move_to_left_end_of_line", None)
+    }
+    fn line_start_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(
+            view_id,
+            "move_to_left_end_of_line_and_modify_selection",
+            None,
+        )
+    }
+    fn line_end(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "move_to_right_end_of_line", None)
+    }
+    fn line_end_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(
+            view_id,
+            "move_to_right_end_of_line_and_modify_selection",
+            None,
+        )
+    }
+    fn insert_newline(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "insert_newline", None)
+    }
+    fn f1(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "debug_rewrap", None)
+    }
+    fn f2(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.send_edit(view_id, "debug_test_fg_spans", None)
+    }
+    fn char(&mut self, view_id: ViewId, ch: char) -> ClientResult<()> {
+        let chars = ch.to_string();
+        self.send_edit(view_id, "insert", Some(json!({"chars": chars})))
+    }
+    fn click(&mut self, view_id: ViewId, line: u64, column: u64) -> ClientResult<()> {
+        self.send_edit(view_id, "click", Some(json!([line, column, 0, 1])))
+    }
+    fn drag(&mut self, view_id: ViewId, line: u64, column: u64) -> ClientResult<()> {
+        self.send_edit(view_id, "drag", Some(json!([line, column, 0])))
+    }
+    fn new_view(&mut self, _file_path: Option<String>) -> ClientResult<ViewId> {
+        Box::new(err(ClientError::RequestFailed))
+    }
+    fn close_view(&mut self, view_id: ViewId) -> ClientResult<()> {
+        let view = self.view(view_id);
+        self.send("close_view", json!({"view_id": view}))
+    }
+    fn save(&mut self, view_id: ViewId, file_path: &str) -> ClientResult<()> {
+        let view = self.view(view_id);
+        self.send("save", json!({"view_id": view, "file_path": file_path}))
+    }
+    fn set_theme(&mut self, theme: &str) -> ClientResult<()> {
+        self.send("set_theme", json!({"theme_name": theme}))
+    }
+    fn start_plugin(&mut self, view_id: ViewId, name: &str) -> ClientResult<()> {
+        self.send_plugin(view_id, "start", name)
+    }
+    fn stop_plugin(&mut self, view_id: ViewId, name: &str) -> ClientResult<()> {
+        self.send_plugin(view_id, "stop", name)
+    }
+    fn notify_plugin(
+        &mut self,
+        view_id: ViewId,
+        plugin: &str,
+        method: &str,
+        params: Value,
+    ) -> ClientResult<()> {
+        self.send(
+            "plugin",
+            json!({
+                "command": "plugin_rpc",
+                "view_id": self.view(view_id),
+                "receiver": plugin,
+                "rpc": {"method": method, "params": params},
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use client::{Applier, apply};
+    use effect::Effect;
+    use futures::Future;
+    use op::MotionOp;
+
+    #[test]
+    fn edits_in_xi_shape() {
+        let mut client = JsonClient::new();
+        client.char(ViewId(1), 'x').wait().unwrap();
+        client.left(ViewId(1)).wait().unwrap();
+        assert_eq!(
+            vec![
+                json!({
+                    "jsonrpc": "2.0",
+                    "method": "edit",
+                    "params": {
+                        "view_id": 1,
+                        "method": "insert",
+                        "params": {"chars": "x"},
+                    },
+                }),
+                json!({
+                    "jsonrpc": "2.0",
+                    "method": "edit",
+                    "params": {"view_id": 1, "method": "move_left"},
+                }),
+            ],
+            client.drain()
+        );
+        assert!(client.drain().is_empty());
+    }
+
+    #[test]
+    fn xi_view_ids() {
+        let mut client = JsonClient::xi();
+        client.close_view(ViewId(3)).wait().unwrap();
+        assert_eq!(json!("view-id-3"), client.drain()[0]["params"]["view_id"]);
+    }
+
+    #[test]
+    fn requests_fail() {
+        let mut client = JsonClient::new();
+        assert!(client.new_view(None).wait().is_err());
+        assert!(client.request("method", json!({})).wait().is_err());
+        assert!(client.drain().is_empty());
+    }
+
+    #[test]
+    fn merged_effects() {
+        let mut client = JsonClient::new();
+        let effects = vec![
+            Effect::Insert(String::from("a\nb")),
+            Effect::MoveCount(MotionOp::Down, 2),
+        ];
+        for effect in &effects {
+            for request in apply(&mut client, ViewId(1), effect) {
+                request.wait().unwrap();
+            }
+        }
+        let methods: Vec<Value> = client
+            .drain()
+            .iter()
+            .map(|call| call["params"]["method"].clone())
+            .collect();
+        assert_eq!(vec!["insert", "move_down", "move_down"], methods);
+    }
+
+    #[test]
+    fn changes_undone_as_one() {
+        let mut client = JsonClient::new();
+        let mut applier = Applier::new();
+        let mut methods = |effects: &[Effect]| {
+            for effect in effects {
+                for request in applier.apply(&mut client, ViewId(1), effect) {
+                    request.wait().unwrap();
+                }
+            }
+            client
+                .drain()
+                .iter()
+                .map(|call| call["params"]["method"].clone())
+                .collect::<Vec<Value>>()
+        };
+        let (start, end) = (Effect::StartUndoGroup, Effect::EndUndoGroup);
+        // As with `cwab<Esc>`, the insert replaces the selected word.
+        let change = vec![
+            start.clone(),
+            Effect::Delete(MotionOp::Word),
+            Effect::Insert(String::from("ab")),
+            end.clone(),
+        ];
+        let select = "move_word_right_and_modify_selection";
+        assert_eq!(vec![select, "insert"], methods(&change));
+        // With nothing inserted, as with `dw`, the selection is deleted.
+        let delete = vec![start, Effect::Delete(MotionOp::Word), end];
+        assert_eq!(vec![select, "delete_forward"], methods(&delete));
+    }
+}
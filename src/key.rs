@@ -1,4 +1,5 @@
 use nom::IResult;
+use std::fmt;
 use typeahead::Parse;
 
 #[derive(Serialize, Deserialize, PartialOrd, Ord, Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -73,6 +74,82 @@ impl Parse for MultiKey {
     }
 }
 
+impl Key {
+    /// The key's name in Vim's key notation, without angle brackets.
+    /// Printable characters are their own name.
+    pub fn name(&self) -> String {
+        use self::Key::*;
+        let name = match *self {
+            Backspace => "BS",
+            Left => "Left",
+            Right => "Right",
+            Up => "Up",
+            Down => "Down",
+            Home => "Home",
+            End => "End",
+            PageUp => "PageUp",
+            PageDown => "PageDown",
+            Delete => "Del",
+            Insert => "Insert",
+            F(n) => {
+                return format!("F{}", n);
+            }
+            Char('\n') => "NL",
+            Char('\x0c') => "FF",
+            Char('\r') => "CR",
+            Char('<') => "lt",
+            Char(c) => {
+                return c.to_string();
+            }
+            Null => "Nul",
+            Esc => "Esc",
+            Space => "Space",
+            Tab => "Tab",
+            Csi => "CSI",
+            XCsi => "xCSI",
+            Eol => "EOL",
+            Help => "Help",
+            Undo => "Undo",
+            Keypad(n) => {
+                return format!("k{}", n);
+            }
+            KeypadHome => "kHome",
+            KeypadEnd => "kEnd",
+            KeypadPageUp => "kPageUp",
+            KeypadPageDown => "kPageDown",
+            KeypadPlus => "kPlus",
+            KeypadMinus => "kMinus",
+            KeypadMultiply => "kMultiply",
+            KeypadDivide => "kDivide",
+            KeypadEnter => "kEnter",
+            KeypadDot => "kPoint",
+        };
+        String::from(name)
+    }
+}
+
+/// Formats a key in Vim's key notation, such that `parse` reverses it.
+impl fmt::Display for MultiKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (prefix, key) = match *self {
+            MultiKey::A(key) => {
+                let name = key.name();
+                return match key {
+                    Key::Char(c) if name.chars().count() == 1 => {
+                        write!(f, "{}", c)
+                    }
+                    _ => write!(f, "<{}>", name),
+                };
+            }
+            MultiKey::Shift(key) => ("S", key),
+            MultiKey::Ctrl(key) => ("C", key),
+            MultiKey::Alt(key) => ("M", key),
+            MultiKey::Cmd(key) => ("D", key),
+        };
+        write!(f, "<{}-{}>", prefix, key.name())
+    }
+}
+
 // From vim, :help map-special-keys:
 // DETAIL: Vim first checks if a sequence from the keyboard is mapped.  If it
 // isn't the terminal key codes are tried.  If a terminal code is found it is
@@ -167,6 +244,8 @@ pub fn parse_any(buffer: &str) -> Option<Key> {
 pub mod parse {
     use super::*;
     use key::MultiKey::*;
+    #[cfg(test)]
+    use key::Key::*;
 
     named!(
         shift<&str, MultiKey>,
@@ -268,4 +347,14 @@ pub mod parse {
     fn parse_test() {
         assert_eq!(parse::parse("<S-Tab>S"), vec![Shift(Tab), A(Char('S'))]);
     }
+
+    #[test]
+    fn display_test() {
+        let keys = "<S-Tab>S<lt><C-x><Esc><CR>12\"a<M-F4>";
+        let rendered: String = parse::parse(keys)
+            .iter()
+            .map(|k| k.to_string())
+            .collect();
+        assert_eq!(keys, rendered);
+    }
 }
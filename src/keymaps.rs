@@ -0,0 +1,88 @@
+use key::MultiKey;
+use maps;
+use mode_map::ModeMap;
+use op::{InsertOp, NormalOp, PendingOp};
+use ordered_vec_map::InsertionResult;
+use std::sync::Arc;
+use typeahead::Parse;
+use view::MapMode;
+
+/// The mappings of every mode, for any number of sessions to share, e.g.
+/// the defaults along with a user's configuration.
+///
+/// Clones share each mode's table behind an `Arc`, across threads too.
+/// Sessions layer their own mappings on top, looking them up before the
+/// shared table rather than copying it.
+#[derive(Clone)]
+pub struct Keymaps<K>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    normal: Arc<ModeMap<K, NormalOp>>,
+    pending: Arc<ModeMap<K, PendingOp>>,
+    insert: Arc<ModeMap<K, InsertOp>>,
+}
+
+impl<K> Keymaps<K>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    pub fn new(
+        normal: ModeMap<K, NormalOp>,
+        pending: ModeMap<K, PendingOp>,
+        insert: ModeMap<K, InsertOp>,
+    ) -> Self {
+        Keymaps {
+            normal: Arc::new(normal),
+            pending: Arc::new(pending),
+            insert: Arc::new(insert),
+        }
+    }
+
+    /// Keymaps with no mappings at all.
+    pub fn empty() -> Self {
+        Keymaps::new(ModeMap::new(), ModeMap::new(), ModeMap::new())
+    }
+
+    /// Map `lhs` to `rhs`, as with `:map`. Sessions already using these
+    /// keymaps, and other clones, are unaffected. Returns false if the
+    /// mapping is invalid.
+    pub fn map(&mut self, mode: MapMode, lhs: Vec<K>, rhs: Vec<K>) -> bool {
+        let result = match mode {
+            MapMode::Normal => Arc::make_mut(&mut self.normal)
+                .insert_remap(lhs, rhs),
+            MapMode::Pending => Arc::make_mut(&mut self.pending)
+                .insert_remap(lhs, rhs),
+            MapMode::Insert => Arc::make_mut(&mut self.insert)
+                .insert_remap(lhs, rhs),
+        };
+        result != InsertionResult::InvalidKey
+    }
+
+    pub fn normal(&self) -> &Arc<ModeMap<K, NormalOp>> {
+        &self.normal
+    }
+
+    pub fn pending(&self) -> &Arc<ModeMap<K, PendingOp>> {
+        &self.pending
+    }
+
+    pub fn insert(&self) -> &Arc<ModeMap<K, InsertOp>> {
+        &self.insert
+    }
+}
+
+/// Vim's default mappings.
+impl Default for Keymaps<MultiKey> {
+    fn default() -> Self {
+        Keymaps::new(
+            maps::normal_mode_map(),
+            maps::pending_mode_map(),
+            maps::insert_mode_map(),
+        )
+    }
+}
@@ -10,23 +10,41 @@ extern crate serde_derive;
 extern crate nom;
 
 extern crate regex;
-extern crate xrl;
 extern crate futures;
 
-mod client;
+pub mod client;
+pub mod clock;
+pub mod command;
 mod disambiguation_map;
+pub mod effect;
+pub mod error;
+pub mod event;
+pub mod extension;
+pub mod ffi;
 mod insert_mode;
-mod key;
+pub mod json_client;
+pub mod key;
+pub mod keymaps;
+pub mod line_cache;
 mod maps;
+pub mod memory_client;
+pub mod message;
 mod mode;
+pub mod mode_info;
 mod mode_map;
 mod normal_mode;
-mod op;
+pub mod op;
+pub mod options;
 mod ordered_vec_map;
 mod pending_mode;
+pub mod plugin;
+pub mod server;
+pub mod showcmd;
 mod state;
 mod state_machine;
+pub mod trace;
 mod typeahead;
+pub mod view;
 
 
 pub mod vixi;
@@ -0,0 +1,283 @@
+use std::ops::Range;
+use view::Position;
+
+/// Style id xi-core uses for selections.
+const SELECTION_STYLE: i64 = 0;
+
+/// A line as sent by xi-core, including its trailing newline, if any.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Line {
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub cursor: Vec<u64>, // Byte offsets of cursors on this line.
+    #[serde(default)]
+    pub styles: Vec<i64>, // Triples of relative start, length and style id.
+}
+
+impl Line {
+    /// The text without its trailing newline.
+    pub fn content(&self) -> &str {
+        self.text.trim_right_matches(|c| c == '\n' || c == '\r')
+    }
+
+    /// Byte ranges of this line which are selected.
+    pub fn selections(&self) -> Vec<Range<u64>> {
+        let mut selections = Vec::new();
+        let mut end = 0i64;
+        for style in self.styles.chunks(3) {
+            if style.len() < 3 {
+                break;
+            }
+            let start = end + style[0];
+            end = start + style[1];
+            if style[2] == SELECTION_STYLE {
+                selections.push(start as u64..end as u64);
+            }
+        }
+        selections
+    }
+}
+
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OpType {
+    Copy, // Keep `n` lines of the old cache.
+    Skip, // Drop `n` lines of the old cache.
+    Invalidate, // Add `n` lines whose contents are unknown.
+    Update, // Keep `n` lines of the old cache with new cursors and styles.
+    Ins, // Add new lines.
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct UpdateOp {
+    pub op: OpType,
+    pub n: u64,
+    #[serde(default)]
+    pub lines: Vec<Line>,
+}
+
+/// The `update` parameter of xi-core's `update` notification.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Update {
+    pub ops: Vec<UpdateOp>,
+    #[serde(default)]
+    pub pristine: bool,
+}
+
+/// A local copy of a view's lines, kept in sync by xi-core updates.
+///
+/// Columns are byte offsets into a line, as with xi-core.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LineCache {
+    lines: Vec<Option<Line>>, // `None` for invalidated lines.
+    pristine: bool,
+}
+
+impl LineCache {
+    pub fn new() -> Self {
+        LineCache {
+            lines: Vec::new(),
+            pristine: true,
+        }
+    }
+
+    /// Apply an update, rebuilding the cache from the old one.
+    pub fn apply(&mut self, update: &Update) {
+        let old = &self.lines;
+        let mut lines = Vec::with_capacity(old.len());
+        let mut index = 0;
+        for op in &update.ops {
+            let n = op.n as usize;
+            match op.op {
+                OpType::Copy => {
+                    let end = (index + n).min(old.len());
+                    lines.extend_from_slice(&old[index.min(end)..end]);
+                    index += n;
+                }
+                OpType::Skip => {
+                    index += n;
+                }
+                OpType::Invalidate => {
+                    lines.extend((0..n).map(|_| None));
+                }
+                OpType::Update => {
+                    for (i, new) in op.lines.iter().enumerate() {
+                        lines.push(old.get(index + i).and_then(|line| {
+                            line.as_ref().map(|line| Line {
+                                text: line.text.clone(),
+                                cursor: new.cursor.clone(),
+                                styles: new.styles.clone(),
+                            })
+                        }));
+                    }
+                    index += n;
+                }
+                OpType::Ins => {
+                    lines.extend(op.lines.iter().cloned().map(Some));
+                }
+            }
+        }
+        self.lines = lines;
+        self.pristine = update.pristine;
+    }
+
+    /// Number of lines, including invalidated ones.
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Returns true if the buffer has no unsaved changes.
+    pub fn is_pristine(&self) -> bool {
+        self.pristine
+    }
+
+    /// Returns `None` if line `n` is out of range or has been invalidated.
+    pub fn line(&self, n: usize) -> Option<&Line> {
+        self.lines.get(n).and_then(|line| line.as_ref())
+    }
+
+    /// Every cursor, in buffer order.
+    pub fn cursors(&self) -> Vec<Position> {
+        let mut cursors = Vec::new();
+        for (n, line) in self.valid_lines() {
+            for &column in &line.cursor {
+                cursors.push(Position {
+                    line: n,
+                    column: column,
+                });
+            }
+        }
+        cursors
+    }
+
+    /// The first cursor, if any is known.
+    pub fn cursor(&self) -> Option<Position> {
+        self.cursors().into_iter().next()
+    }
+
+    /// Every selected span, one per line, in buffer order.
+    pub fn selections(&self) -> Vec<(Position, Position)> {
+        let mut selections = Vec::new();
+        for (n, line) in self.valid_lines() {
+            for range in line.selections() {
+                selections.push((
+                    Position {
+                        line: n,
+                        column: range.start,
+                    },
+                    Position {
+                        line: n,
+                        column: range.end,
+                    },
+                ));
+            }
+        }
+        selections
+    }
+
+    fn valid_lines(&self) -> Vec<(u64, &Line)> {
+        self.lines
+            .iter()
+            .enumerate()
+            .filter_map(|(n, line)| line.as_ref().map(|line| (n as u64, line)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json;
+
+    fn update(json: serde_json::Value) -> Update {
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn contents(cache: &LineCache) -> Vec<Option<&str>> {
+        (0..cache.len())
+            .map(|n| cache.line(n).map(|line| line.content()))
+            .collect()
+    }
+
+    #[test]
+    fn insert_lines() {
+        let mut cache = LineCache::new();
+        cache.apply(&update(json!({
+            "ops": [{"op": "ins", "n": 2, "lines": [
+                {"text": "one\n", "cursor": [1]},
+                {"text": "two", "styles": []},
+            ]}],
+            "pristine": true,
+        })));
+        assert_eq!(vec![Some("one"), Some("two")], contents(&cache));
+        assert!(cache.is_pristine());
+        assert_eq!(Some(Position { line: 0, column: 1 }), cache.cursor());
+    }
+
+    #[test]
+    fn copy_skip_and_invalidate() {
+        let mut cache = LineCache::new();
+        cache.apply(&update(json!({"ops": [{"op": "ins", "n": 3, "lines": [
+            {"text": "one\n"},
+            {"text": "two\n"},
+            {"text": "three"},
+        ]}]})));
+        cache.apply(&update(json!({"ops": [
+            {"op": "copy", "n": 1},
+            {"op": "skip", "n": 1},
+            {"op": "ins", "n": 1, "lines": [{"text": "deux\n"}]},
+            {"op": "invalidate", "n": 1},
+            {"op": "copy", "n": 1},
+        ]})));
+        assert_eq!(
+            vec![Some("one"), Some("deux"), None, Some("three")],
+            contents(&cache)
+        );
+        assert!(!cache.is_pristine());
+    }
+
+    #[test]
+    fn update_keeps_text() {
+        let mut cache = LineCache::new();
+        cache.apply(&update(json!({"ops": [{"op": "ins", "n": 2, "lines": [
+            {"text": "one\n", "cursor": [0]},
+            {"text": "two"},
+        ]}]})));
+        cache.apply(&update(json!({"ops": [
+            {"op": "update", "n": 2, "lines": [
+                {"cursor": []},
+                {"cursor": [2]},
+            ]},
+        ]})));
+        assert_eq!(vec![Some("one"), Some("two")], contents(&cache));
+        assert_eq!(vec![Position { line: 1, column: 2 }], cache.cursors());
+    }
+
+    #[test]
+    fn selections() {
+        let mut cache = LineCache::new();
+        cache.apply(&update(json!({"ops": [{"op": "ins", "n": 2, "lines": [
+            // Style 2 on [0, 1), then a selection on [2, 4).
+            {"text": "hello\n", "styles": [0, 1, 2, 1, 2, 0]},
+            {"text": "world", "styles": [0, 3, 0]},
+        ]}]})));
+        assert_eq!(
+            vec![
+                (
+                    Position { line: 0, column: 2 },
+                    Position { line: 0, column: 4 },
+                ),
+                (
+                    Position { line: 1, column: 0 },
+                    Position { line: 1, column: 3 },
+                ),
+            ],
+            cache.selections()
+        );
+    }
+}
@@ -1,40 +1,99 @@
 use key::parse::parse;
 use key::MultiKey;
 use mode_map::ModeMap;
-use op::{HasOperator, HasMotion, HasObject, PendingOp, ObjectOp, MotionOp, InsertOp, NormalOp,
-         OperatorOp};
+use op::{HasOperator, HasMotion, HasObject, HasCommand, PendingOp, ObjectOp, MotionOp,
+         InsertOp, NormalOp, OperatorOp, CommandId};
 use ordered_vec_map::InsertionResult;
+use typeahead::Parse;
 
-impl HasOperator<MultiKey> for ModeMap<MultiKey, NormalOp> {
-    fn insert_operator(&mut self, key: Vec<MultiKey>, op: OperatorOp) -> InsertionResult {
+impl<K> HasOperator<K> for ModeMap<K, NormalOp>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    fn insert_operator(&mut self, key: Vec<K>, op: OperatorOp) -> InsertionResult {
         self.insert_op(key, NormalOp::Operator(op))
     }
 }
 
-impl HasMotion<MultiKey> for ModeMap<MultiKey, NormalOp> {
-    fn insert_motion(&mut self, key: Vec<MultiKey>, op: MotionOp) -> InsertionResult {
+impl<K> HasMotion<K> for ModeMap<K, NormalOp>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    fn insert_motion(&mut self, key: Vec<K>, op: MotionOp) -> InsertionResult {
         self.insert_op(key, NormalOp::Motion(op))
     }
 }
 
-impl HasOperator<MultiKey> for ModeMap<MultiKey, PendingOp> {
-    fn insert_operator(&mut self, key: Vec<MultiKey>, op: OperatorOp) -> InsertionResult {
+impl<K> HasOperator<K> for ModeMap<K, PendingOp>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    fn insert_operator(&mut self, key: Vec<K>, op: OperatorOp) -> InsertionResult {
         self.insert_op(key, PendingOp::Operator(op))
     }
 }
 
-impl HasMotion<MultiKey> for ModeMap<MultiKey, PendingOp> {
-    fn insert_motion(&mut self, key: Vec<MultiKey>, op: MotionOp) -> InsertionResult {
+impl<K> HasMotion<K> for ModeMap<K, PendingOp>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    fn insert_motion(&mut self, key: Vec<K>, op: MotionOp) -> InsertionResult {
         self.insert_op(key, PendingOp::Motion(op))
     }
 }
 
-impl HasObject<MultiKey> for ModeMap<MultiKey, PendingOp> {
-    fn insert_object(&mut self, key: Vec<MultiKey>, op: ObjectOp) -> InsertionResult {
+impl<K> HasObject<K> for ModeMap<K, PendingOp>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    fn insert_object(&mut self, key: Vec<K>, op: ObjectOp) -> InsertionResult {
         self.insert_op(key, PendingOp::Object(op))
     }
 }
 
+impl<K> HasCommand<K> for ModeMap<K, NormalOp>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    fn insert_command(&mut self, key: Vec<K>, id: CommandId) -> InsertionResult {
+        self.insert_op(key, NormalOp::Command(id))
+    }
+}
+
+impl<K> HasCommand<K> for ModeMap<K, PendingOp>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    fn insert_command(&mut self, key: Vec<K>, id: CommandId) -> InsertionResult {
+        self.insert_op(key, PendingOp::Command(id))
+    }
+}
+
+impl<K> HasCommand<K> for ModeMap<K, InsertOp>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    fn insert_command(&mut self, key: Vec<K>, id: CommandId) -> InsertionResult {
+        self.insert_op(key, InsertOp::Command(id))
+    }
+}
+
 fn add_operators<Op>(map: &mut ModeMap<MultiKey, Op>)
 where
     Op: Copy,
@@ -75,6 +134,7 @@ where
     map.insert_motion(parse("gg"), Top);
     map.insert_motion(parse("G"), Bottom);
     map.insert_motion(parse("w"), Word);
+    map.insert_motion(parse("b"), WordBackward);
     map.insert_motion(parse("<bs>"), Backspace);
     map.insert_motion(parse("<home>"), Home);
     map.insert_motion(parse("<end>"), End);
@@ -134,6 +194,11 @@ pub fn normal_mode_map() -> ModeMap<MultiKey, NormalOp> {
     map.insert_op(parse("r"), ReplaceChar);
     map.insert_op(parse("R"), ReplaceMode);
     map.insert_op(parse("."), Repeat);
+    map.insert_op(parse("q"), Record);
+    map.insert_op(parse("@"), Execute);
+    map.insert_op(parse("u"), Undo);
+    map.insert_op(parse("<C-r>"), Redo);
+    map.insert_op(parse("\""), Register);
     add_operators(&mut map);
     add_motions(&mut map);
     return map;
@@ -153,12 +218,13 @@ pub fn insert_mode_map() -> ModeMap<MultiKey, InsertOp> {
     use op::InsertOp::*;
     let mut map = ModeMap::new();
     map.insert_op(parse("<Esc>"), Cancel);
+    map.insert_op(parse("<C-o>"), InsertNormal); // (Ctrl-O));
     map.insert_op(parse("<Up>"), Up);
     map.insert_op(parse("<Down>"), Down);
     map.insert_op(parse("<Left>"), Left);
     map.insert_op(parse("<Right>"), Right);
-    map.insert_op(parse("<Backspace>"), Backspace);
-    map.insert_op(parse("<Delete>"), Delete);
+    map.insert_op(parse("<BS>"), Backspace);
+    map.insert_op(parse("<Del>"), Delete);
     map.insert_op(parse("<PageUp>"), PageUp);
     map.insert_op(parse("<PageDown>"), PageDown);
     map.insert_op(parse("<kpageUp>"), PageUp);
@@ -173,12 +239,15 @@ pub fn insert_mode_map() -> ModeMap<MultiKey, InsertOp> {
     map.insert_op(parse("<C-k>"), Digraph); // (Ctrl-K));
     map.insert_op(parse("<C-r>"), InsertRegister); // (Ctrl-R));
     map.insert_op(parse("<C-r><C-r>"), InsertRegisterContents); // (Ctrl-R Ctrl-R));
+    map.insert_op(parse("<C-g>u"), BreakUndo); // (Ctrl-G u));
     return map;
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use typeahead::{Typeahead, RemapType};
+    use disambiguation_map::Match;
 
     #[test]
     fn decimal_full_match() {
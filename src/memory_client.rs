@@ -0,0 +1,803 @@
+use client::{Client, ClientError, ClientResult, TextQuery};
+use futures::future::{err, ok};
+use op::MotionOp;
+use serde_json::Value;
+use std::fs;
+use std::io;
+use std::mem;
+use std::sync::{Arc, Mutex};
+use view::{Position, ViewId};
+
+/// Lines scrolled by `page_up` and `page_down`.
+const PAGE_LINES: u64 = 20;
+
+/// Classes of characters, between which words break.
+#[derive(Copy, Clone, PartialEq)]
+enum CharClass {
+    Space,
+    Word,
+    Punctuation,
+}
+
+fn class(c: char) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Space
+    } else if c.is_alphanumeric() || c == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+/// The contents of a view.
+struct Buffer {
+    id: ViewId,
+    lines: Vec<String>, // Never empty; lines exclude their newline.
+    cursor: Position,
+    anchor: Option<Position>, // The other end of the selection, if any.
+    undo: Vec<(Vec<String>, Position)>, // Lines and cursor before changes.
+    redo: Vec<(Vec<String>, Position)>, // Lines and cursor before undoing.
+    groups: u32, // Undo groups started and not yet ended.
+    grouped: bool, // Whether the outermost group has changed anything.
+}
+
+impl Buffer {
+    fn new(id: ViewId, text: &str) -> Self {
+        Buffer {
+            id: id,
+            lines: text.split('\n').map(String::from).collect(),
+            cursor: Position { line: 0, column: 0 },
+            anchor: None,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            groups: 0,
+            grouped: false,
+        }
+    }
+
+    fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    fn line_len(&self, line: u64) -> u64 {
+        self.lines[line as usize].len() as u64
+    }
+
+    fn last_line(&self) -> u64 {
+        self.lines.len() as u64 - 1
+    }
+
+    /// The selection as (start, end), if it isn't empty.
+    fn selection(&self) -> Option<(Position, Position)> {
+        match self.anchor {
+            Some(anchor) if anchor < self.cursor => Some((anchor, self.cursor)),
+            Some(anchor) if anchor > self.cursor => Some((self.cursor, anchor)),
+            _ => None,
+        }
+    }
+
+    fn offset(&self, position: Position) -> usize {
+        let before: usize = self.lines[..position.line as usize]
+            .iter()
+            .map(|line| line.len() + 1)
+            .sum();
+        before + position.column as usize
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let mut rest = offset;
+        for (n, line) in self.lines.iter().enumerate() {
+            if rest <= line.len() {
+                return Position {
+                    line: n as u64,
+                    column: rest as u64,
+                };
+            }
+            rest -= line.len() + 1;
+        }
+        self.end()
+    }
+
+    fn end(&self) -> Position {
+        let line = self.last_line();
+        Position {
+            line: line,
+            column: self.line_len(line),
+        }
+    }
+
+    /// Move the cursor, extending the selection if `extend` is set.
+    fn move_to(&mut self, position: Position, extend: bool) {
+        if extend {
+            self.anchor = self.anchor.or(Some(self.cursor));
+        } else {
+            self.anchor = None;
+        }
+        self.cursor = position;
+    }
+
+    /// Position of `column` on `line`, clamped to the line and moved back
+    /// to a character boundary.
+    fn clamp(&self, line: u64, column: u64) -> Position {
+        let line = line.min(self.last_line());
+        let text = &self.lines[line as usize];
+        let mut column = (column as usize).min(text.len());
+        while !text.is_char_boundary(column) {
+            column -= 1;
+        }
+        Position {
+            line: line,
+            column: column as u64,
+        }
+    }
+
+    fn left(&self) -> Position {
+        let Position { line, column } = self.cursor;
+        if column > 0 {
+            let text = &self.lines[line as usize][..column as usize];
+            let c = text.chars().next_back().unwrap();
+            self.clamp(line, column - c.len_utf8() as u64)
+        } else if line > 0 {
+            self.clamp(line - 1, self.line_len(line - 1))
+        } else {
+            self.cursor
+        }
+    }
+
+    fn right(&self) -> Position {
+        let Position { line, column } = self.cursor;
+        if column < self.line_len(line) {
+            let text = &self.lines[line as usize][column as usize..];
+            let c = text.chars().next().unwrap();
+            self.clamp(line, column + c.len_utf8() as u64)
+        } else if line < self.last_line() {
+            self.clamp(line + 1, 0)
+        } else {
+            self.cursor
+        }
+    }
+
+    fn line_start(&self) -> Position {
+        self.clamp(self.cursor.line, 0)
+    }
+
+    fn line_end(&self) -> Position {
+        let line = self.cursor.line;
+        self.clamp(line, self.line_len(line))
+    }
+
+    /// The start of the next word, as with Vim's `w`.
+    fn word_right(&self) -> Position {
+        let text = self.text();
+        let offset = self.offset(self.cursor);
+        let mut chars = text[offset..].char_indices().peekable();
+        let start = chars.peek().map(|&(_, c)| class(c));
+        if let Some(start) = start {
+            while chars.peek().map_or(false, |&(_, c)| class(c) == start) {
+                chars.next();
+            }
+        }
+        while chars.peek().map_or(false, |&(_, c)| c.is_whitespace()) {
+            chars.next();
+        }
+        match chars.peek() {
+            Some(&(i, _)) => self.position(offset + i),
+            None => self.end(),
+        }
+    }
+
+    /// The start of the previous word, as with Vim's `b`.
+    fn word_left(&self) -> Position {
+        let text = self.text();
+        let offset = self.offset(self.cursor);
+        let mut chars = text[..offset].char_indices().rev().peekable();
+        while chars.peek().map_or(false, |&(_, c)| c.is_whitespace()) {
+            chars.next();
+        }
+        let start = match chars.next() {
+            Some((i, c)) => {
+                let word = class(c);
+                let mut start = i;
+                while let Some(&(i, c)) = chars.peek() {
+                    if class(c) != word {
+                        break;
+                    }
+                    start = i;
+                    chars.next();
+                }
+                start
+            }
+            None => 0,
+        };
+        self.position(start)
+    }
+
+    fn start_group(&mut self) {
+        if self.groups == 0 {
+            self.grouped = false;
+        }
+        self.groups += 1;
+    }
+
+    fn end_group(&mut self) {
+        self.groups = self.groups.saturating_sub(1);
+    }
+
+    /// Save the text for `undo` before the first change of a group.
+    fn change(&mut self) {
+        if !self.grouped {
+            self.undo.push((self.lines.clone(), self.cursor));
+            self.redo.clear();
+            self.grouped = true;
+        }
+    }
+
+    /// Go back to the text before the last group of changes, keeping the
+    /// current text for `redo`, or the other way around.
+    fn undo(&mut self, redo: bool) {
+        let (from, to) = if redo {
+            (&mut self.redo, &mut self.undo)
+        } else {
+            (&mut self.undo, &mut self.redo)
+        };
+        if let Some((lines, cursor)) = from.pop() {
+            let lines = mem::replace(&mut self.lines, lines);
+            to.push((lines, self.cursor));
+            self.cursor = cursor;
+            self.anchor = None;
+        }
+    }
+
+    /// Delete the text between `start` and `end`, leaving the cursor at
+    /// `start`.
+    fn delete_range(&mut self, start: Position, end: Position) {
+        self.change();
+        let (first, last) = (start.line as usize, end.line as usize);
+        let tail = self.lines[last].split_off(end.column as usize);
+        self.lines[first].truncate(start.column as usize);
+        self.lines[first].push_str(&tail);
+        self.lines.drain(first + 1..last + 1);
+        self.cursor = start;
+        self.anchor = None;
+    }
+
+    /// Delete the selection, if there is one. Returns false if there was
+    /// nothing to delete.
+    fn delete_selection(&mut self) -> bool {
+        match self.selection() {
+            Some((start, end)) => {
+                self.delete_range(start, end);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn insert(&mut self, s: &str) {
+        self.change();
+        self.delete_selection();
+        self.anchor = None;
+        let line = self.cursor.line as usize;
+        let tail = self.lines[line].split_off(self.cursor.column as usize);
+        let mut parts = s.split('\n');
+        self.lines[line].push_str(parts.next().unwrap_or(""));
+        let added: Vec<String> = parts.map(String::from).collect();
+        let last = line + added.len();
+        self.lines.splice(line + 1..line + 1, added);
+        let column = self.lines[last].len();
+        self.lines[last].push_str(&tail);
+        self.cursor = Position {
+            line: last as u64,
+            column: column as u64,
+        };
+    }
+
+    fn delete_backward(&mut self) {
+        if !self.delete_selection() {
+            let (start, end) = (self.left(), self.cursor);
+            self.delete_range(start, end);
+        }
+    }
+
+    fn delete_forward(&mut self) {
+        if !self.delete_selection() {
+            let (start, end) = (self.cursor, self.right());
+            self.delete_range(start, end);
+        }
+    }
+
+    /// Carry out one of xi-core's edit commands. Unknown ones are ignored.
+    fn edit(&mut self, method: &str, params: Option<&Value>) {
+        let extend = method.ends_with("_and_modify_selection");
+        let motion = method.trim_right_matches("_and_modify_selection");
+        let target = match motion {
+            "move_left" => Some(self.left()),
+            "move_right" => Some(self.right()),
+            "move_word_left" => Some(self.word_left()),
+            "move_word_right" => Some(self.word_right()),
+            "move_to_left_end_of_line" => Some(self.line_start()),
+            "move_to_right_end_of_line" => Some(self.line_end()),
+            "move_to_beginning_of_document" => Some(self.clamp(0, 0)),
+            "move_to_end_of_document" => Some(self.end()),
+            _ => None,
+        };
+        if let Some(target) = target {
+            self.move_to(target, extend);
+            return;
+        }
+        match method {
+            "insert" => {
+                let chars = params
+                    .and_then(|params| params["chars"].as_str())
+                    .unwrap_or("");
+                self.insert(chars);
+            }
+            "insert_newline" => self.insert("\n"),
+            "undo" => self.undo(false),
+            "redo" => self.undo(true),
+            "delete_backward" => self.delete_backward(),
+            "delete_forward" => self.delete_forward(),
+            "delete_word_backward" => {
+                if !self.delete_selection() {
+                    let (start, end) = (self.word_left(), self.cursor);
+                    self.delete_range(start, end);
+                }
+            }
+            "delete_to_beginning_of_line" => {
+                if !self.delete_selection() {
+                    let end = self.cursor;
+                    let start = self.clamp(end.line, 0);
+                    self.delete_range(start, end);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+struct Buffers {
+    views: Vec<Buffer>,
+    next_id: u64,
+}
+
+/// A `Client` which edits buffers held in memory, for tests and for
+/// embedding vixi without xi-core.
+///
+/// Clones share the same buffers, so one can be given to `Vixi` as its
+/// client and another as its `TextQuery`. There is a single, empty view,
+/// `ViewId(0)`, to begin with. Calls for unknown views are ignored, as
+/// xi-core does.
+#[derive(Clone)]
+pub struct MemoryClient {
+    buffers: Arc<Mutex<Buffers>>,
+}
+
+impl MemoryClient {
+    pub fn new() -> Self {
+        MemoryClient {
+            buffers: Arc::new(Mutex::new(Buffers {
+                views: vec![Buffer::new(ViewId(0), "")],
+                next_id: 1,
+            })),
+        }
+    }
+
+    /// The full text of a view, with lines joined by newlines.
+    pub fn text(&self, view_id: ViewId) -> Option<String> {
+        let buffers = self.buffers.lock().unwrap();
+        buffers
+            .views
+            .iter()
+            .find(|buffer| buffer.id == view_id)
+            .map(|buffer| buffer.text())
+    }
+
+    /// Replace the text of a view, as when it changes outside vixi. The
+    /// cursor stays where it still fits, and the undo history is dropped.
+    pub fn set_text(&mut self, view_id: ViewId, text: &str) {
+        let mut buffers = self.buffers.lock().unwrap();
+        if let Some(buffer) =
+            buffers.views.iter_mut().find(|buffer| buffer.id == view_id)
+        {
+            let cursor = buffer.cursor;
+            *buffer = Buffer::new(view_id, text);
+            buffer.cursor = buffer.clamp(cursor.line, cursor.column);
+        }
+    }
+
+    /// Run `f` on the buffer of `view_id`, if it exists.
+    fn with<F>(&mut self, view_id: ViewId, f: F) -> ClientResult<()>
+    where
+        F: FnOnce(&mut Buffer),
+    {
+        let mut buffers = self.buffers.lock().unwrap();
+        if let Some(buffer) =
+            buffers.views.iter_mut().find(|buffer| buffer.id == view_id)
+        {
+            // A call is undone at once, unless part of a larger group.
+            buffer.start_group();
+            f(buffer);
+            buffer.end_group();
+        }
+        Box::new(ok(()))
+    }
+
+    fn move_to<F>(
+        &mut self,
+        view_id: ViewId,
+        extend: bool,
+        target: F,
+    ) -> ClientResult<()>
+    where
+        F: FnOnce(&Buffer) -> Position,
+    {
+        self.with(view_id, |buffer| {
+            let position = target(buffer);
+            buffer.move_to(position, extend);
+        })
+    }
+
+    fn vertical(
+        &mut self,
+        view_id: ViewId,
+        lines: i64,
+        extend: bool,
+    ) -> ClientResult<()> {
+        self.move_to(view_id, extend, |buffer| {
+            let Position { line, column } = buffer.cursor;
+            let line = (line as i64 + lines).max(0) as u64;
+            buffer.clamp(line, column)
+        })
+    }
+}
+
+impl Client for MemoryClient {
+    fn notify(&mut self, _method: &str, _params: Value) -> ClientResult<()> {
+        Box::new(ok(()))
+    }
+    fn request(&mut self, _method: &str, _params: Value) -> ClientResult<Value> {
+        Box::new(ok(Value::Null))
+    }
+    fn edit(&mut self, view_id: ViewId, method: &str, params: Option<Value>) -> ClientResult<()> {
+        self.with(view_id, |buffer| buffer.edit(method, params.as_ref()))
+    }
+    fn scroll(&mut self, _view_id: ViewId, _first_line: u64, _last_line: u64) -> ClientResult<()> {
+        Box::new(ok(()))
+    }
+    fn left(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.move_to(view_id, false, |buffer| buffer.left())
+    }
+    fn left_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.move_to(view_id, true, |buffer| buffer.left())
+    }
+    fn right(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.move_to(view_id, false, |buffer| buffer.right())
+    }
+    fn right_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.move_to(view_id, true, |buffer| buffer.right())
+    }
+    fn up(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.vertical(view_id, -1, false)
+    }
+    fn up_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.vertical(view_id, -1, true)
+    }
+    fn down(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.vertical(view_id, 1, false)
+    }
+    fn down_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.vertical(view_id, 1, true)
+    }
+    fn delete(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.with(view_id, |buffer| buffer.delete_forward())
+    }
+    fn backspace(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.with(view_id, |buffer| buffer.delete_backward())
+    }
+    fn del(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.with(view_id, |buffer| buffer.delete_backward())
+    }
+    fn page_up(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.vertical(view_id, -(PAGE_LINES as i64), false)
+    }
+    fn page_up_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.vertical(view_id, -(PAGE_LINES as i64), true)
+    }
+    fn page_down(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.vertical(view_id, PAGE_LINES as i64, false)
+    }
+    fn page_down_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.vertical(view_id, PAGE_LINES as i64, true)
+    }
+    fn line_start(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.move_to(view_id, false, |buffer| buffer.line_start())
+    }
+    fn line_start_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.move_to(view_id, true, |buffer| buffer.line_start())
+    }
+    fn line_end(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.move_to(view_id, false, |buffer| buffer.line_end())
+    }
+    fn line_end_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.move_to(view_id, true, |buffer| buffer.line_end())
+    }
+    fn insert_newline(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.with(view_id, |buffer| buffer.insert("\n"))
+    }
+    fn f1(&mut self, _view_id: ViewId) -> ClientResult<()> {
+        Box::new(ok(()))
+    }
+    fn f2(&mut self, _view_id: ViewId) -> ClientResult<()> {
+        Box::new(ok(()))
+    }
+    fn char(&mut self, view_id: ViewId, ch: char) -> ClientResult<()> {
+        self.with(view_id, |buffer| buffer.insert(&ch.to_string()))
+    }
+    fn click(&mut self, view_id: ViewId, line: u64, column: u64) -> ClientResult<()> {
+        self.move_to(view_id, false, |buffer| buffer.clamp(line, column))
+    }
+    fn drag(&mut self, view_id: ViewId, line: u64, column: u64) -> ClientResult<()> {
+        self.move_to(view_id, true, |buffer| buffer.clamp(line, column))
+    }
+    fn new_view(&mut self, file_path: Option<String>) -> ClientResult<ViewId> {
+        // As with xi-core, a file which doesn't exist yet opens empty.
+        let text = match file_path.map(fs::read_to_string) {
+            Some(Ok(text)) => text,
+            Some(Err(ref e)) if e.kind() != io::ErrorKind::NotFound => {
+                let message = json!({"message": e.to_string()});
+                return Box::new(err(ClientError::ErrorReturned(message)));
+            }
+            _ => String::new(),
+        };
+        let mut buffers = self.buffers.lock().unwrap();
+        let view_id = ViewId(buffers.next_id);
+        buffers.next_id += 1;
+        buffers.views.push(Buffer::new(view_id, &text));
+        Box::new(ok(view_id))
+    }
+    fn close_view(&mut self, view_id: ViewId) -> ClientResult<()> {
+        let mut buffers = self.buffers.lock().unwrap();
+        buffers.views.retain(|buffer| buffer.id != view_id);
+        Box::new(ok(()))
+    }
+    fn save(&mut self, view_id: ViewId, file_path: &str) -> ClientResult<()> {
+        let text = match self.text(view_id) {
+            Some(text) => text,
+            None => {
+                return Box::new(ok(()));
+            }
+        };
+        match fs::write(file_path, text) {
+            Ok(()) => Box::new(ok(())),
+            Err(e) => {
+                let message = json!({"message": e.to_string()});
+                Box::new(err(ClientError::ErrorReturned(message)))
+            }
+        }
+    }
+    fn set_theme(&mut self, _theme: &str) -> ClientResult<()> {
+        Box::new(ok(()))
+    }
+    fn start_plugin(&mut self, _view_id: ViewId, _name: &str) -> ClientResult<()> {
+        Box::new(ok(()))
+    }
+    fn stop_plugin(&mut self, _view_id: ViewId, _name: &str) -> ClientResult<()> {
+        Box::new(ok(()))
+    }
+    fn notify_plugin(
+        &mut self,
+        _view_id: ViewId,
+        _plugin: &str,
+        _method: &str,
+        _params: Value,
+    ) -> ClientResult<()> {
+        Box::new(ok(()))
+    }
+    fn groups_undo(&self) -> bool {
+        true
+    }
+    fn start_undo_group(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.with(view_id, |buffer| buffer.start_group())
+    }
+    fn end_undo_group(&mut self, view_id: ViewId) -> ClientResult<()> {
+        self.with(view_id, |buffer| buffer.end_group())
+    }
+    fn move_count(
+        &mut self,
+        view_id: ViewId,
+        motion: MotionOp,
+        count: i32,
+    ) -> Option<ClientResult<()>> {
+        let lines = match motion {
+            MotionOp::Up => -count,
+            MotionOp::Down => count,
+            _ => {
+                return None;
+            }
+        };
+        Some(self.vertical(view_id, lines as i64, false))
+    }
+}
+
+impl TextQuery for MemoryClient {
+    fn line_count(&self, view_id: ViewId) -> u64 {
+        let buffers = self.buffers.lock().unwrap();
+        buffers
+            .views
+            .iter()
+            .find(|buffer| buffer.id == view_id)
+            .map_or(0, |buffer| buffer.lines.len() as u64)
+    }
+
+    fn line(&self, view_id: ViewId, line: u64) -> Option<String> {
+        let buffers = self.buffers.lock().unwrap();
+        let buffer = buffers.views.iter().find(|buffer| buffer.id == view_id)?;
+        buffer.lines.get(line as usize).cloned()
+    }
+
+    fn cursor(&self, view_id: ViewId) -> Option<Position> {
+        let buffers = self.buffers.lock().unwrap();
+        buffers
+            .views
+            .iter()
+            .find(|buffer| buffer.id == view_id)
+            .map(|buffer| buffer.cursor)
+    }
+
+    fn selections(&self, view_id: ViewId) -> Vec<(Position, Position)> {
+        let buffers = self.buffers.lock().unwrap();
+        buffers
+            .views
+            .iter()
+            .find(|buffer| buffer.id == view_id)
+            .and_then(|buffer| buffer.selection())
+            .into_iter()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::Future;
+    use std::env;
+
+    fn position(line: u64, column: u64) -> Position {
+        Position {
+            line: line,
+            column: column,
+        }
+    }
+
+    /// A client whose only view holds `text`, with the cursor at the start.
+    fn client(text: &str) -> MemoryClient {
+        let mut client = MemoryClient::new();
+        for c in text.chars() {
+            let request = match c {
+                '\n' => client.insert_newline(ViewId(0)),
+                _ => client.char(ViewId(0), c),
+            };
+            request.wait().unwrap();
+        }
+        client.click(ViewId(0), 0, 0).wait().unwrap();
+        client
+    }
+
+    #[test]
+    fn insert_and_backspace() {
+        let mut client = client("");
+        for c in "héllo".chars() {
+            client.char(ViewId(0), c).wait().unwrap();
+        }
+        client.insert_newline(ViewId(0)).wait().unwrap();
+        client.backspace(ViewId(0)).wait().unwrap();
+        client.backspace(ViewId(0)).wait().unwrap();
+        assert_eq!(Some(String::from("héll")), client.text(ViewId(0)));
+        assert_eq!(Some(position(0, 5)), client.cursor(ViewId(0)));
+    }
+
+    #[test]
+    fn forward_delete_joins_lines() {
+        let mut client = client("ab\ncd");
+        client.line_end(ViewId(0)).wait().unwrap();
+        client.delete(ViewId(0)).wait().unwrap();
+        assert_eq!(Some(String::from("abcd")), client.text(ViewId(0)));
+    }
+
+    #[test]
+    fn vertical_moves_clamp_column() {
+        let mut client = client("long line\nab");
+        client.line_end(ViewId(0)).wait().unwrap();
+        client.down(ViewId(0)).wait().unwrap();
+        assert_eq!(Some(position(1, 2)), client.cursor(ViewId(0)));
+        client.down(ViewId(0)).wait().unwrap();
+        assert_eq!(Some(position(1, 2)), client.cursor(ViewId(0)));
+    }
+
+    #[test]
+    fn word_motions() {
+        let mut client = client("one, two\n  three");
+        let mut words = Vec::new();
+        for _ in 0..4 {
+            client.edit(ViewId(0), "move_word_right", None).wait().unwrap();
+            words.push(client.cursor(ViewId(0)).unwrap());
+        }
+        assert_eq!(
+            vec![
+                position(0, 3),
+                position(0, 5),
+                position(1, 2),
+                position(1, 7),
+            ],
+            words
+        );
+        client.edit(ViewId(0), "move_word_left", None).wait().unwrap();
+        assert_eq!(Some(position(1, 2)), client.cursor(ViewId(0)));
+        client.edit(ViewId(0), "move_word_left", None).wait().unwrap();
+        assert_eq!(Some(position(0, 5)), client.cursor(ViewId(0)));
+    }
+
+    #[test]
+    fn drag_selects_and_delete_removes_selection() {
+        let mut client = client("hello world");
+        client.click(ViewId(0), 0, 2).wait().unwrap();
+        client.drag(ViewId(0), 0, 8).wait().unwrap();
+        assert_eq!(
+            vec![(position(0, 2), position(0, 8))],
+            client.selections(ViewId(0))
+        );
+        client.delete(ViewId(0)).wait().unwrap();
+        assert_eq!(Some(String::from("herld")), client.text(ViewId(0)));
+        assert!(client.selections(ViewId(0)).is_empty());
+    }
+
+    #[test]
+    fn insert_edit() {
+        let mut client = client("ac");
+        client.right(ViewId(0)).wait().unwrap();
+        let params = Some(json!({"chars": "b\nb"}));
+        client.edit(ViewId(0), "insert", params).wait().unwrap();
+        assert_eq!(Some(String::from("ab\nbc")), client.text(ViewId(0)));
+        assert_eq!(Some(position(1, 1)), client.cursor(ViewId(0)));
+    }
+
+    #[test]
+    fn save_and_open() {
+        let path = env::temp_dir().join("vixi_memory_client_test.txt");
+        let path = path.to_str().unwrap();
+        let mut client = client("saved\n");
+        client.save(ViewId(0), path).wait().unwrap();
+        let view_id = client.new_view(Some(String::from(path))).wait().unwrap();
+        assert_eq!(Some(String::from("saved\n")), client.text(view_id));
+        assert_eq!(2, client.line_count(view_id));
+        client.close_view(view_id).wait().unwrap();
+        assert_eq!(None, client.text(view_id));
+        fs::remove_file(path).unwrap();
+
+        // A new file opens empty, but one which can't be read fails.
+        let view_id = client.new_view(Some(String::from(path))).wait().unwrap();
+        assert_eq!(Some(String::new()), client.text(view_id));
+        let dir = env::temp_dir().to_str().map(String::from);
+        assert!(client.new_view(dir).wait().is_err());
+    }
+
+    #[test]
+    fn undo_groups() {
+        let mut client = client("");
+        client.start_undo_group(ViewId(0)).wait().unwrap();
+        client.char(ViewId(0), 'a').wait().unwrap();
+        client.insert_newline(ViewId(0)).wait().unwrap();
+        client.end_undo_group(ViewId(0)).wait().unwrap();
+        client.char(ViewId(0), 'b').wait().unwrap();
+        assert_eq!(Some(String::from("a\nb")), client.text(ViewId(0)));
+
+        client.edit(ViewId(0), "undo", None).wait().unwrap();
+        assert_eq!(Some(String::from("a\n")), client.text(ViewId(0)));
+        client.edit(ViewId(0), "undo", None).wait().unwrap();
+        assert_eq!(Some(String::new()), client.text(ViewId(0)));
+        assert_eq!(Some(position(0, 0)), client.cursor(ViewId(0)));
+        client.edit(ViewId(0), "redo", None).wait().unwrap();
+        assert_eq!(Some(String::from("a\n")), client.text(ViewId(0)));
+        assert_eq!(Some(position(1, 0)), client.cursor(ViewId(0)));
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+use std::collections::vec_deque::Iter;
+use std::fmt;
+
+/// Number of messages remembered for `:messages`, as with Vim's default
+/// 'msghistory'.
+pub const DEFAULT_HISTORY: usize = 200;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// A message for the user, optionally carrying a Vim error code, e.g. `E223`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Message {
+    pub severity: Severity,
+    pub code: Option<u16>, // Vim's error number, if there is one.
+    pub text: String,
+}
+
+impl Message {
+    pub fn info(text: &str) -> Self {
+        Message {
+            severity: Severity::Info,
+            code: None,
+            text: String::from(text),
+        }
+    }
+
+    pub fn warning(text: &str) -> Self {
+        Message {
+            severity: Severity::Warning,
+            code: None,
+            text: String::from(text),
+        }
+    }
+
+    pub fn error(code: Option<u16>, text: &str) -> Self {
+        Message {
+            severity: Severity::Error,
+            code: code,
+            text: String::from(text),
+        }
+    }
+
+    /// E223: An infinite loop was found while remapping.
+    pub fn recursive_mapping() -> Self {
+        Message::error(Some(223), "recursive mapping")
+    }
+
+    /// E492: `command` is not a known command.
+    pub fn not_an_editor_command(command: &str) -> Self {
+        Message::error(
+            Some(492),
+            &format!("Not an editor command: {}", command),
+        )
+    }
+}
+
+/// Formats the message as Vim would show it, e.g. "E223: recursive mapping".
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "E{}: {}", code, self.text),
+            None => write!(f, "{}", self.text),
+        }
+    }
+}
+
+/// A bounded message history which remembers which messages are new.
+#[derive(Debug)]
+pub struct Messages {
+    history: VecDeque<Message>,
+    unread: usize, // Number of messages at the back not yet drained.
+    capacity: usize,
+}
+
+impl Messages {
+    pub fn new(capacity: usize) -> Self {
+        Messages {
+            history: VecDeque::new(),
+            unread: 0,
+            capacity: capacity,
+        }
+    }
+
+    pub fn push(&mut self, message: Message) {
+        self.history.push_back(message);
+        while self.history.len() > self.capacity {
+            self.history.pop_front();
+        }
+        self.unread = (self.unread + 1).min(self.history.len());
+    }
+
+    /// Returns messages pushed since the last call, oldest first.
+    pub fn drain_new(&mut self) -> Vec<Message> {
+        let start = self.history.len() - self.unread;
+        self.unread = 0;
+        self.history.iter().skip(start).cloned().collect()
+    }
+
+    /// Iterates over the remembered messages, oldest first.
+    pub fn iter(&self) -> Iter<Message> {
+        self.history.iter()
+    }
+
+    /// Forget every message, as with `:messages clear`.
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.unread = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn display_with_code() {
+        assert_eq!(
+            "E223: recursive mapping",
+            Message::recursive_mapping().to_string()
+        );
+        assert_eq!("hello", Message::info("hello").to_string());
+    }
+
+    #[test]
+    fn drain_only_new() {
+        let mut messages = Messages::new(DEFAULT_HISTORY);
+        messages.push(Message::info("one"));
+        assert_eq!(vec![Message::info("one")], messages.drain_new());
+        messages.push(Message::info("two"));
+        messages.push(Message::info("three"));
+        assert_eq!(
+            vec![Message::info("two"), Message::info("three")],
+            messages.drain_new()
+        );
+        assert!(messages.drain_new().is_empty());
+        assert_eq!(3, messages.iter().count());
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut messages = Messages::new(2);
+        messages.push(Message::info("one"));
+        messages.push(Message::info("two"));
+        messages.push(Message::info("three"));
+        assert_eq!(
+            vec![Message::info("two"), Message::info("three")],
+            messages.drain_new()
+        );
+        assert_eq!(
+            vec![&Message::info("two"), &Message::info("three")],
+            messages.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn clear_forgets_everything() {
+        let mut messages = Messages::new(DEFAULT_HISTORY);
+        messages.push(Message::info("one"));
+        messages.clear();
+        assert!(messages.drain_new().is_empty());
+        assert_eq!(0, messages.iter().count());
+    }
+}
@@ -1,3 +1,5 @@
+use mode_info::ModeInfo;
+use op::{NormalOp, OperatorOp};
 use state::State;
 use std::marker::PhantomData;
 use typeahead::Parse;
@@ -9,34 +11,40 @@ where
     K: Parse,
 {
     fn name(&self) -> &'static str;
+    fn info(&self, state: &State<K>) -> ModeInfo;
     fn transition(&self, state: &mut State<K>) -> Mode<K>;
 }
 
-#[derive(Clone, Copy, Debug)]
-pub struct NormalMode<K> {
-    t: PhantomData<K>,
-}
-
-/// Used by `PendingMode` to remember what mode to transition to next.
-#[derive(Clone, Copy, Debug)]
+/// Used by `PendingMode` and `NormalMode` to remember what mode to
+/// transition to next.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum NextMode {
     Normal,
     Insert,
+    Replace,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct NormalMode<K> {
+    t: PhantomData<K>,
+    pub next_mode: NextMode, // Mode to return to after a command (Ctrl-O).
+    pub char_op: Option<NormalOp>, // Op awaiting a character, e.g. `q{reg}`.
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct PendingMode<K> {
     t: PhantomData<K>,
+    pub operator: OperatorOp, // Operator awaiting a motion or text object.
     pub next_mode: NextMode, // Mode to return to after motion or text object.
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct InsertMode<K> {
     t: PhantomData<K>,
-    replace_mode: bool,
+    pub replace_mode: bool,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub enum Mode<K> {
     Normal(NormalMode<K>),
     Pending(PendingMode<K>),
@@ -44,16 +52,43 @@ pub enum Mode<K> {
 }
 
 pub fn normal<K>() -> Mode<K> {
-    Mode::Normal(NormalMode::<K> { t: PhantomData::<K> {} })
+    Mode::Normal(NormalMode::<K> {
+        t: PhantomData::<K> {},
+        next_mode: NextMode::Normal,
+        char_op: None,
+    })
+}
+
+/// Normal mode for a single command, after which `next_mode` is entered.
+pub fn insert_normal<K>(next_mode: NextMode) -> Mode<K> {
+    Mode::Normal(NormalMode::<K> {
+        t: PhantomData::<K> {},
+        next_mode: next_mode,
+        char_op: None,
+    })
 }
 
 pub fn recast_normal<K>(orig: &NormalMode<K>) -> Mode<K> {
-    Mode::Normal(NormalMode::<K> { t: PhantomData::<K> {} })
+    Mode::Normal(NormalMode::<K> {
+        t: PhantomData::<K> {},
+        next_mode: orig.next_mode,
+        char_op: orig.char_op,
+    })
 }
 
-pub fn pending<K>(next_mode: NextMode) -> Mode<K> {
+/// Normal mode, waiting for the character argument of `op`.
+pub fn char_pending<K>(orig: &NormalMode<K>, op: NormalOp) -> Mode<K> {
+    Mode::Normal(NormalMode::<K> {
+        t: PhantomData::<K> {},
+        next_mode: orig.next_mode,
+        char_op: Some(op),
+    })
+}
+
+pub fn pending<K>(operator: OperatorOp, next_mode: NextMode) -> Mode<K> {
     Mode::Pending(PendingMode::<K> {
         t: PhantomData::<K> {},
+        operator: operator,
         next_mode: next_mode,
     })
 }
@@ -61,6 +96,7 @@ pub fn pending<K>(next_mode: NextMode) -> Mode<K> {
 pub fn recast_pending<K>(orig: &PendingMode<K>) -> Mode<K> {
     Mode::Pending(PendingMode::<K> {
         t: PhantomData::<K> {},
+        operator: orig.operator,
         next_mode: orig.next_mode,
     })
 }
@@ -79,6 +115,22 @@ pub fn replace<K>() -> Mode<K> {
     })
 }
 
+pub fn recast_insert<K>(orig: &InsertMode<K>) -> Mode<K> {
+    Mode::Insert(InsertMode::<K> {
+        t: PhantomData::<K> {},
+        replace_mode: orig.replace_mode,
+    })
+}
+
+/// Returns the mode named by `next_mode`.
+pub fn next<K>(next_mode: NextMode) -> Mode<K> {
+    match next_mode {
+        NextMode::Normal => normal(),
+        NextMode::Insert => insert(),
+        NextMode::Replace => replace(),
+    }
+}
+
 impl<K> Transition<K> for Mode<K>
 where
     K: Ord,
@@ -93,6 +145,14 @@ where
         }
     }
 
+    fn info(&self, state: &State<K>) -> ModeInfo {
+        match *self {
+            Mode::Normal(x) => x.info(state),
+            Mode::Pending(x) => x.info(state),
+            Mode::Insert(x) => x.info(state),
+        }
+    }
+
     fn transition(&self, state: &mut State<K>) -> Mode<K> {
         match *self {
             Mode::Normal(x) => x.transition(state),
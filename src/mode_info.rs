@@ -0,0 +1,96 @@
+use op::OperatorOp;
+
+/// The basic modes, see `:help vim-modes`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum BaseMode {
+    Normal,
+    Insert,
+}
+
+/// Variants of a `BaseMode`, see `:help vim-modes`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SubMode {
+    None,
+    OperatorPending(OperatorOp), // Normal, waiting for a motion (e.g. `d`).
+    Replace, // Insert, overwriting existing text (`R`).
+    InsertNormal, // Normal for one command, entered from Insert (Ctrl-O).
+    ReplaceNormal, // Normal for one command, entered from Replace (Ctrl-O).
+}
+
+/// The cursor shape a frontend should draw, mirroring Vim's 'guicursor'
+/// defaults.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CursorShape {
+    Block,
+    Bar,
+    Underline,
+}
+
+/// Everything a frontend needs to render a mode indicator.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModeInfo {
+    pub base: BaseMode,
+    pub submode: SubMode,
+    pub recording: Option<char>, // Register being recorded into with `q`.
+    pub showmode: String, // Vim's 'showmode' text, e.g. "-- INSERT --".
+    pub cursor: CursorShape,
+}
+
+impl ModeInfo {
+    pub fn new(
+        base: BaseMode,
+        submode: SubMode,
+        recording: Option<char>,
+    ) -> Self {
+        let mut showmode = String::from(match (base, submode) {
+            (BaseMode::Insert, SubMode::Replace) => "-- REPLACE --",
+            (BaseMode::Insert, _) => "-- INSERT --",
+            (_, SubMode::InsertNormal) => "-- (insert) --",
+            (_, SubMode::ReplaceNormal) => "-- (replace) --",
+            _ => "",
+        });
+        if let Some(register) = recording {
+            showmode.push_str("recording @");
+            showmode.push(register);
+        }
+        let cursor = match (base, submode) {
+            (BaseMode::Insert, SubMode::Replace) => CursorShape::Underline,
+            (BaseMode::Insert, _) => CursorShape::Bar,
+            (_, SubMode::OperatorPending(_)) => CursorShape::Underline,
+            _ => CursorShape::Block,
+        };
+        ModeInfo {
+            base: base,
+            submode: submode,
+            recording: recording,
+            showmode: showmode,
+            cursor: cursor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn normal_has_no_showmode() {
+        let info = ModeInfo::new(BaseMode::Normal, SubMode::None, None);
+        assert_eq!("", info.showmode);
+        assert_eq!(CursorShape::Block, info.cursor);
+    }
+
+    #[test]
+    fn replace_showmode() {
+        let info = ModeInfo::new(BaseMode::Insert, SubMode::Replace, None);
+        assert_eq!("-- REPLACE --", info.showmode);
+        assert_eq!(CursorShape::Underline, info.cursor);
+    }
+
+    #[test]
+    fn recording_showmode() {
+        let info = ModeInfo::new(BaseMode::Insert, SubMode::None, Some('a'));
+        assert_eq!("-- INSERT --recording @a", info.showmode);
+        assert_eq!(CursorShape::Bar, info.cursor);
+    }
+}
@@ -7,6 +7,7 @@ use disambiguation_map::{DisambiguationMap, Match};
 use ordered_vec_map::InsertionResult;
 use std::cmp::min;
 use std::ops::Range;
+use std::sync::Arc;
 use typeahead::{Parse, Typeahead, RemapType};
 
 impl Parse for u8 {
@@ -28,7 +29,21 @@ pub enum MapErr {
     InfiniteRecursion, // An infinite loop due to remapping is suspected.
 }
 
-#[derive(Debug, PartialEq)]
+/// What a key sequence is mapped to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target<K, Op> {
+    Remap(Vec<K>), // Keys replacing the sequence in the typeahead buffer.
+    Op(Op),
+}
+
+/// A mapping which could complete a partially-typed key sequence.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Continuation<K, Op> {
+    pub keys: Vec<K>, // Keys remaining after the typed prefix.
+    pub target: Target<K, Op>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ModeMap<K, Op>
 where
     K: Ord,
@@ -38,6 +53,8 @@ where
 {
     remap_map: DisambiguationMap<K, Vec<K>>,
     op_map: DisambiguationMap<K, Op>,
+    #[serde(skip, default = "Option::default")]
+    base: Option<Arc<ModeMap<K, Op>>>, // Looked up after this map, if any.
 }
 
 impl<K, Op> ModeMap<K, Op>
@@ -51,6 +68,16 @@ where
         ModeMap {
             remap_map: DisambiguationMap::new(),
             op_map: DisambiguationMap::new(),
+            base: None,
+        }
+    }
+
+    /// An empty map layered over `base`, which is shared rather than
+    /// copied. Mappings added to it override those of `base`.
+    pub fn layered(base: Arc<ModeMap<K, Op>>) -> Self {
+        ModeMap {
+            base: Some(base),
+            ..ModeMap::new()
         }
     }
 
@@ -66,10 +93,8 @@ where
             }
             i += 1;
 
-            let remap_result =
-                self.remap_map.process(typeahead, RemapType::Remap);
-            let op_result =
-                self.op_map.process(typeahead, RemapType::NotRelavant);
+            let remap_result = self.match_remap(typeahead);
+            let op_result = self.match_op(typeahead);
 
             match (remap_result, op_result) {
                 (Match::PartialMatch, _) |
@@ -96,6 +121,128 @@ where
         return Err(MapErr::NoMatch);
     }
 
+    /// Returns true if the front of `typeahead` is a strict prefix of a
+    /// remap or op key, meaning more keys are needed to disambiguate it.
+    pub fn is_ambiguous(&self, typeahead: &Typeahead<K>) -> bool {
+        let remap_result = self.match_remap(typeahead);
+        let op_result = self.match_op(typeahead);
+        match (remap_result, op_result) {
+            (Match::PartialMatch, _) |
+            (_, Match::PartialMatch) => true,
+            _ => false,
+        }
+    }
+
+    /// Process a typeahead buffer whose mappings have timed out: rather than
+    /// waiting for more keys, the shortest prefix which is a remap or op key
+    /// is taken. Keys matching neither are left for the mode to treat
+    /// literally.
+    pub fn resolve(&self, typeahead: &mut Typeahead<K>) -> Result<Op, MapErr> {
+        const MAX_REMAP_ITERATIONS: i32 = 1000;
+        for _ in 0..MAX_REMAP_ITERATIONS {
+            match self.process(typeahead) {
+                Err(MapErr::NoMatch) => {}
+                result => {
+                    return result;
+                }
+            }
+            let keys: Vec<K> = typeahead.value_iter().collect();
+            let remappable = typeahead
+                .iter()
+                .take_while(|&&(_, remap_type)| remap_type == RemapType::Remap)
+                .count();
+            let remap = |len: usize| if len <= remappable {
+                self.get_remap(&keys[..len].to_vec())
+            } else {
+                None
+            };
+            let len = (1..keys.len() + 1).find(|&len| {
+                remap(len).is_some() ||
+                    self.get_op(&keys[..len].to_vec()).is_some()
+            });
+            let len = match len {
+                Some(len) => len,
+                None => {
+                    return Err(MapErr::NoMatch);
+                }
+            };
+            match remap(len) {
+                Some(mapped) => {
+                    typeahead.drain(Range { start: 0, end: len });
+                    typeahead.put_front(mapped, RemapType::Remap);
+                }
+                None => {
+                    typeahead.drain(Range { start: 0, end: len });
+                    return Ok(*self.get_op(&keys[..len].to_vec()).unwrap());
+                }
+            }
+        }
+        Err(MapErr::InfiniteRecursion)
+    }
+
+    /// Returns true if the front of `typeahead` is a strict prefix of a
+    /// remap key, i.e. a mapping is waiting to time out.
+    pub fn awaits_remap(&self, typeahead: &Typeahead<K>) -> bool {
+        match self.match_remap(typeahead) {
+            Match::PartialMatch => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if a mode should wait for more keys after `process` or
+    /// `resolve` found no match. Once mappings have timed out, only op keys,
+    /// such as `gg`, are waited for.
+    pub fn waits(&self, typeahead: &Typeahead<K>, timed_out: bool) -> bool {
+        if !timed_out {
+            return self.is_ambiguous(typeahead);
+        }
+        match self.match_op(typeahead) {
+            Match::PartialMatch => true,
+            _ => false,
+        }
+    }
+
+    /// Lists every remap and op whose key strictly extends `prefix`, ordered
+    /// by key. Ops shadowed by a remap of the same key are omitted.
+    pub fn continuations(&self, prefix: &Vec<K>) -> Vec<Continuation<K, Op>> {
+        let remaps = self.remap_continuations(prefix);
+        let mut result: Vec<(&Vec<K>, Target<K, Op>)> = remaps
+            .iter()
+            .map(|kv| (&kv.0, Target::Remap(kv.1.clone())))
+            .collect();
+        for kv in self.op_continuations(prefix) {
+            if !remaps.iter().any(|remap| remap.0 == kv.0) {
+                result.push((&kv.0, Target::Op(kv.1)));
+            }
+        }
+        result.sort_by(|a, b| a.0.cmp(b.0));
+        result
+            .into_iter()
+            .map(|(key, target)| {
+                Continuation {
+                    keys: key[prefix.len()..].to_vec(),
+                    target: target,
+                }
+            })
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remap_map.is_empty() && self.op_map.is_empty() &&
+            self.base.as_ref().map_or(true, |base| base.is_empty())
+    }
+
+    /// Add every mapping of `other`, but not of its base, replacing those
+    /// with the same keys.
+    pub fn extend(&mut self, other: &ModeMap<K, Op>) {
+        for kv in other.remap_map.iter() {
+            self.remap_map.insert(kv.clone());
+        }
+        for kv in other.op_map.iter() {
+            self.op_map.insert(kv.clone());
+        }
+    }
+
     /// Insert a mapping from `key` to `value` in the operations map.
     /// Empty `key`s are not allowed.
     pub fn insert_op(&mut self, key: Vec<K>, value: Op) -> InsertionResult {
@@ -119,6 +266,95 @@ where
             self.remap_map.insert((key, value))
         }
     }
+
+    fn match_remap(
+        &self,
+        typeahead: &Typeahead<K>,
+    ) -> Match<&(Vec<K>, Vec<K>)> {
+        let result = self.remap_map.process(typeahead, RemapType::Remap);
+        match self.base {
+            Some(ref base) => layer_match(result, base.match_remap(typeahead)),
+            None => result,
+        }
+    }
+
+    fn match_op(&self, typeahead: &Typeahead<K>) -> Match<&(Vec<K>, Op)> {
+        let result = self.op_map.process(typeahead, RemapType::NotRelavant);
+        match self.base {
+            Some(ref base) => layer_match(result, base.match_op(typeahead)),
+            None => result,
+        }
+    }
+
+    fn get_remap(&self, key: &Vec<K>) -> Option<&Vec<K>> {
+        self.remap_map.get(key).or_else(|| {
+            self.base.as_ref().and_then(|base| base.get_remap(key))
+        })
+    }
+
+    fn get_op(&self, key: &Vec<K>) -> Option<&Op> {
+        self.op_map.get(key).or_else(|| {
+            self.base.as_ref().and_then(|base| base.get_op(key))
+        })
+    }
+
+    fn remap_continuations(&self, prefix: &Vec<K>) -> Vec<&(Vec<K>, Vec<K>)> {
+        let result = self.remap_map.continuations(prefix);
+        match self.base {
+            Some(ref base) => {
+                layer_entries(result, base.remap_continuations(prefix))
+            }
+            None => result,
+        }
+    }
+
+    fn op_continuations(&self, prefix: &Vec<K>) -> Vec<&(Vec<K>, Op)> {
+        let result = self.op_map.continuations(prefix);
+        match self.base {
+            Some(ref base) => {
+                layer_entries(result, base.op_continuations(prefix))
+            }
+            None => result,
+        }
+    }
+}
+
+/// Combine the match of a map with that of its base, as if they were one
+/// map: the longest key wins, and the map's own on a tie.
+fn layer_match<'a, K, T>(
+    own: Match<&'a (Vec<K>, T)>,
+    base: Match<&'a (Vec<K>, T)>,
+) -> Match<&'a (Vec<K>, T)> {
+    match (own, base) {
+        (Match::PartialMatch, _) |
+        (_, Match::PartialMatch) => Match::PartialMatch,
+        (Match::FullMatch(own), Match::FullMatch(base)) => {
+            if base.0.len() > own.0.len() {
+                Match::FullMatch(base)
+            } else {
+                Match::FullMatch(own)
+            }
+        }
+        (Match::NoMatch, base) => base,
+        (own, Match::NoMatch) => own,
+    }
+}
+
+/// Add to a map's own entries those of its base which it doesn't override.
+fn layer_entries<'a, K, T>(
+    mut own: Vec<&'a (Vec<K>, T)>,
+    base: Vec<&'a (Vec<K>, T)>,
+) -> Vec<&'a (Vec<K>, T)>
+where
+    K: Ord,
+{
+    for kv in base {
+        if !own.iter().any(|own_kv| own_kv.0 == kv.0) {
+            own.push(kv);
+        }
+    }
+    own.sort_by(|a, b| a.0.cmp(&b.0));
+    own
 }
 
 
@@ -369,6 +605,112 @@ mod test {
         assert_eq!(1, typeahead.len());
     }
 
+    #[test]
+    fn ambiguous_sequence_is_ambiguous() {
+        let mut mode_map = ModeMap::<u8, TestOp>::new();
+        assert_eq!(
+            InsertionResult::Create,
+            mode_map.insert_op(vec![1u8, 2u8], TestOp::ThingOne)
+        );
+
+        let mut typeahead = Typeahead::<u8>::new();
+        typeahead.push_back(1u8, RemapType::Remap);
+        assert_eq!(Err(MapErr::NoMatch), mode_map.process(&mut typeahead));
+        assert!(mode_map.is_ambiguous(&typeahead));
+
+        typeahead.push_back(3u8, RemapType::Remap);
+        assert!(!mode_map.is_ambiguous(&typeahead));
+    }
+
+    #[test]
+    fn resolve_shortest_match() {
+        let mut mode_map = ModeMap::<u8, TestOp>::new();
+        mode_map.insert_remap(vec![1u8, 1u8, 1u8], vec![2u8]);
+        mode_map.insert_remap(vec![1u8, 1u8], vec![3u8]);
+        mode_map.insert_op(vec![1u8], TestOp::ThingOne);
+        mode_map.insert_op(vec![3u8], TestOp::ThingTwo);
+
+        let mut typeahead = Typeahead::<u8>::new();
+        typeahead.push_back(1u8, RemapType::Remap);
+        typeahead.push_back(1u8, RemapType::Remap);
+        assert_eq!(Err(MapErr::NoMatch), mode_map.process(&mut typeahead));
+        assert!(mode_map.awaits_remap(&typeahead));
+        assert_eq!(Ok(TestOp::ThingOne), mode_map.resolve(&mut typeahead));
+        assert_eq!(Ok(TestOp::ThingOne), mode_map.resolve(&mut typeahead));
+        assert!(typeahead.is_empty());
+    }
+
+    #[test]
+    fn resolve_literal_keys() {
+        let mut mode_map = ModeMap::<u8, TestOp>::new();
+        mode_map.insert_remap(vec![1u8, 1u8], vec![2u8]);
+
+        let mut typeahead = Typeahead::<u8>::new();
+        typeahead.push_back(1u8, RemapType::Remap);
+        assert_eq!(Err(MapErr::NoMatch), mode_map.resolve(&mut typeahead));
+        assert_eq!(1, typeahead.len());
+        assert!(mode_map.waits(&typeahead, false));
+        assert!(!mode_map.waits(&typeahead, true));
+
+        // Op keys are still waited for.
+        mode_map.insert_op(vec![1u8, 2u8], TestOp::ThingOne);
+        assert!(mode_map.waits(&typeahead, true));
+    }
+
+    #[test]
+    fn continuations_of_prefix() {
+        let mut mode_map = ModeMap::<u8, TestOp>::new();
+        mode_map.insert_op(vec![1u8, 2u8], TestOp::ThingOne);
+        mode_map.insert_op(vec![1u8, 3u8], TestOp::ThingTwo);
+        mode_map.insert_remap(vec![1u8, 3u8], vec![4u8]);
+        mode_map.insert_remap(vec![1u8, 4u8, 5u8], vec![2u8]);
+        mode_map.insert_op(vec![2u8], TestOp::ThingTwo);
+
+        assert_eq!(
+            vec![
+                Continuation {
+                    keys: vec![2u8],
+                    target: Target::Op(TestOp::ThingOne),
+                },
+                Continuation {
+                    keys: vec![3u8],
+                    target: Target::Remap(vec![4u8]),
+                },
+                Continuation {
+                    keys: vec![4u8, 5u8],
+                    target: Target::Remap(vec![2u8]),
+                },
+            ],
+            mode_map.continuations(&vec![1u8])
+        );
+    }
+
+    #[test]
+    fn layered_over_base() {
+        let mut base = ModeMap::<u8, TestOp>::new();
+        base.insert_op(vec![1u8], TestOp::ThingOne);
+        base.insert_op(vec![2u8, 2u8], TestOp::ThingTwo);
+        let base = Arc::new(base);
+        let mut mode_map = ModeMap::layered(base.clone());
+        mode_map.insert_op(vec![1u8], TestOp::ThingTwo);
+        mode_map.insert_op(vec![2u8], TestOp::ThingOne);
+
+        // Own mappings override those of the base.
+        let mut typeahead = Typeahead::<u8>::new();
+        typeahead.push_back(1u8, RemapType::Remap);
+        assert_eq!(Ok(TestOp::ThingTwo), mode_map.process(&mut typeahead));
+        typeahead.push_back(1u8, RemapType::Remap);
+        assert_eq!(Ok(TestOp::ThingOne), base.process(&mut typeahead));
+
+        // Longer keys of the base are still waited for.
+        typeahead.push_back(2u8, RemapType::Remap);
+        assert!(mode_map.is_ambiguous(&typeahead));
+        assert_eq!(1, mode_map.continuations(&vec![2u8]).len());
+        typeahead.push_back(2u8, RemapType::Remap);
+        assert_eq!(Ok(TestOp::ThingTwo), mode_map.process(&mut typeahead));
+        assert_eq!(0, typeahead.len());
+    }
+
     #[test]
     fn process_disambiguated_remap() {
         let mut mode_map = ModeMap::<u8, TestOp>::new();
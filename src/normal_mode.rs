@@ -1,10 +1,51 @@
 use disambiguation_map::Match;
+use effect::Effect;
+use event::Event;
+use message::Message;
 use mode::*;
+use mode_info::{BaseMode, ModeInfo, SubMode};
 use mode_map::MapErr;
-use op::NormalOp;
-use state::State;
+use op::{MotionOp, NormalOp, OperatorOp, Span};
+use pending_mode::operate;
+use state::{Change, State};
 use typeahead::Parse;
 
+/// Returns true if `c` names a register, see `:help registers`.
+fn is_register(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "\"-*+_/.:%#=".contains(c)
+}
+
+/// Repeat the last change, as with `.`. A count replaces the original one.
+fn repeat<K>(state: &mut State<K>)
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    let text = state.last_insert.clone().unwrap_or_default();
+    match state.last_change {
+        Some(Change::Operator(operator, span, count)) => {
+            // Without a count of its own, `.` keeps the original one.
+            if state.count == 1 {
+                state.count = count;
+            }
+            operate(state, operator, span);
+            if operator == OperatorOp::Change {
+                state.emit_text(&text);
+            }
+        }
+        Some(Change::Insert) => {
+            for _ in 0..state.count {
+                state.emit_text(&text);
+            }
+            state.finish_command();
+        }
+        None => {
+            state.finish_command();
+        }
+    }
+}
+
 impl<K> Transition<K> for NormalMode<K>
 where
     K: Ord,
@@ -15,8 +56,54 @@ where
         "Normal"
     }
 
+    fn info(&self, state: &State<K>) -> ModeInfo {
+        let submode = match self.next_mode {
+            NextMode::Normal => SubMode::None,
+            NextMode::Insert => SubMode::InsertNormal,
+            NextMode::Replace => SubMode::ReplaceNormal,
+        };
+        ModeInfo::new(BaseMode::Normal, submode, state.recording)
+    }
+
     fn transition(&self, state: &mut State<K>) -> Mode<K> {
-        match state.normal_mode_map.process(&mut state.typeahead) {
+        if let Some(op) = self.char_op {
+            // The next key is the argument to `op`, not a command.
+            let arg = match state.typeahead.pop_front() {
+                Some((key, _)) => key.character(),
+                None => {
+                    return recast_normal(self);
+                }
+            };
+            match (op, arg) {
+                (NormalOp::Record, Some(register)) => {
+                    state.start_recording(register);
+                }
+                (NormalOp::Execute, Some(register)) => {
+                    // TODO Nested macros, which may recurse.
+                    if state.macro_rest.is_none() {
+                        state.run_macro(register);
+                    }
+                    state.finish_command();
+                }
+                (NormalOp::Register, Some(register))
+                    if is_register(register) => {
+                    // The command itself is still to come.
+                    state.register = Some(register);
+                    return insert_normal(self.next_mode);
+                }
+                _ => {
+                    // Invalid argument, e.g. `q<Esc>`; abandon the command.
+                    state.finish_command();
+                }
+            };
+            return next(self.next_mode);
+        }
+        let result = if state.timed_out {
+            state.normal_mode_map.resolve(&mut state.typeahead)
+        } else {
+            state.normal_mode_map.process(&mut state.typeahead)
+        };
+        match result {
             Err(MapErr::NoMatch) => {
                 // In vim, if one remaps a numeric, e.g.
                 //   nnoremap 123 iasdf<Esc>
@@ -34,21 +121,47 @@ where
                         return recast_normal(self);
                     }
                     Match::NoMatch => {
+                        let map = &state.normal_mode_map;
+                        if map.waits(&state.typeahead, state.timed_out) {
+                            // Wait for more keys.
+                            return recast_normal(self);
+                        }
                         // In Normal mode, unmatched typeahead gets dropped.
                         state.typeahead.clear();
                     }
                 };
-            } 
+            }
             Err(MapErr::InfiniteRecursion) => {
-                // TODO Tell the user they've created an infinite remap loop.
+                state.emit(Effect::Message(Message::recursive_mapping()));
+                state.notify(Event::MappingRecursionDetected);
                 state.typeahead.clear();
-            } 
+            }
             Ok(op) => {
                 match op {
                     NormalOp::Cancel => {
                         state.cancel();
                     }
+                    NormalOp::Register => {
+                        return char_pending(self, op);
+                    }
+                    NormalOp::Record => {
+                        if !state.stop_recording() {
+                            return char_pending(self, op);
+                        }
+                    }
+                    NormalOp::Execute => {
+                        return char_pending(self, op);
+                    }
+                    NormalOp::Undo => {
+                        state.emit_counted(Effect::Undo);
+                    }
+                    NormalOp::Redo => {
+                        state.emit_counted(Effect::Redo);
+                    }
                     NormalOp::Insert => {
+                        // TODO Repeat the insertion [count] times.
+                        state.last_change = Some(Change::Insert);
+                        state.finish_command();
                         return insert();
                     }
                     NormalOp::ReplaceChar => {
@@ -58,20 +171,34 @@ where
                         return replace();
                     }
                     NormalOp::Repeat => {
-                        // TODO
+                        repeat(state);
                     }
                     NormalOp::Operator(o) => {
-                        // TODO
                         // Enter operator pending mode.
-                        return pending(NextMode::Normal);
+                        let next_mode = match o {
+                            OperatorOp::Change => NextMode::Insert,
+                            _ => self.next_mode,
+                        };
+                        state.notify(Event::OperatorStarted(o));
+                        return pending(o, next_mode);
+                    }
+                    NormalOp::Motion(MotionOp::Custom(id)) => {
+                        let span = Span::Motion(MotionOp::Custom(id));
+                        state.emit(Effect::Operate(None, span, state.count));
+                        state.finish_command();
                     }
                     NormalOp::Motion(m) => {
-                        // TODO
+                        state.emit_counted(Effect::Move(m));
+                    }
+                    NormalOp::Command(id) => {
+                        let (count, register) = (state.count, state.register);
+                        state.emit(Effect::Command(id, count, register));
+                        state.finish_command();
                     }
                 }
             }
         };
-        // Stay in normal mode.
-        normal()
+        // Stay in normal mode, unless this was a single command from Insert.
+        next(self.next_mode)
     }
 }
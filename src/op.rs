@@ -19,7 +19,11 @@ pub trait HasObject<K> {
     fn insert_object(&mut self, key: Vec<K>, op: ObjectOp) -> InsertionResult;
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+pub trait HasCommand<K> {
+    fn insert_command(&mut self, key: Vec<K>, id: CommandId) -> InsertionResult;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum OperatorOp {
     Change, // c
     Delete, // d
@@ -35,9 +39,10 @@ pub enum OperatorOp {
     ShiftLeft, // <
     DefineFold, // zf
     OperatorFunc, // g@
+    Custom(OperatorId), // Defined by the embedder.
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum MotionOp {
     Left, // l
     Right, // ;
@@ -46,14 +51,16 @@ pub enum MotionOp {
     Top, // gg
     Bottom, // G
     Word, // w
+    WordBackward, // b
     PageUp,
     PageDown,
     Backspace,
     Home,
     End,
+    Custom(MotionId), // Defined by the embedder.
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ObjectOp {
     AWord,
     InnerWord,
@@ -79,33 +86,79 @@ pub enum ObjectOp {
     InnerDoubleQuote,
     ABackTick, // A `...` block.
     InnerBackTick,
+    Custom(ObjectId), // Defined by the embedder.
+}
+
+/// What an operator applies to.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Span {
+    Motion(MotionOp), // From the cursor to where the motion goes.
+    Object(ObjectOp), // The text object around the cursor.
+}
+
+impl Span {
+    /// Returns true if the span needs an extension to be resolved.
+    pub fn is_custom(&self) -> bool {
+        match *self {
+            Span::Motion(MotionOp::Custom(_)) |
+            Span::Object(ObjectOp::Custom(_)) => true,
+            _ => false,
+        }
+    }
 }
 
+/// Identifies a command defined by the embedder, see `command::Commands`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash,
+         Serialize, Deserialize)]
+pub struct CommandId(pub u32);
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+/// Identifies a motion defined by the embedder, see `extension::Extensions`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash,
+         Serialize, Deserialize)]
+pub struct MotionId(pub u32);
+
+/// Identifies a text object defined by the embedder.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash,
+         Serialize, Deserialize)]
+pub struct ObjectId(pub u32);
+
+/// Identifies an operator defined by the embedder.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash,
+         Serialize, Deserialize)]
+pub struct OperatorId(pub u32);
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum NormalOp {
     Cancel, // Drop back to normal (Esc).
     Insert, // Transitions to Insert (i).
     ReplaceChar, // Replace a single character.
     ReplaceMode, // Transitions to Replace (r).
-    Repeat, // Repeats the last change (.). TODO redo-register
+    Repeat, // Repeats the last change (.).
+    Record, // Records typed keys into a register (q).
+    Execute, // Runs the keys recorded in a register (@).
+    Register, // Names the register used by the next op (").
+    Undo, // Undoes the last change (u).
+    Redo, // Redoes the last change undone (Ctrl-R).
     Operator(OperatorOp),
     Motion(MotionOp), // Moves cursor. Transitions back to Normal.
+    Command(CommandId), // Runs a user-defined command.
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum PendingOp {
     Cancel, // Drop back to normal (Esc).
     Operator(OperatorOp),
     Motion(MotionOp), // Cursor motions.
     Object(ObjectOp), // Text-objects.
+    Command(CommandId), // Runs a user-defined command instead.
 }
 
 // :help ins-special-keys
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum InsertOp {
     Cancel, // Drop back to normal (Esc).
     Quit, // Go back to normal without abbreviations (Ctrl-C).
+    InsertNormal, // Execute one Normal mode command (Ctrl-O).
     Up,
     Down,
     Left,
@@ -122,4 +175,150 @@ pub enum InsertOp {
     Digraph, // (Ctrl-K).
     InsertRegister, // (Ctrl-R).
     InsertRegisterContents, // (Ctrl-R Ctrl-R).
+    BreakUndo, // Start a new undoable change (Ctrl-G u).
+    Command(CommandId), // Runs a user-defined command.
+}
+
+/// Short human-readable descriptions of ops, e.g. for which-key popups.
+pub trait Describe {
+    fn describe(&self) -> &'static str;
+}
+
+impl Describe for OperatorOp {
+    fn describe(&self) -> &'static str {
+        use self::OperatorOp::*;
+        match *self {
+            Change => "change",
+            Delete => "delete",
+            Yank => "yank into register",
+            SwapCase => "swap case",
+            ToLower => "make lowercase",
+            ToUpper => "make uppercase",
+            ExternalPrg => "filter through an external program",
+            EqualPrg => "filter through 'equalprg'",
+            TextFormat => "format text",
+            Rot13 => "ROT13 encode",
+            ShiftRight => "shift right",
+            ShiftLeft => "shift left",
+            DefineFold => "define a fold",
+            OperatorFunc => "call 'operatorfunc'",
+            Custom(_) => "user operator",
+        }
+    }
+}
+
+impl Describe for MotionOp {
+    fn describe(&self) -> &'static str {
+        use self::MotionOp::*;
+        match *self {
+            Left => "left",
+            Right => "right",
+            Up => "up",
+            Down => "down",
+            Top => "first line",
+            Bottom => "last line",
+            Word => "next word",
+            WordBackward => "previous word",
+            PageUp => "page up",
+            PageDown => "page down",
+            Backspace => "left, across lines",
+            Home => "start of line",
+            End => "end of line",
+            Custom(_) => "user motion",
+        }
+    }
+}
+
+impl Describe for ObjectOp {
+    fn describe(&self) -> &'static str {
+        use self::ObjectOp::*;
+        match *self {
+            AWord => "a word",
+            InnerWord => "inner word",
+            AWORD => "a WORD",
+            InnerWORD => "inner WORD",
+            ASentence => "a sentence",
+            InnerSentence => "inner sentence",
+            AParagraph => "a paragraph",
+            InnerParagraph => "inner paragraph",
+            ASquareBlock => "a [] block",
+            InnerSquareBlock => "inner [] block",
+            AParen => "a () block",
+            InnerParen => "inner () block",
+            AAngle => "a <> block",
+            InnerAngle => "inner <> block",
+            ATag => "a tag block",
+            InnerTag => "inner tag block",
+            ABrace => "a {} block",
+            InnerBrace => "inner {} block",
+            ASingleQuote => "a single-quoted string",
+            InnerSingleQuote => "inner single-quoted string",
+            ADoubleQuote => "a double-quoted string",
+            InnerDoubleQuote => "inner double-quoted string",
+            ABackTick => "a backtick string",
+            InnerBackTick => "inner backtick string",
+            Custom(_) => "user text object",
+        }
+    }
+}
+
+impl Describe for NormalOp {
+    fn describe(&self) -> &'static str {
+        match *self {
+            NormalOp::Cancel => "cancel",
+            NormalOp::Insert => "insert",
+            NormalOp::ReplaceChar => "replace a character",
+            NormalOp::ReplaceMode => "replace mode",
+            NormalOp::Repeat => "repeat last change",
+            NormalOp::Record => "record into register",
+            NormalOp::Execute => "execute register",
+            NormalOp::Register => "use register",
+            NormalOp::Undo => "undo",
+            NormalOp::Redo => "redo",
+            NormalOp::Operator(o) => o.describe(),
+            NormalOp::Motion(m) => m.describe(),
+            NormalOp::Command(_) => "user command",
+        }
+    }
+}
+
+impl Describe for PendingOp {
+    fn describe(&self) -> &'static str {
+        match *self {
+            PendingOp::Cancel => "cancel",
+            PendingOp::Operator(o) => o.describe(),
+            PendingOp::Motion(m) => m.describe(),
+            PendingOp::Object(o) => o.describe(),
+            PendingOp::Command(_) => "user command",
+        }
+    }
+}
+
+impl Describe for InsertOp {
+    fn describe(&self) -> &'static str {
+        use self::InsertOp::*;
+        match *self {
+            Cancel => "back to normal",
+            Quit => "back to normal, no abbreviations",
+            InsertNormal => "execute one normal command",
+            Up => "up",
+            Down => "down",
+            Left => "left",
+            Right => "right",
+            PageUp => "page up",
+            PageDown => "page down",
+            Backspace => "delete previous character",
+            Home => "start of line",
+            End => "end of line",
+            Delete => "delete next character",
+            DeleteWord => "delete previous word",
+            DeleteLine => "delete to start of line",
+            Tab => "insert a tab",
+            Digraph => "insert a digraph",
+            InsertRegister => "insert register",
+            InsertRegisterContents => "insert register literally",
+            BreakUndo => "start a new undoable change",
+            Command(_) => "user command",
+        }
+    }
 }
@@ -0,0 +1,136 @@
+use std::fmt;
+
+/// The value of an option, as given to `:set`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OptionValue {
+    Bool(bool), // A toggle, e.g. `:set expandtab`.
+    Number(u64), // e.g. `:set tabstop=8`.
+}
+
+impl fmt::Display for OptionValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OptionValue::Bool(value) => write!(f, "{}", value),
+            OptionValue::Number(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum OptionError {
+    Unknown(String), // E518: No option has this name.
+    InvalidValue(String, OptionValue), // E474: The value has the wrong type.
+}
+
+/// Vim options understood by vixi, with Vim's defaults unless noted.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Options {
+    pub tabstop: u64, // 'tabstop', 'ts': Columns a tab counts for.
+    pub expandtab: bool, // 'expandtab', 'et': Insert spaces for a tab.
+    pub timeout: bool, // 'timeout', 'to': Mappings time out.
+    pub timeoutlen: u64, // 'timeoutlen', 'tm': Milliseconds to wait.
+    pub ttimeoutlen: u64, // 'ttimeoutlen', 'ttm': The same, after `<Esc>`.
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            tabstop: 4, // As with xi-core, rather than Vim's 8.
+            expandtab: false,
+            timeout: true,
+            timeoutlen: 1000,
+            ttimeoutlen: 100, // As with Vim's defaults.vim.
+        }
+    }
+}
+
+impl Options {
+    /// Set an option by its full or short name.
+    pub fn set(
+        &mut self,
+        name: &str,
+        value: OptionValue,
+    ) -> Result<(), OptionError> {
+        let invalid = || OptionError::InvalidValue(String::from(name), value);
+        match (full_name(name), value) {
+            (Some("tabstop"), OptionValue::Number(n)) if n > 0 => {
+                self.tabstop = n;
+            }
+            (Some("expandtab"), OptionValue::Bool(b)) => {
+                self.expandtab = b;
+            }
+            (Some("timeout"), OptionValue::Bool(b)) => {
+                self.timeout = b;
+            }
+            (Some("timeoutlen"), OptionValue::Number(n)) => {
+                self.timeoutlen = n;
+            }
+            (Some("ttimeoutlen"), OptionValue::Number(n)) => {
+                self.ttimeoutlen = n;
+            }
+            (Some(_), _) => {
+                return Err(invalid());
+            }
+            (None, _) => {
+                return Err(OptionError::Unknown(String::from(name)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Get an option by its full or short name.
+    pub fn get(&self, name: &str) -> Option<OptionValue> {
+        match full_name(name)? {
+            "tabstop" => Some(OptionValue::Number(self.tabstop)),
+            "expandtab" => Some(OptionValue::Bool(self.expandtab)),
+            "timeout" => Some(OptionValue::Bool(self.timeout)),
+            "timeoutlen" => Some(OptionValue::Number(self.timeoutlen)),
+            "ttimeoutlen" => Some(OptionValue::Number(self.ttimeoutlen)),
+            _ => None,
+        }
+    }
+}
+
+fn full_name(name: &str) -> Option<&'static str> {
+    match name {
+        "tabstop" | "ts" => Some("tabstop"),
+        "expandtab" | "et" => Some("expandtab"),
+        "timeout" | "to" => Some("timeout"),
+        "timeoutlen" | "tm" => Some("timeoutlen"),
+        "ttimeoutlen" | "ttm" => Some("ttimeoutlen"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn set_by_short_name() {
+        let mut options = Options::default();
+        assert_eq!(Ok(()), options.set("ts", OptionValue::Number(8)));
+        assert_eq!(8, options.tabstop);
+        assert_eq!(Some(OptionValue::Number(8)), options.get("tabstop"));
+    }
+
+    #[test]
+    fn invalid_values() {
+        let mut options = Options::default();
+        assert_eq!(
+            Err(OptionError::InvalidValue(
+                String::from("et"),
+                OptionValue::Number(1),
+            )),
+            options.set("et", OptionValue::Number(1))
+        );
+        assert!(options.set("tabstop", OptionValue::Number(0)).is_err());
+        assert_eq!(
+            Err(OptionError::Unknown(String::from("nosuch"))),
+            options.set("nosuch", OptionValue::Bool(true))
+        );
+        assert_eq!(Options::default(), options);
+    }
+}
@@ -1,9 +1,13 @@
 use mode::*;
 use mode_map::MapErr;
-use op::PendingOp;
-use state::State;
+use op::{MotionOp, OperatorOp, PendingOp, Span};
+use mode_info::{BaseMode, ModeInfo, SubMode};
+use state::{Change, State};
 use typeahead::Parse;
 use disambiguation_map::Match;
+use effect::Effect;
+use event::Event;
+use message::Message;
 
 impl<K> PendingMode<K>
 where
@@ -12,11 +16,88 @@ where
     K: Parse,
 {
     fn next_mode(&self) -> Mode<K> {
-        match self.next_mode {
-            NextMode::Insert => insert(),
-            NextMode::Normal => normal(),
+        next(self.next_mode)
+    }
+}
+
+/// Apply `operator` over `span`, whether typed or repeated with `.`.
+pub fn operate<K>(state: &mut State<K>, operator: OperatorOp, span: Span)
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    let motion = match span {
+        // `dj` and `dk` take whole lines, as far as the buffer has them,
+        // which the client can't select, so they're resolved against the
+        // text instead.
+        Span::Motion(MotionOp::Up) |
+        Span::Motion(MotionOp::Down) if operator != OperatorOp::Yank => {
+            operate_custom(state, operator, span);
+            return;
+        }
+        Span::Motion(motion) if !span.is_custom() => motion,
+        _ => {
+            operate_custom(state, operator, span);
+            return;
+        }
+    };
+    let yanked = Event::TextYanked(
+        state.register.unwrap_or('"'),
+        operator,
+        motion,
+    );
+    match operator {
+        OperatorOp::Change | OperatorOp::Delete => {
+            state.last_change =
+                Some(Change::Operator(operator, span, state.count));
+            state.emit_counted(Effect::Delete(motion));
+            state.notify(yanked);
+            // The deletion ends up wherever core leaves the cursor.
+            state.changing = true;
+        }
+        OperatorOp::Yank => {
+            // TODO Store the yanked text once buffer contents are known.
+            // TODO Mark the end of the motion, rather than its start.
+            state.finish_command();
+            state.notify(yanked);
+            if let Some(cursor) = state.cursor {
+                state.mark_change(cursor, cursor);
+            }
+        }
+        OperatorOp::Custom(_) => operate_custom(state, operator, span),
+        _ => {
+            // TODO Remaining operators.
+            state.finish_command();
+        }
+    }
+}
+
+/// Apply an operator over a span which needs text the machine doesn't
+/// have, or a user operator, by leaving it to `Vixi`.
+fn operate_custom<K>(state: &mut State<K>, operator: OperatorOp, span: Span)
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    match (operator, span) {
+        (_, Span::Object(_)) if !span.is_custom() => {
+            // TODO Built-in text objects.
+        }
+        (OperatorOp::Change, _) |
+        (OperatorOp::Delete, _) |
+        (OperatorOp::Custom(_), _) => {
+            let count = state.count;
+            state.last_change = Some(Change::Operator(operator, span, count));
+            state.emit(Effect::Operate(Some(operator), span, count));
+            state.changing = true;
+        }
+        _ => {
+            // TODO Remaining operators.
         }
     }
+    state.finish_command();
 }
 
 impl<K> Transition<K> for PendingMode<K>
@@ -29,8 +110,21 @@ where
         "Pending"
     }
 
+    fn info(&self, state: &State<K>) -> ModeInfo {
+        ModeInfo::new(
+            BaseMode::Normal,
+            SubMode::OperatorPending(self.operator),
+            state.recording,
+        )
+    }
+
     fn transition(&self, state: &mut State<K>) -> Mode<K> {
-        match state.pending_mode_map.process(&mut state.typeahead) {
+        let result = if state.timed_out {
+            state.pending_mode_map.resolve(&mut state.typeahead)
+        } else {
+            state.pending_mode_map.process(&mut state.typeahead)
+        };
+        match result {
             Err(MapErr::NoMatch) => {
                 // In vim, if one remaps a numeric, e.g.
                 //   nnoremap 123 iasdf<Esc>
@@ -48,32 +142,48 @@ where
                         return recast_pending(self);
                     }
                     Match::NoMatch => {
+                        let map = &state.pending_mode_map;
+                        if map.waits(&state.typeahead, state.timed_out) {
+                            // Wait for more keys.
+                            return recast_pending(self);
+                        }
                         // In Pending mode, unmatched typeahead gets dropped.
                         state.typeahead.clear();
                     }
                 };
-            } 
+            }
             Err(MapErr::InfiniteRecursion) => {
-                // TODO Tell the user they've created an infinite remap loop.
+                state.emit(Effect::Message(Message::recursive_mapping()));
+                state.notify(Event::MappingRecursionDetected);
                 state.typeahead.clear();
-            } 
+            }
             Ok(op) => {
                 match op {
                     PendingOp::Cancel => {
-                        // TODO drop back to normal mode; clear count.
+                        // Drop back to normal mode; clear count.
+                        state.cancel();
+                        return normal();
                     }
                     PendingOp::Operator(o) => {
-                        // TODO Perform operation over [motion].
+                        // TODO Perform linewise operation, e.g. `dd`.
+                        state.finish_command();
                         return self.next_mode();
                     }
                     PendingOp::Motion(m) => {
-                        // TODO Perform operation over [motion].
+                        operate(state, self.operator, Span::Motion(m));
                         return self.next_mode();
                     }
                     PendingOp::Object(o) => {
-                        // TODO Perform operation over [object].
+                        operate(state, self.operator, Span::Object(o));
                         return self.next_mode();
                     }
+                    PendingOp::Command(id) => {
+                        // The command runs instead of the operator.
+                        let (count, register) = (state.count, state.register);
+                        state.emit(Effect::Command(id, count, register));
+                        state.finish_command();
+                        return normal();
+                    }
                 }
             }
         };
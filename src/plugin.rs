@@ -0,0 +1,689 @@
+use client::{Client, ClientResult};
+use effect::Effect;
+use futures::Future;
+use json_client::JsonClient;
+use key::MultiKey;
+use keymaps::Keymaps;
+use memory_client::MemoryClient;
+use serde_json;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use view::{Position, ViewId};
+use vixi::Vixi;
+
+/// The name vixi runs under as an xi-core plugin, for `Client::start_plugin`
+/// and `Client::notify_plugin`. Also the author of its edits.
+pub const PLUGIN_NAME: &str = "vixi";
+
+/// Key of the status item showing the mode of a view.
+const MODE_STATUS_ITEM: &str = "vixi-mode";
+
+/// Key of the status item showing the last message, e.g. an error.
+const MESSAGE_STATUS_ITEM: &str = "vixi-message";
+
+/// The most text asked of core at once, more than any buffer vixi edits.
+const MAX_FETCH: u64 = 1 << 32;
+
+/// Priority of vixi's edits, which core uses to order edits it receives
+/// at the same revision.
+const EDIT_PRIORITY: u64 = 0x1000_0000;
+
+/// Runs vixi inside xi-core as a plugin, speaking xi's plugin protocol with
+/// one JSON-RPC message per line.
+///
+/// Frontends forward keys, in Vim's key notation, as a `keys` notification
+/// with params `{"keys": keys}` through `Client::notify_plugin`, which core
+/// delivers as a `custom_command`. Each view has its own `Vixi`, whose mode
+/// and messages are shown in status items.
+///
+/// Each view's text is fetched with `get_data`, then kept up to date from
+/// the deltas in core's `update`s. Vixi edits a copy of it, and what each
+/// `keys` changes is sent back as one `edit` against the tracked revision.
+/// Keys wait while the text is being fetched, and until core reports the
+/// revision made by vixi's last edit.
+///
+/// This is experimental: xi's plugin API can neither read nor move the
+/// cursor. Frontends should send it along with the keys, as `"cursor":
+/// {"line": line, "column": column}`, and vixi's own moves aren't shown.
+pub struct Plugin {
+    plugin_id: Value, // As given by core in `initialize`.
+    keymaps: Keymaps<MultiKey>, // Shared by the views.
+    views: BTreeMap<ViewId, PluginView>,
+    client: JsonClient,
+    requests: Vec<Value>, // Requests to core, to go after notifications.
+    fetches: BTreeMap<u64, (ViewId, u64)>, // View and rev, by request id.
+    next_id: u64, // Of the next request to core.
+}
+
+/// How a view's copy of its text stands against core's.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Sync {
+    Fetching, // Asked for with `get_data`.
+    Synced, // The same as at `rev`.
+    Edited, // Ahead of `rev` by vixi's last edit, until core reports it.
+}
+
+/// A view of a buffer in core, with the copy of its text vixi edits.
+struct PluginView {
+    vixi: Vixi,
+    text: MemoryClient, // Edited by `vixi`, as `ViewId(0)`.
+    buffer_id: Value, // Views of one buffer see the same updates.
+    rev: u64, // The buffer's revision, as last reported by core.
+    sync: Sync,
+    queued: Vec<(String, Option<Position>)>, // Keys, with the cursor.
+}
+
+impl Plugin {
+    pub fn new() -> Self {
+        Plugin {
+            plugin_id: Value::Null,
+            keymaps: Keymaps::default(),
+            views: BTreeMap::new(),
+            client: JsonClient::xi(),
+            requests: Vec::new(),
+            fetches: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Handle a line from core, returning the messages to send in order.
+    pub fn handle(&mut self, line: &str) -> Vec<Value> {
+        let message: Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(_) => {
+                return Vec::new(); // Core has nobody to report errors to.
+            }
+        };
+        let method = match message["method"].as_str() {
+            Some(method) => method,
+            None => {
+                // A response, to `get_data`.
+                if let Some(id) = message["id"].as_u64() {
+                    self.fetched(id, &message["result"]);
+                }
+                return self.drain();
+            }
+        };
+        let result = self.call(method, &message["params"]);
+        let mut messages = self.drain();
+        if let Some(id) = message.get("id") {
+            messages.push(json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": result,
+            }));
+        }
+        messages
+    }
+
+    /// The views being tracked, in order.
+    pub fn views(&self) -> Vec<ViewId> {
+        self.views.keys().cloned().collect()
+    }
+
+    fn call(&mut self, method: &str, params: &Value) -> Value {
+        match method {
+            "initialize" | "new_buffer" => {
+                if method == "initialize" {
+                    self.plugin_id = params["plugin_id"].clone();
+                }
+                let buffers = params["buffer_info"].as_array();
+                for buffer in buffers.into_iter().flat_map(|b| b.iter()) {
+                    let rev = buffer["rev"].as_u64().unwrap_or(0);
+                    let views = buffer["views"].as_array();
+                    for view in views.into_iter().flat_map(|v| v.iter()) {
+                        if let Some(view_id) = view_id(view) {
+                            self.add_view(view_id, &buffer["buffer_id"], rev);
+                        }
+                    }
+                }
+            }
+            "did_close" => {
+                if let Some(view_id) = view_id(&params["view_id"]) {
+                    self.views.remove(&view_id);
+                }
+            }
+            "custom_command" if params["method"] == "keys" => {
+                let keys = params["params"]["keys"].as_str();
+                let cursor = &params["params"]["cursor"];
+                let cursor = serde_json::from_value(cursor.clone()).ok();
+                if let (Some(view_id), Some(keys)) =
+                    (view_id(&params["view_id"]), keys)
+                {
+                    self.keys(view_id, keys, cursor);
+                }
+            }
+            "update" => {
+                self.update(params);
+                return json!(0); // Edits are sent separately.
+            }
+            _ => {}
+        }
+        Value::Null
+    }
+
+    fn add_view(&mut self, view_id: ViewId, buffer_id: &Value, rev: u64) {
+        if self.views.contains_key(&view_id) {
+            return;
+        }
+        let text = MemoryClient::new();
+        let mut vixi = Vixi::with_keymaps(self.keymaps.clone());
+        vixi.set_client(Box::new(text.clone()));
+        vixi.set_text_query(Box::new(text.clone()));
+        let showmode = vixi.mode_info().showmode;
+        self.views.insert(
+            view_id,
+            PluginView {
+                vixi: vixi,
+                text: text,
+                buffer_id: buffer_id.clone(),
+                rev: rev,
+                sync: Sync::Fetching,
+                queued: Vec::new(),
+            },
+        );
+        for &(key, value) in
+            &[(MODE_STATUS_ITEM, &*showmode), (MESSAGE_STATUS_ITEM, "")]
+        {
+            self.notify(
+                "add_status_item",
+                view_id,
+                json!({"key": key, "value": value, "alignment": "left"}),
+            );
+        }
+        self.fetch(view_id);
+    }
+
+    /// Ask core for the text of `view_id` at its current revision.
+    fn fetch(&mut self, view_id: ViewId) {
+        let rev = match self.views.get_mut(&view_id) {
+            Some(view) => {
+                view.sync = Sync::Fetching;
+                view.rev
+            }
+            None => {
+                return;
+            }
+        };
+        let id = self.next_id;
+        self.next_id += 1;
+        self.fetches.insert(id, (view_id, rev));
+        self.requests.push(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "get_data",
+            "params": {
+                "plugin_id": self.plugin_id.clone(),
+                "view_id": xi_view_id(view_id),
+                "start": 0,
+                "unit": "utf8",
+                "max_size": MAX_FETCH,
+                "rev": rev,
+            },
+        }));
+    }
+
+    /// Take the text core answered the `get_data` request `id` with, unless
+    /// the buffer has changed since.
+    fn fetched(&mut self, id: u64, result: &Value) {
+        let (view_id, rev) = match self.fetches.remove(&id) {
+            Some(fetch) => fetch,
+            None => {
+                return;
+            }
+        };
+        match (self.views.get_mut(&view_id), result.as_str()) {
+            (Some(ref mut view), Some(text)) if view.rev == rev => {
+                view.text.set_text(ViewId(0), text);
+                view.sync = Sync::Synced;
+            }
+            _ => {
+                return;
+            }
+        }
+        self.run_queued(view_id);
+    }
+
+    /// Follow core's `update` of a buffer, through one of its views.
+    fn update(&mut self, params: &Value) {
+        let buffer_id = match view_id(&params["view_id"])
+            .and_then(|view_id| self.views.get(&view_id))
+        {
+            Some(view) => view.buffer_id.clone(),
+            None => {
+                return;
+            }
+        };
+        let rev = match params["rev"].as_u64() {
+            Some(rev) => rev,
+            None => {
+                return;
+            }
+        };
+        let own = params["author"] == PLUGIN_NAME;
+        let view_ids: Vec<ViewId> = self.views
+            .iter()
+            .filter(|&(_, view)| view.buffer_id == buffer_id)
+            .map(|(&view_id, _)| view_id)
+            .collect();
+        for view_id in view_ids {
+            let synced = match self.views.get_mut(&view_id) {
+                Some(view) => {
+                    view.rev = rev;
+                    view.follow(own, &params["delta"])
+                }
+                None => false,
+            };
+            if synced {
+                self.run_queued(view_id);
+            } else {
+                self.fetch(view_id);
+            }
+        }
+    }
+
+    fn keys(&mut self, view_id: ViewId, keys: &str, cursor: Option<Position>) {
+        match self.views.get_mut(&view_id) {
+            Some(view) => view.queued.push((String::from(keys), cursor)),
+            None => {
+                return; // Core hasn't told us of the view.
+            }
+        }
+        self.run_queued(view_id);
+    }
+
+    /// Process the keys waiting for `view_id`, while its text is in sync.
+    fn run_queued(&mut self, view_id: ViewId) {
+        loop {
+            let (result, old, new, rev) = match self.views.get_mut(&view_id) {
+                Some(view) => {
+                    if view.sync != Sync::Synced || view.queued.is_empty() {
+                        return;
+                    }
+                    let (keys, cursor) = view.queued.remove(0);
+                    if let Some(cursor) = cursor {
+                        let (line, column) = (cursor.line, cursor.column);
+                        wait(view.text.click(ViewId(0), line, column));
+                    }
+                    let old = view.text.text(ViewId(0)).unwrap_or_default();
+                    let result = view.vixi.process(&keys);
+                    let new = view.text.text(ViewId(0)).unwrap_or_default();
+                    if new != old {
+                        view.sync = Sync::Edited;
+                    }
+                    (result, old, new, view.rev)
+                }
+                None => {
+                    return;
+                }
+            };
+            match result {
+                Ok(effects) => self.show(view_id, &effects),
+                Err(err) => {
+                    self.status(view_id, MESSAGE_STATUS_ITEM, err.to_string())
+                }
+            }
+            if new != old {
+                let edit = json!({
+                    "rev": rev,
+                    "delta": delta(&old, &new),
+                    "priority": EDIT_PRIORITY,
+                    "after_cursor": false,
+                    "author": PLUGIN_NAME,
+                });
+                self.notify("edit", view_id, json!({"edit": edit}));
+            }
+        }
+    }
+
+    /// Show the modes and messages among `effects` in status items.
+    fn show(&mut self, view_id: ViewId, effects: &[Effect]) {
+        for effect in effects {
+            match *effect {
+                Effect::ModeChange(ref info) => {
+                    let showmode = info.showmode.clone();
+                    self.status(view_id, MODE_STATUS_ITEM, showmode);
+                }
+                Effect::Message(ref message) => {
+                    let message = message.to_string();
+                    self.status(view_id, MESSAGE_STATUS_ITEM, message);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn status(&mut self, view_id: ViewId, key: &str, value: String) {
+        self.notify(
+            "update_status_item",
+            view_id,
+            json!({"key": key, "value": value}),
+        );
+    }
+
+    /// Send a notification about `view_id` to core, which expects plugins
+    /// to name themselves in each.
+    fn notify(&mut self, method: &str, view_id: ViewId, mut params: Value) {
+        params["view_id"] = json!(xi_view_id(view_id));
+        params["plugin_id"] = self.plugin_id.clone();
+        wait(self.client.notify(method, params));
+    }
+
+    /// The messages for core, notifications first.
+    fn drain(&mut self) -> Vec<Value> {
+        let mut messages = self.client.drain();
+        messages.extend(self.requests.drain(..));
+        messages
+    }
+}
+
+impl PluginView {
+    /// Follow an update to `delta`, made by vixi if `own`, returning false
+    /// if the text must be fetched again.
+    fn follow(&mut self, own: bool, delta: &Value) -> bool {
+        match self.sync {
+            Sync::Synced => {
+                let text = self.text.text(ViewId(0)).unwrap_or_default();
+                match apply_delta(&text, delta) {
+                    Some(text) => {
+                        self.text.set_text(ViewId(0), &text);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            // Vixi's edit is already in the text.
+            Sync::Edited if own => {
+                self.sync = Sync::Synced;
+                true
+            }
+            Sync::Edited | Sync::Fetching => false,
+        }
+    }
+}
+
+/// Apply a delta from core, `{"base_len": n, "els": [...]}`, each element
+/// copying a byte range of `text` or inserting new text. Returns `None` if
+/// the delta doesn't fit `text`.
+fn apply_delta(text: &str, delta: &Value) -> Option<String> {
+    if delta["base_len"].as_u64()? != text.len() as u64 {
+        return None;
+    }
+    let mut result = String::new();
+    for element in delta["els"].as_array()? {
+        if let Some(inserted) = element["insert"].as_str() {
+            result.push_str(inserted);
+            continue;
+        }
+        let copy = element["copy"].as_array()?;
+        let start = copy.get(0)?.as_u64()? as usize;
+        let end = copy.get(1)?.as_u64()? as usize;
+        result.push_str(text.get(start..end)?);
+    }
+    Some(result)
+}
+
+/// The delta from `old` to `new`, replacing what lies between the start
+/// and end they have in common.
+fn delta(old: &str, new: &str) -> Value {
+    let prefix: usize = old.chars()
+        .zip(new.chars())
+        .take_while(|&(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    let suffix: usize = old[prefix..]
+        .chars()
+        .rev()
+        .zip(new[prefix..].chars().rev())
+        .take_while(|&(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    let mut elements = Vec::new();
+    if prefix > 0 {
+        elements.push(json!({"copy": [0, prefix]}));
+    }
+    let inserted = &new[prefix..new.len() - suffix];
+    if !inserted.is_empty() {
+        elements.push(json!({"insert": inserted}));
+    }
+    if suffix > 0 {
+        elements.push(json!({"copy": [old.len() - suffix, old.len()]}));
+    }
+    json!({"base_len": old.len(), "els": elements})
+}
+
+/// Formats a view id as core does.
+fn xi_view_id(view_id: ViewId) -> String {
+    format!("view-id-{}", view_id.0)
+}
+
+/// Parses a view id as sent by core, e.g. `"view-id-1"`.
+fn view_id(value: &Value) -> Option<ViewId> {
+    const PREFIX: &str = "view-id-";
+    let id = value.as_str()?;
+    if !id.starts_with(PREFIX) {
+        return None;
+    }
+    id[PREFIX.len()..].parse().ok().map(ViewId)
+}
+
+/// `JsonClient` only records what it is asked to send, so never fails.
+fn wait(request: ClientResult<()>) {
+    let _ = request.wait();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn initialize(plugin: &mut Plugin) -> Vec<Value> {
+        plugin.handle(
+            r#"{"method": "initialize", "params": {"plugin_id": 1,
+                "buffer_info": [
+                    {"buffer_id": 1, "views": ["view-id-1", "view-id-2"]},
+                    {"buffer_id": 2, "views": ["view-id-3"]}
+                ]}}"#,
+        )
+    }
+
+    fn keys(plugin: &mut Plugin, view: u64, keys: &str) -> Vec<Value> {
+        plugin.handle(&json!({
+            "method": "custom_command",
+            "params": {
+                "view_id": format!("view-id-{}", view),
+                "method": "keys",
+                "params": {"keys": keys},
+            },
+        }).to_string())
+    }
+
+    /// Answer the `get_data` request among `messages` for `view`.
+    fn fetched(
+        plugin: &mut Plugin,
+        messages: &[Value],
+        view: u64,
+        text: &str,
+    ) -> Vec<Value> {
+        let view_id = format!("view-id-{}", view);
+        let request = messages
+            .iter()
+            .find(|message| {
+                message["method"] == "get_data"
+                    && message["params"]["view_id"] == *view_id
+            })
+            .unwrap();
+        plugin.handle(&json!({"id": request["id"], "result": text}).to_string())
+    }
+
+    fn update(plugin: &mut Plugin, params: Value) -> Vec<Value> {
+        plugin.handle(
+            &json!({"id": 9, "method": "update", "params": params}).to_string(),
+        )
+    }
+
+    /// The `edit` of `view` at `rev`, with the delta elements `els`.
+    fn edit(view: u64, rev: u64, base_len: u64, els: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "method": "edit",
+            "params": {
+                "view_id": format!("view-id-{}", view),
+                "plugin_id": 1,
+                "edit": {
+                    "rev": rev,
+                    "delta": {"base_len": base_len, "els": els},
+                    "priority": EDIT_PRIORITY,
+                    "after_cursor": false,
+                    "author": "vixi",
+                },
+            },
+        })
+    }
+
+    #[test]
+    fn tracks_views() {
+        let mut plugin = Plugin::new();
+        let messages = initialize(&mut plugin);
+        assert_eq!(vec![ViewId(1), ViewId(2), ViewId(3)], plugin.views());
+        assert_eq!(9, messages.len());
+        assert_eq!(
+            json!({
+                "jsonrpc": "2.0",
+                "method": "add_status_item",
+                "params": {
+                    "view_id": "view-id-1",
+                    "plugin_id": 1,
+                    "key": "vixi-mode",
+                    "value": "",
+                    "alignment": "left",
+                },
+            }),
+            messages[0]
+        );
+        // Each view's text is asked for.
+        assert_eq!(
+            json!({
+                "jsonrpc": "2.0",
+                "id": 0,
+                "method": "get_data",
+                "params": {
+                    "plugin_id": 1,
+                    "view_id": "view-id-1",
+                    "start": 0,
+                    "unit": "utf8",
+                    "max_size": MAX_FETCH,
+                    "rev": 0,
+                },
+            }),
+            messages[6]
+        );
+        plugin.handle(r#"{"method": "did_close",
+                          "params": {"view_id": "view-id-2"}}"#);
+        assert_eq!(vec![ViewId(1), ViewId(3)], plugin.views());
+    }
+
+    #[test]
+    fn keys_update_status_per_view() {
+        let mut plugin = Plugin::new();
+        let requests = initialize(&mut plugin);
+        fetched(&mut plugin, &requests, 1, "");
+        fetched(&mut plugin, &requests, 3, "");
+        let messages = keys(&mut plugin, 1, "ia");
+        assert_eq!(2, messages.len());
+        assert_eq!("update_status_item", messages[0]["method"]);
+        assert_eq!("view-id-1", messages[0]["params"]["view_id"]);
+        assert_eq!(1, messages[0]["params"]["plugin_id"]);
+        assert_eq!("-- INSERT --", messages[0]["params"]["value"]);
+        assert_eq!(edit(1, 0, 0, json!([{"insert": "a"}])), messages[1]);
+        // View 3 is still in Normal mode.
+        assert!(keys(&mut plugin, 3, "j").is_empty());
+    }
+
+    #[test]
+    fn sends_edits_against_the_revision() {
+        let mut plugin = Plugin::new();
+        let requests = initialize(&mut plugin);
+        // Keys wait for the text.
+        assert!(keys(&mut plugin, 1, "dw").is_empty());
+        let messages = fetched(&mut plugin, &requests, 1, "hello world");
+        let els = json!([{"copy": [6, 11]}]);
+        assert_eq!(Some(&edit(1, 0, 11, els)), messages.last());
+        // And for core to take the edit.
+        assert!(keys(&mut plugin, 1, "dl").is_empty());
+        let messages = update(
+            &mut plugin,
+            json!({
+                "view_id": "view-id-1",
+                "rev": 1,
+                "author": "vixi",
+                "delta": {"base_len": 11, "els": [{"copy": [6, 11]}]},
+            }),
+        );
+        let els = json!([{"copy": [1, 5]}]);
+        assert!(messages.contains(&edit(1, 1, 5, els)));
+        assert_eq!(json!(0), messages.last().unwrap()["result"]);
+    }
+
+    #[test]
+    fn follows_updates() {
+        let mut plugin = Plugin::new();
+        let requests = initialize(&mut plugin);
+        fetched(&mut plugin, &requests, 1, "abc");
+        fetched(&mut plugin, &requests, 2, "abc");
+        let messages = update(
+            &mut plugin,
+            json!({
+                "view_id": "view-id-1",
+                "rev": 4,
+                "author": "frontend",
+                "delta": {"base_len": 3, "els": [
+                    {"insert": "é"},
+                    {"copy": [0, 3]},
+                ]},
+            }),
+        );
+        assert_eq!(1, messages.len());
+        // Both views of the buffer follow, and keys act at the cursor sent.
+        let messages = plugin.handle(&json!({
+            "method": "custom_command",
+            "params": {
+                "view_id": "view-id-2",
+                "method": "keys",
+                "params": {"keys": "dl", "cursor": {"line": 0, "column": 2}},
+            },
+        }).to_string());
+        let els = json!([{"copy": [0, 2]}, {"copy": [3, 5]}]);
+        assert_eq!(Some(&edit(2, 4, 5, els)), messages.last());
+        // Without a delta, both views fetch the text again.
+        let messages =
+            update(&mut plugin, json!({"view_id": "view-id-1", "rev": 5}));
+        assert_eq!(3, messages.len());
+        for message in &messages[..2] {
+            assert_eq!("get_data", message["method"]);
+            assert_eq!(5, message["params"]["rev"]);
+        }
+    }
+
+    #[test]
+    fn finds_deltas() {
+        let els = json!([{"copy": [0, 1]}, {"insert": "xy"}, {"copy": [2, 3]}]);
+        assert_eq!(json!({"base_len": 3, "els": els}), delta("abc", "axyc"));
+        let text = apply_delta("abc", &delta("abc", "axyc"));
+        assert_eq!(Some(String::from("axyc")), text);
+        assert_eq!(json!({"base_len": 2, "els": [{"copy": [0, 1]}]}),
+                   delta("aa", "a"));
+        assert_eq!(None, apply_delta("ab", &delta("abc", "")));
+    }
+
+    #[test]
+    fn answers_updates() {
+        let mut plugin = Plugin::new();
+        let messages = plugin.handle(
+            r#"{"id": 7, "method": "update",
+                "params": {"view_id": "view-id-1", "rev": 2}}"#,
+        );
+        let response = json!({"jsonrpc": "2.0", "id": 7, "result": 0});
+        assert_eq!(response, messages[0]);
+        assert!(plugin.handle(r#"{"id": 1, "result": null}"#).is_empty());
+    }
+}
@@ -0,0 +1,315 @@
+use client::{Applier, Client};
+use effect::Effect;
+use futures::Future;
+use json_client::JsonClient;
+use options::OptionValue;
+use serde::de::DeserializeOwned;
+use serde_json;
+use serde_json::Value;
+use view::{MapMode, ViewId};
+use vixi::{FeedFlags, Vixi};
+
+// JSON-RPC 2.0 error codes.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+type RpcResult = Result<Value, (i64, String)>;
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Option<Value>, // Absent for notifications, which get no response.
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+struct KeysParams {
+    keys: String,
+}
+
+#[derive(Deserialize)]
+struct FeedKeysParams {
+    keys: String,
+    #[serde(default)]
+    flags: String, // As for Vim's `feedkeys()`, e.g. "nx".
+}
+
+#[derive(Deserialize)]
+struct SetOptionParams {
+    name: String,
+    value: OptionValue,
+}
+
+#[derive(Deserialize)]
+struct MapParams {
+    mode: MapMode,
+    lhs: String,
+    rhs: String,
+    #[serde(default)]
+    local: bool,
+}
+
+/// Serves a `Vixi` over JSON-RPC 2.0, one message per line, so frontends
+/// written in other languages can use it without linking Rust.
+///
+/// Requests:
+/// * `keys {keys}`: process keys in Vim's key notation.
+/// * `feedkeys {keys, flags}`: queue keys as Vim's `feedkeys()` does; see
+///   `FeedFlags::parse`.
+/// * `mode`: the current mode.
+/// * `set_option {name, value}`: set an option, as with `:set`.
+/// * `map {mode, lhs, rhs, local}`: map keys in `"normal"`, `"pending"` or
+///   `"insert"` mode, only in the focused view if `local` is true.
+///
+/// Except for `set_option` and `map`, responses carry the mode, as with
+/// `mode`. Before the response to `keys` or `feedkeys`, the edits vixi
+/// makes are sent as notifications in xi-core's protocol (see
+/// `JsonClient`), along with a `mode_changed` notification whenever the
+/// mode changes and a `message` notification for each message.
+pub struct Server {
+    vixi: Vixi,
+    client: JsonClient,
+    applier: Applier, // Forwards effects to `client`.
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Server {
+            vixi: Vixi::new(),
+            client: JsonClient::new(),
+            applier: Applier::new(),
+        }
+    }
+
+    /// Handle a line of input, returning the messages to send in order.
+    pub fn handle(&mut self, line: &str) -> Vec<Value> {
+        let request = match serde_json::from_str::<Value>(line) {
+            Ok(value) => serde_json::from_value::<Request>(value),
+            Err(err) => {
+                let message = err.to_string();
+                return vec![error(Value::Null, PARSE_ERROR, &message)];
+            }
+        };
+        let request = match request {
+            Ok(request) => request,
+            Err(err) => {
+                let message = err.to_string();
+                return vec![error(Value::Null, INVALID_REQUEST, &message)];
+            }
+        };
+        let result = self.call(&request.method, request.params);
+        let mut messages = self.client.drain();
+        if let Some(id) = request.id {
+            messages.push(match result {
+                Ok(result) => {
+                    json!({"jsonrpc": "2.0", "id": id, "result": result})
+                }
+                Err((code, message)) => error(id, code, &message),
+            });
+        }
+        messages
+    }
+
+    fn call(&mut self, method: &str, params: Value) -> RpcResult {
+        match method {
+            "keys" => {
+                let params: KeysParams = parse(params)?;
+                let view_id = self.vixi.view_id();
+                let effects =
+                    self.vixi.process(&params.keys).map_err(internal)?;
+                self.send(view_id, &effects)
+            }
+            "feedkeys" => {
+                let params: FeedKeysParams = parse(params)?;
+                let view_id = self.vixi.view_id();
+                let flags = FeedFlags::parse(&params.flags);
+                let effects = self.vixi
+                    .feedkeys(&params.keys, flags)
+                    .map_err(internal)?;
+                self.send(view_id, &effects)
+            }
+            "mode" => Ok(self.mode()),
+            "set_option" => {
+                let params: SetOptionParams = parse(params)?;
+                self.vixi
+                    .set_option(&params.name, params.value)
+                    .map_err(|err| (INVALID_PARAMS, err.to_string()))?;
+                Ok(Value::Null)
+            }
+            "map" => {
+                let params: MapParams = parse(params)?;
+                let (mode, lhs, rhs) = (params.mode, &params.lhs, &params.rhs);
+                let mapped = if params.local {
+                    self.vixi.map_local(mode, lhs, rhs)
+                } else {
+                    self.vixi.map(mode, lhs, rhs)
+                };
+                if !mapped {
+                    let message = format!("invalid mapping: {}", lhs);
+                    return Err((INVALID_PARAMS, message));
+                }
+                Ok(Value::Null)
+            }
+            _ => {
+                let message = format!("unknown method: {}", method);
+                Err((METHOD_NOT_FOUND, message))
+            }
+        }
+    }
+
+    /// Send notifications for the effects of keys on `view_id`.
+    fn send(&mut self, view_id: ViewId, effects: &[Effect]) -> RpcResult {
+        for effect in effects {
+            let requests = match *effect {
+                Effect::ModeChange(ref info) => {
+                    vec![self.client.notify("mode_changed", json!(info))]
+                }
+                Effect::Message(ref message) => {
+                    vec![self.client.notify("message", json!(message))]
+                }
+                _ => self.applier.apply(&mut self.client, view_id, effect),
+            };
+            for request in requests {
+                request.wait().map_err(internal)?;
+            }
+        }
+        Ok(self.mode())
+    }
+
+    fn mode(&self) -> Value {
+        json!({
+            "mode": self.vixi.mode(),
+            "pending": self.vixi.is_pending(),
+            "info": self.vixi.mode_info(),
+        })
+    }
+}
+
+fn parse<T: DeserializeOwned>(params: Value) -> Result<T, (i64, String)> {
+    serde_json::from_value(params)
+        .map_err(|err| (INVALID_PARAMS, err.to_string()))
+}
+
+fn internal<E: ToString>(err: E) -> (i64, String) {
+    (INTERNAL_ERROR, err.to_string())
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": code, "message": message},
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn methods(messages: &[Value]) -> Vec<&str> {
+        messages
+            .iter()
+            .map(|message| {
+                message["method"].as_str().unwrap_or("(response)")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keys_send_edits_then_respond() {
+        let mut server = Server::new();
+        let messages = server.handle(
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "keys",
+                "params": {"keys": "ia<Esc>"}}"#,
+        );
+        assert_eq!(
+            vec![
+                "mode_changed",
+                "edit",
+                "edit",
+                "mode_changed",
+                "(response)",
+            ],
+            methods(&messages)
+        );
+        assert_eq!("Insert", messages[0]["params"]["base"]);
+        assert_eq!("insert", messages[1]["params"]["method"]);
+        assert_eq!(json!({"chars": "a"}), messages[1]["params"]["params"]);
+        assert_eq!("move_left", messages[2]["params"]["method"]);
+        assert_eq!("Normal", messages[3]["params"]["base"]);
+        assert_eq!(json!(1), messages[4]["id"]);
+        assert_eq!("Normal", messages[4]["result"]["mode"]);
+    }
+
+    #[test]
+    fn feedkeys_waits_unless_executed() {
+        let mut server = Server::new();
+        let messages = server.handle(
+            r#"{"id": 1, "method": "feedkeys", "params": {"keys": "i"}}"#,
+        );
+        assert_eq!(vec!["(response)"], methods(&messages));
+        assert_eq!("Normal", messages[0]["result"]["mode"]);
+        let messages = server.handle(
+            r#"{"id": 2, "method": "feedkeys",
+                "params": {"keys": "a", "flags": "x"}}"#,
+        );
+        assert_eq!(
+            vec!["mode_changed", "edit", "(response)"],
+            methods(&messages)
+        );
+    }
+
+    #[test]
+    fn map_and_set_option() {
+        let mut server = Server::new();
+        let messages = server.handle(
+            r#"{"id": 1, "method": "map", "params":
+                {"mode": "normal", "lhs": "Q", "rhs": "i"}}"#,
+        );
+        assert_eq!(Value::Null, messages[0]["result"]);
+        let messages = server.handle(
+            r#"{"id": 2, "method": "keys", "params": {"keys": "Q"}}"#,
+        );
+        assert_eq!("Insert", messages.last().unwrap()["result"]["mode"]);
+        let messages = server.handle(
+            r#"{"id": 3, "method": "set_option",
+                "params": {"name": "ts", "value": 8}}"#,
+        );
+        assert_eq!(Value::Null, messages[0]["result"]);
+        let messages = server.handle(
+            r#"{"id": 4, "method": "set_option",
+                "params": {"name": "nosuch", "value": true}}"#,
+        );
+        assert_eq!(json!(INVALID_PARAMS), messages[0]["error"]["code"]);
+    }
+
+    #[test]
+    fn notifications_get_no_response() {
+        let mut server = Server::new();
+        let messages =
+            server.handle(r#"{"method": "keys", "params": {"keys": "2j"}}"#);
+        assert_eq!(vec!["edit", "edit"], methods(&messages));
+        assert_eq!("move_down", messages[1]["params"]["method"]);
+    }
+
+    #[test]
+    fn errors() {
+        let mut server = Server::new();
+        let code = |messages: Vec<Value>| messages[0]["error"]["code"].clone();
+        assert_eq!(json!(PARSE_ERROR), code(server.handle("{")));
+        assert_eq!(json!(INVALID_REQUEST), code(server.handle("{}")));
+        assert_eq!(
+            json!(METHOD_NOT_FOUND),
+            code(server.handle(r#"{"id": 1, "method": "nosuch"}"#))
+        );
+        assert_eq!(
+            json!(INVALID_PARAMS),
+            code(server.handle(r#"{"id": 1, "method": "keys"}"#))
+        );
+    }
+}
@@ -0,0 +1,32 @@
+use op::OperatorOp;
+use std::fmt;
+
+/// A partially-typed command, e.g. `12"ad2`, for display in a showcmd area.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShowCmd<K> {
+    pub count: Option<i32>, // Count applied to the command, if any.
+    pub register: Option<char>, // Register named with `"`, if any.
+    pub operator: Option<OperatorOp>, // Operator awaiting a motion, if any.
+    pub pending: Vec<K>, // Keys not yet resolved to a command.
+    pub typed: Vec<K>, // Every key typed since the command began.
+}
+
+impl<K> ShowCmd<K> {
+    /// Returns true if no command is in progress.
+    pub fn is_empty(&self) -> bool {
+        self.typed.is_empty()
+    }
+}
+
+/// Formats the typed keys in Vim's key notation.
+impl<K> fmt::Display for ShowCmd<K>
+where
+    K: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for key in self.typed.iter() {
+            write!(f, "{}", key)?;
+        }
+        Ok(())
+    }
+}
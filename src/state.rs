@@ -1,9 +1,33 @@
+use effect::Effect;
+use event::Event;
+use keymaps::Keymaps;
+use line_cache::LineCache;
 use mode_map::ModeMap;
-use op::{NormalOp, PendingOp, InsertOp};
+use op::{NormalOp, OperatorOp, PendingOp, InsertOp, MotionOp, Span};
+use std::collections::BTreeMap;
+use std::mem;
+use std::sync::Arc;
 use typeahead::{Parse, RemapType, Typeahead};
-use client;
-use xrl;
+use view::{MapMode, Position, ViewId};
 
+fn empty_map<K, Op>() -> Arc<ModeMap<K, Op>>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+    Op: Copy,
+{
+    Arc::new(ModeMap::new())
+}
+
+/// A change that `.` repeats.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Change {
+    Operator(OperatorOp, Span, i32), // An operator, its span and count.
+    Insert, // An insert, of the text in `last_insert`.
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct State<K>
 where
     K: Ord,
@@ -11,12 +35,45 @@ where
     K: Parse,
 {
     pub typeahead: Typeahead<K>,
-    pub normal_mode_map: ModeMap<K, NormalOp>,
-    pub pending_mode_map: ModeMap<K, PendingOp>,
-    pub insert_mode_map: ModeMap<K, InsertOp>,
+    #[serde(default)]
+    pub timed_out: bool, // Resolve the typeahead rather than wait for keys.
+    // Shared, session and local mappings combined, rebuilt rather than
+    // serialized.
+    #[serde(skip, default = "empty_map")]
+    pub normal_mode_map: Arc<ModeMap<K, NormalOp>>,
+    #[serde(skip, default = "empty_map")]
+    pub pending_mode_map: Arc<ModeMap<K, PendingOp>>,
+    #[serde(skip, default = "empty_map")]
+    pub insert_mode_map: Arc<ModeMap<K, InsertOp>>,
     pub count: i32, // Used when an op is to be performed [count] times.
-    pub view_id: xrl::ViewId,
-    pub client: Box<client::Client>,
+    pub register: Option<char>, // Register named with `"` for the next op.
+    pub view_id: ViewId, // The focused view.
+    #[serde(skip)]
+    pub effects: Vec<Effect>, // Effects produced since the last key.
+    #[serde(skip)]
+    pub events: Vec<Event>, // Events not yet delivered to observers.
+    pub recording: Option<char>, // Register being recorded into, if any.
+    pub registers: BTreeMap<char, Vec<K>>, // Recorded keys, by register.
+    recorded: Vec<K>, // Keys typed since recording started.
+    pub marks: BTreeMap<char, Position>, // Marks set in the focused view.
+    pub local_maps: Vec<(MapMode, Vec<K>, Vec<K>)>, // Buffer-local remaps.
+    pub inserted: String, // Text typed since Insert mode was entered.
+    // Characters typed just before the cursor, or 0 if it may be at the
+    // start of the line.
+    #[serde(default)]
+    pub typed_on_line: u64,
+    pub last_insert: Option<String>, // Text typed during the last insert.
+    #[serde(default)]
+    pub last_change: Option<Change>, // Made last, for `.`.
+    pub lines: LineCache, // Contents of the focused view.
+    pub cursor: Option<Position>, // Cursor last reported by core.
+    pub selections: Vec<(Position, Position)>, // Last reported by core.
+    pub changing: bool, // Set `[` and `]` once core reports the next cursor.
+    #[serde(default)]
+    pub undo_group: bool, // Whether an undo group has been started.
+    // While a macro runs, the number of keys queued behind it.
+    #[serde(default)]
+    pub macro_rest: Option<usize>,
 }
 
 impl<K> State<K>
@@ -25,20 +82,33 @@ where
     K: Copy,
     K: Parse,
 {
-    pub fn new(
-        client: Box<client::Client>,
-        normal_map: ModeMap<K, NormalOp>,
-        pending_map: ModeMap<K, PendingOp>,
-        insert_map: ModeMap<K, InsertOp>,
-    ) -> Self {
+    pub fn new(view_id: ViewId, keymaps: &Keymaps<K>) -> Self {
         State {
             typeahead: Typeahead::<K>::new(),
-            normal_mode_map: normal_map,
-            pending_mode_map: pending_map,
-            insert_mode_map: insert_map,
+            timed_out: false,
+            normal_mode_map: keymaps.normal().clone(),
+            pending_mode_map: keymaps.pending().clone(),
+            insert_mode_map: keymaps.insert().clone(),
             count: 1,
-            view_id: xrl::ViewId(0), // TODO pass this in
-            client: client,
+            register: None,
+            view_id: view_id,
+            effects: Vec::new(),
+            events: Vec::new(),
+            recording: None,
+            registers: BTreeMap::new(),
+            recorded: Vec::new(),
+            marks: BTreeMap::new(),
+            local_maps: Vec::new(),
+            inserted: String::new(),
+            typed_on_line: 0,
+            last_insert: None,
+            lines: LineCache::new(),
+            cursor: None,
+            selections: Vec::new(),
+            changing: false,
+            last_change: None,
+            undo_group: false,
+            macro_rest: None,
         }
     }
 
@@ -47,9 +117,124 @@ where
         self.typeahead.push_back(key, remap_type);
     }
 
+    /// Queue an effect for the frontend to apply.
+    pub fn emit(&mut self, effect: Effect) {
+        self.effects.push(effect);
+    }
+
+    /// Queue an event for delivery to observers.
+    pub fn notify(&mut self, event: Event) {
+        self.events.push(event);
+    }
+
+    /// Queue `effect` once per count, then finish the command.
+    pub fn emit_counted(&mut self, effect: Effect) {
+        for _ in 0..self.count {
+            self.effects.push(effect.clone());
+        }
+        self.finish_command();
+    }
+
+    /// Reset the count and register once a command has been carried out.
+    pub fn finish_command(&mut self) {
+        self.count = 1;
+        self.register = None;
+    }
+
+    /// Queue the effects to insert `text`, as typing it in Insert mode
+    /// would.
+    pub fn emit_text(&mut self, text: &str) {
+        for c in text.chars() {
+            match c {
+                '\n' => self.emit(Effect::InsertNewline),
+                _ => self.emit(Effect::InsertChar(c)),
+            }
+        }
+    }
+
+    /// Remember a typed key if a recording is in progress.
+    pub fn record(&mut self, key: K) {
+        if self.recording.is_some() {
+            self.recorded.push(key);
+        }
+    }
+
+    /// Start recording typed keys into `register`, as with `q{register}`.
+    /// An uppercase register appends to its lowercase counterpart.
+    pub fn start_recording(&mut self, register: char) {
+        self.recorded = match register {
+            'a'...'z' | '0'...'9' | '"' => Vec::new(),
+            'A'...'Z' => {
+                let lower = register.to_ascii_lowercase();
+                self.registers.get(&lower).cloned().unwrap_or_default()
+            }
+            _ => {
+                return;
+            }
+        };
+        let register = register.to_ascii_lowercase();
+        self.recording = Some(register);
+        self.notify(Event::RecordingStarted(register));
+    }
+
+    /// Stop recording and store the recorded keys, minus the `q` that ended
+    /// the recording. Returns false if no recording was in progress.
+    pub fn stop_recording(&mut self) -> bool {
+        match self.recording.take() {
+            Some(register) => {
+                let mut keys = mem::replace(&mut self.recorded, Vec::new());
+                keys.pop();
+                self.registers.insert(register, keys);
+                self.notify(Event::RecordingStopped(register));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Run the keys recorded in `register` [count] times, as typeahead.
+    /// Returns false if the register is empty.
+    pub fn run_macro(&mut self, register: char) -> bool {
+        let keys = match self.registers.get(&register.to_ascii_lowercase()) {
+            Some(keys) if !keys.is_empty() => keys.clone(),
+            _ => {
+                return false;
+            }
+        };
+        self.macro_rest = Some(self.typeahead.len());
+        for _ in 0..self.count {
+            self.typeahead.put_front(&keys, RemapType::Remap);
+        }
+        true
+    }
+
+    /// End the undo group, if one was started, unless a macro is running.
+    pub fn end_undo_group(&mut self) {
+        if self.undo_group && self.macro_rest.is_none() {
+            self.undo_group = false;
+            self.emit(Effect::EndUndoGroup);
+        }
+    }
+
+    /// Move the cursor back onto the last character typed, as Vim does on
+    /// leaving Insert mode. Unless the cursor is known to be past the start
+    /// of the line it stays put, as core would move it onto the line above.
+    pub fn leave_insert(&mut self) {
+        if self.typed_on_line > 0 {
+            self.emit(Effect::Move(MotionOp::Left));
+        }
+        self.typed_on_line = 0;
+    }
+
+    /// Set the `[` and `]` marks around changed or yanked text.
+    pub fn mark_change(&mut self, start: Position, end: Position) {
+        self.marks.insert('[', start);
+        self.marks.insert(']', end);
+    }
+
     /// Clear state variables. Used when an `<Esc>` is encountered.
     pub fn cancel(&mut self) {
-        self.count = 1;
+        self.finish_command();
         self.typeahead.clear();
     }
 }
@@ -1,18 +1,161 @@
-use mode::{Mode, normal, Transition};
-use mode_map::ModeMap;
-use op::{InsertOp, PendingOp, NormalOp};
-use state::State;
-use typeahead::{Parse, RemapType};
-use client;
+use client::TextQuery;
+use effect::Effect;
+use event::{Event, Observers, SubscriptionId};
+use keymaps::Keymaps;
+use line_cache::{LineCache, Update};
+use mode::{Mode, NextMode, next, normal, Transition};
+use mode_info::{BaseMode, ModeInfo};
+use mode_map::{ModeMap, Target};
+use op::{CommandId, Describe, HasCommand, HasMotion, HasObject, HasOperator,
+         InsertOp, MotionOp, ObjectOp, OperatorOp, PendingOp, NormalOp};
+use options::{OptionError, OptionValue, Options};
+use ordered_vec_map::InsertionResult;
+use showcmd::ShowCmd;
+use state::{Change, State};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+use typeahead::{Parse, RemapType, Typeahead};
+use view::{MapMode, Position, ViewId};
 
+/// The mappings of each mode.
+#[derive(Clone, Serialize, Deserialize)]
+struct Maps<K>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    normal: ModeMap<K, NormalOp>,
+    pending: ModeMap<K, PendingOp>,
+    insert: ModeMap<K, InsertOp>,
+}
+
+impl<K> Maps<K>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    fn remap(
+        &mut self,
+        mode: MapMode,
+        lhs: Vec<K>,
+        rhs: Vec<K>,
+    ) -> InsertionResult {
+        match mode {
+            MapMode::Normal => self.normal.insert_remap(lhs, rhs),
+            MapMode::Pending => self.pending.insert_remap(lhs, rhs),
+            MapMode::Insert => self.insert.insert_remap(lhs, rhs),
+        }
+    }
+
+    fn command(
+        &mut self,
+        mode: MapMode,
+        lhs: Vec<K>,
+        id: CommandId,
+    ) -> InsertionResult {
+        match mode {
+            MapMode::Normal => self.normal.insert_command(lhs, id),
+            MapMode::Pending => self.pending.insert_command(lhs, id),
+            MapMode::Insert => self.insert.insert_command(lhs, id),
+        }
+    }
+
+    /// Bind a motion both on its own and after an operator.
+    fn motion(&mut self, lhs: Vec<K>, motion: MotionOp) -> InsertionResult {
+        match self.normal.insert_motion(lhs.clone(), motion) {
+            InsertionResult::InvalidKey => InsertionResult::InvalidKey,
+            _ => self.pending.insert_motion(lhs, motion),
+        }
+    }
+}
+
+/// The editing context of a view which is not focused.
+#[derive(Serialize, Deserialize)]
+struct View<K>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    id: ViewId,
+    mode: Mode<K>,
+    typed: Vec<K>,
+    typeahead: Typeahead<K>,
+    count: i32,
+    register: Option<char>,
+    marks: BTreeMap<char, Position>,
+    local_maps: Vec<(MapMode, Vec<K>, Vec<K>)>,
+    inserted: String,
+    #[serde(default)]
+    typed_on_line: u64,
+    last_insert: Option<String>,
+    #[serde(default)]
+    last_change: Option<Change>,
+    lines: LineCache,
+    cursor: Option<Position>,
+    selections: Vec<(Position, Position)>,
+    changing: bool,
+}
+
+impl<K> View<K>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    fn new(id: ViewId) -> Self {
+        View {
+            id: id,
+            mode: normal(),
+            typed: Vec::new(),
+            typeahead: Typeahead::new(),
+            count: 1,
+            register: None,
+            marks: BTreeMap::new(),
+            local_maps: Vec::new(),
+            inserted: String::new(),
+            typed_on_line: 0,
+            last_insert: None,
+            last_change: None,
+            lines: LineCache::new(),
+            cursor: None,
+            selections: Vec::new(),
+            changing: false,
+        }
+    }
+}
+
+/// Processes keys for a set of views, one of which has focus.
+///
+/// Each view has its own mode, pending command, marks, buffer-local mappings,
+/// last insert, line cache, cursor and selections. Global mappings, global
+/// marks and registers are shared.
+///
+/// Everything but subscribers can be serialized, to be restored later with
+/// `restore`.
+#[derive(Serialize, Deserialize)]
 pub struct StateMachine<K>
 where
     K: Ord,
     K: Copy,
     K: Parse,
 {
-    state: State<K>,
+    state: State<K>, // Global state, and that of the focused view.
     mode: Mode<K>,
+    typed: Vec<K>, // Keys typed since the current command began.
+    #[serde(skip, default = "Observers::new")]
+    observers: Observers,
+    #[serde(skip, default = "Keymaps::empty")]
+    keymaps: Keymaps<K>, // Mappings shared with other sessions.
+    maps: Maps<K>, // Mappings made in this session, for every view.
+    views: Vec<View<K>>, // Unfocused views, most recently focused last.
+    #[serde(default)]
+    global_marks: BTreeMap<char, (ViewId, Position)>, // `A`-`Z`, `0`-`9`.
+    options: Options,
 }
 
 impl<K> StateMachine<K>
@@ -21,24 +164,1193 @@ where
     K: Copy,
     K: Parse,
 {
+    /// Create a machine with a single view, `ViewId(0)`.
+    #[cfg(test)]
     pub fn new(
-        client: Box<client::Client>,
         normal_map: ModeMap<K, NormalOp>,
         pending_map: ModeMap<K, PendingOp>,
         insert_map: ModeMap<K, InsertOp>,
     ) -> Self {
+        StateMachine::with_keymaps(
+            Keymaps::new(normal_map, pending_map, insert_map),
+        )
+    }
+
+    /// Create a machine with a single view, `ViewId(0)`, sharing `keymaps`
+    /// rather than copying them.
+    pub fn with_keymaps(keymaps: Keymaps<K>) -> Self {
         StateMachine {
-            state: State::new(client, normal_map, pending_map, insert_map),
+            state: State::new(ViewId(0), &keymaps),
             mode: normal(),
+            typed: Vec::new(),
+            observers: Observers::new(),
+            keymaps: keymaps,
+            maps: Maps {
+                normal: ModeMap::new(),
+                pending: ModeMap::new(),
+                insert: ModeMap::new(),
+            },
+            views: Vec::new(),
+            global_marks: BTreeMap::new(),
+            options: Options::default(),
+        }
+    }
+
+    /// Process a single key, returning the effects it produced in order.
+    #[cfg(test)]
+    pub fn process(&mut self, key: K) -> Vec<Effect> {
+        self.process_keys(&[key])
+    }
+
+    /// Process keys as if typed one after another, returning the effects
+    /// they produced in order.
+    #[cfg(test)]
+    pub fn process_keys(&mut self, keys: &[K]) -> Vec<Effect> {
+        let mut effects = Vec::new();
+        let result: Result<(), ()> = self.process_keys_with(keys, |effect, _| {
+            effects.push(effect.clone());
+            Ok(())
+        });
+        debug_assert!(result.is_ok());
+        effects
+    }
+
+    /// Process a single key, passing each effect to `apply` as soon as it
+    /// is produced, along with the text as far as core has sent it.
+    ///
+    /// Consecutive inserted characters and repeats of a motion are held
+    /// back and passed as one effect, e.g. `Insert` rather than a series of
+    /// `InsertChar`, before anything else is applied or observed.
+    ///
+    /// If `apply` fails, the command in progress is abandoned: remaining
+    /// effects and keys are dropped, events describing the failed step are
+    /// never delivered, and the machine returns to the mode it was in before
+    /// that step (or to Normal mode, if it was waiting on an operator).
+    pub fn process_with<F, E>(
+        &mut self,
+        key: K,
+        apply: F,
+    ) -> Result<(), E>
+    where
+        F: FnMut(&Effect, &TextQuery) -> Result<(), E>,
+    {
+        self.process_keys_with(&[key], apply)
+    }
+
+    /// Process keys as if typed one after another, passing effects to
+    /// `apply` as `process_with` does. Effects are merged across keys, so
+    /// that e.g. pasted text is inserted at once.
+    pub fn process_keys_with<F, E>(
+        &mut self,
+        keys: &[K],
+        mut apply: F,
+    ) -> Result<(), E>
+    where
+        F: FnMut(&Effect, &TextQuery) -> Result<(), E>,
+    {
+        let mut held = None;
+        for &key in keys {
+            self.feed(&[key], RemapType::Remap, false, true);
+            self.run_typeahead(&mut apply, &mut held)?;
+        }
+        self.release(&mut apply, &mut held)
+    }
+
+    /// Stop waiting for more keys to complete a mapping, as when
+    /// 'timeoutlen' has passed since the last key. The keys typed so far
+    /// become their shortest mapping, or else are taken literally. Returns
+    /// the effects produced in order.
+    #[cfg(test)]
+    pub fn timeout(&mut self) -> Vec<Effect> {
+        let mut effects = Vec::new();
+        let result: Result<(), ()> = self.timeout_with(|effect, _| {
+            effects.push(effect.clone());
+            Ok(())
+        });
+        debug_assert!(result.is_ok());
+        effects
+    }
+
+    /// Time out a pending mapping, passing each effect to `apply` as
+    /// `process_with` does.
+    pub fn timeout_with<F, E>(&mut self, apply: F) -> Result<(), E>
+    where
+        F: FnMut(&Effect, &TextQuery) -> Result<(), E>,
+    {
+        if self.state.typeahead.is_empty() {
+            return Ok(());
         }
+        self.state.timed_out = true;
+        self.process_typeahead_with(apply)
+    }
+
+    /// Queue keys without processing them, as with Vim's `feedkeys()`.
+    ///
+    /// The keys go before any keys still waiting to be processed if `front`
+    /// is true, and after them otherwise. `Noremap` keys are not remapped.
+    /// Keys which are `typed` are recorded into registers and shown by
+    /// `showcmd`; others are handled as if they came from a mapping.
+    pub fn feed(
+        &mut self,
+        keys: &[K],
+        remap_type: RemapType,
+        front: bool,
+        typed: bool,
+    ) {
+        if typed {
+            for &key in keys {
+                self.state.record(key);
+            }
+            self.typed.extend_from_slice(keys);
+        }
+        if front {
+            self.state.typeahead.put_front(&keys.to_vec(), remap_type);
+        } else {
+            for &key in keys {
+                self.state.put(key, remap_type);
+            }
+        }
+        self.state.timed_out = false;
+    }
+
+    /// Process the keys queued by `feed`, passing each effect to `apply` as
+    /// `process_with` does.
+    pub fn process_typeahead_with<F, E>(
+        &mut self,
+        mut apply: F,
+    ) -> Result<(), E>
+    where
+        F: FnMut(&Effect, &TextQuery) -> Result<(), E>,
+    {
+        let mut held = None;
+        self.run_typeahead(&mut apply, &mut held)?;
+        self.release(&mut apply, &mut held)
+    }
+
+    /// Register `observer` to be called with every event, synchronously
+    /// from `process`.
+    pub fn subscribe(
+        &mut self,
+        observer: Box<FnMut(&Event) + Send>,
+    ) -> SubscriptionId {
+        self.observers.subscribe(observer)
     }
 
-    pub fn process(&mut self, key: K) {
-        self.state.put(key, RemapType::Remap);
-        self.mode = self.mode.transition(&mut self.state);
+    /// Returns false if `id` was not subscribed.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.observers.unsubscribe(id)
+    }
+
+    /// The focused view.
+    pub fn view_id(&self) -> ViewId {
+        self.state.view_id
+    }
+
+    /// Lists every view, the focused one first, then the rest from most to
+    /// least recently focused.
+    pub fn views(&self) -> Vec<ViewId> {
+        let mut views = vec![self.state.view_id];
+        views.extend(self.views.iter().rev().map(|view| view.id));
+        views
+    }
+
+    pub fn has_view(&self, view_id: ViewId) -> bool {
+        self.views().contains(&view_id)
+    }
+
+    /// Start tracking a view in Normal mode, without focusing it. Returns
+    /// false if the view is already known.
+    pub fn add_view(&mut self, view_id: ViewId) -> bool {
+        if self.has_view(view_id) {
+            return false;
+        }
+        self.views.insert(0, View::new(view_id));
+        true
+    }
+
+    /// Focus a view, resuming whatever was in progress there. Returns false
+    /// if the view is unknown.
+    pub fn switch_view(&mut self, view_id: ViewId) -> bool {
+        if view_id == self.state.view_id {
+            return true;
+        }
+        let index = self.views.iter().position(|view| view.id == view_id);
+        let view = match index {
+            Some(i) => self.views.remove(i),
+            None => {
+                return false;
+            }
+        };
+        let info = self.mode_info();
+        let parked = self.park();
+        self.views.push(parked);
+        self.unpark(view);
+        let new_info = self.mode_info();
+        if new_info != info {
+            self.state.notify(Event::ModeChanged(info, new_info));
+            self.deliver_events();
+        }
+        true
+    }
+
+    /// Stop tracking a view. Closing the focused view focuses the one
+    /// focused before it. Returns false if the view is unknown or is the
+    /// only one left.
+    pub fn close_view(&mut self, view_id: ViewId) -> bool {
+        if view_id == self.state.view_id {
+            let previous = match self.views.last() {
+                Some(view) => view.id,
+                None => {
+                    return false;
+                }
+            };
+            self.switch_view(previous);
+        }
+        let len = self.views.len();
+        self.views.retain(|view| view.id != view_id);
+        self.global_marks.retain(|_, &mut (id, _)| id != view_id);
+        self.views.len() != len
+    }
+
+    /// Map `lhs` to `rhs` in every view, as with `:map`. Returns false if
+    /// the mapping is invalid.
+    pub fn map(&mut self, mode: MapMode, lhs: Vec<K>, rhs: Vec<K>) -> bool {
+        if self.maps.remap(mode, lhs, rhs) == InsertionResult::InvalidKey {
+            return false;
+        }
+        self.rebuild_maps();
+        true
+    }
+
+    /// Bind `lhs` to a user-defined command in every view, alongside the
+    /// built-in commands. Returns false if `lhs` is empty.
+    pub fn map_command(
+        &mut self,
+        mode: MapMode,
+        lhs: Vec<K>,
+        id: CommandId,
+    ) -> bool {
+        if self.maps.command(mode, lhs, id) == InsertionResult::InvalidKey {
+            return false;
+        }
+        self.rebuild_maps();
+        true
+    }
+
+    /// Bind `lhs` to a motion in every view, both on its own and after an
+    /// operator. Returns false if `lhs` is empty.
+    pub fn map_motion(&mut self, lhs: Vec<K>, motion: MotionOp) -> bool {
+        if self.maps.motion(lhs, motion) == InsertionResult::InvalidKey {
+            return false;
+        }
+        self.rebuild_maps();
+        true
+    }
+
+    /// Bind `lhs` to a text object in every view, for use after an
+    /// operator. Returns false if `lhs` is empty.
+    pub fn map_object(&mut self, lhs: Vec<K>, object: ObjectOp) -> bool {
+        let result = self.maps.pending.insert_object(lhs, object);
+        if result == InsertionResult::InvalidKey {
+            return false;
+        }
+        self.rebuild_maps();
+        true
+    }
+
+    /// Bind `lhs` to an operator in every view. Returns false if `lhs` is
+    /// empty.
+    pub fn map_operator(&mut self, lhs: Vec<K>, operator: OperatorOp) -> bool {
+        let result = self.maps.normal.insert_operator(lhs, operator);
+        if result == InsertionResult::InvalidKey {
+            return false;
+        }
+        self.rebuild_maps();
+        true
+    }
+
+    /// Map `lhs` to `rhs` in the focused view only, as with `:map <buffer>`.
+    /// Buffer-local mappings take precedence over global ones. Returns false
+    /// if the mapping is invalid.
+    pub fn map_local(
+        &mut self,
+        mode: MapMode,
+        lhs: Vec<K>,
+        rhs: Vec<K>,
+    ) -> bool {
+        if lhs.is_empty() || lhs == rhs {
+            return false;
+        }
+        self.state
+            .local_maps
+            .retain(|&(m, ref l, _)| m != mode || *l != lhs);
+        self.state.local_maps.push((mode, lhs, rhs));
+        self.rebuild_maps();
+        true
+    }
+
+    /// Apply an update from xi-core to a view, tracking its cursor and
+    /// selections. Returns the effects needed to keep the cursor where the
+    /// current mode allows it, e.g. off the end of the line in Normal mode,
+    /// or `None` if the view is unknown.
+    pub fn update(
+        &mut self,
+        view_id: ViewId,
+        update: &Update,
+    ) -> Option<Vec<Effect>> {
+        if view_id != self.state.view_id {
+            let view = self.views.iter_mut().find(|view| view.id == view_id)?;
+            view.lines.apply(update);
+            if let Some(cursor) = view.lines.cursor() {
+                view.cursor = Some(cursor);
+                view.selections = view.lines.selections();
+            }
+            return Some(Vec::new());
+        }
+        self.state.lines.apply(update);
+        let cursor = match self.state.lines.cursor() {
+            Some(cursor) => cursor,
+            None => {
+                // The cursor is outside the lines core has sent.
+                return Some(Vec::new());
+            }
+        };
+        self.state.cursor = Some(cursor);
+        self.state.selections = self.state.lines.selections();
+        if self.state.changing {
+            self.state.changing = false;
+            self.state.mark_change(cursor, cursor);
+        }
+        let mut effects = Vec::new();
+        if self.mode_info().base == BaseMode::Normal {
+            // Normal mode can't put the cursor past the last character.
+            let len = self.state
+                .lines
+                .line(cursor.line as usize)
+                .map_or(0, |line| line.content().len() as u64);
+            if len > 0 && cursor.column >= len {
+                effects.push(Effect::Move(MotionOp::Left));
+            }
+        }
+        Some(effects)
+    }
+
+    /// The cursor of the focused view, as last reported by core.
+    pub fn cursor(&self) -> Option<Position> {
+        self.state.cursor
+    }
+
+    /// The selections of the focused view, as last reported by core, one
+    /// span per line.
+    pub fn selections(&self) -> &Vec<(Position, Position)> {
+        &self.state.selections
+    }
+
+    /// The contents of the focused view, as far as they are known.
+    pub fn lines(&self) -> &LineCache {
+        &self.state.lines
+    }
+
+    /// The position of a mark in the focused view. A global mark is only
+    /// returned while the view it was set in has focus.
+    pub fn mark(&self, name: char) -> Option<Position> {
+        if is_global_mark(name) {
+            return self.global_mark(name)
+                .filter(|&(view_id, _)| view_id == self.state.view_id)
+                .map(|(_, position)| position);
+        }
+        self.state.marks.get(&name).cloned()
+    }
+
+    /// The view and position of a global mark, `A`-`Z` or `0`-`9`.
+    pub fn global_mark(&self, name: char) -> Option<(ViewId, Position)> {
+        self.global_marks.get(&name).cloned()
+    }
+
+    /// Set a mark in the focused view. Setting a global mark moves it from
+    /// whichever view it was in.
+    pub fn set_mark(&mut self, name: char, position: Position) {
+        if is_global_mark(name) {
+            self.global_marks.insert(name, (self.state.view_id, position));
+        } else {
+            self.state.marks.insert(name, position);
+        }
+    }
+
+    /// The text typed during the last insert in the focused view.
+    pub fn last_insert(&self) -> Option<&str> {
+        self.state.last_insert.as_ref().map(|s| s.as_str())
     }
 
     pub fn mode(&self) -> &'static str {
         self.mode.name()
     }
+
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Set an option for every view, as with `:set`.
+    pub fn set_option(
+        &mut self,
+        name: &str,
+        value: OptionValue,
+    ) -> Result<(), OptionError> {
+        self.options.set(name, value)
+    }
+
+    /// Take on the state of `other`, e.g. a machine deserialized from a
+    /// snapshot, keeping the current subscribers.
+    pub fn restore(&mut self, mut other: StateMachine<K>) {
+        mem::swap(&mut self.observers, &mut other.observers);
+        mem::swap(&mut self.keymaps, &mut other.keymaps);
+        *self = other;
+        self.rebuild_maps();
+    }
+
+    pub fn mode_info(&self) -> ModeInfo {
+        self.mode.info(&self.state)
+    }
+
+    /// Returns true if a command has been started but not yet completed.
+    pub fn is_pending(&self) -> bool {
+        let mode_pending = match self.mode {
+            Mode::Normal(ref normal) => normal.char_op.is_some(),
+            Mode::Pending(_) => true,
+            Mode::Insert(_) => false,
+        };
+        mode_pending || !self.state.typeahead.is_empty() ||
+            self.state.count != 1 || self.state.register.is_some()
+    }
+
+    /// Returns true if the keys typed so far are the start of a mapping in
+    /// the current mode, which `timeout` would resolve.
+    pub fn awaits_mapping(&self) -> bool {
+        let typeahead = &self.state.typeahead;
+        if self.state.timed_out || typeahead.is_empty() {
+            return false;
+        }
+        match self.mode {
+            Mode::Normal(ref normal) => {
+                normal.char_op.is_none() &&
+                    self.state.normal_mode_map.awaits_remap(typeahead)
+            }
+            Mode::Pending(_) => {
+                self.state.pending_mode_map.awaits_remap(typeahead)
+            }
+            Mode::Insert(_) => {
+                self.state.insert_mode_map.awaits_remap(typeahead)
+            }
+        }
+    }
+
+    /// The keys waiting to be processed, e.g. the start of a mapping.
+    pub fn pending_keys(&self) -> Vec<K> {
+        self.state.typeahead.value_iter().collect()
+    }
+
+    /// Describe the command currently being typed.
+    pub fn showcmd(&self) -> ShowCmd<K> {
+        ShowCmd {
+            count: if self.state.count != 1 {
+                Some(self.state.count)
+            } else {
+                None
+            },
+            register: self.state.register,
+            operator: match self.mode {
+                Mode::Pending(ref pending) => Some(pending.operator),
+                _ => None,
+            },
+            pending: self.state.typeahead.value_iter().collect(),
+            typed: self.typed.clone(),
+        }
+    }
+
+    /// Lists the mappings which could complete the pending keys in the
+    /// current mode, as the remaining keys and a description of each target.
+    pub fn continuations(&self) -> Vec<(Vec<K>, String)>
+    where
+        K: fmt::Display,
+    {
+        let prefix: Vec<K> = self.state.typeahead.value_iter().collect();
+        match self.mode {
+            Mode::Normal(ref normal) => {
+                if normal.char_op.is_some() {
+                    Vec::new()
+                } else {
+                    describe(&self.state.normal_mode_map, &prefix)
+                }
+            }
+            Mode::Pending(_) => describe(&self.state.pending_mode_map, &prefix),
+            Mode::Insert(_) => describe(&self.state.insert_mode_map, &prefix),
+        }
+    }
+
+    fn run_typeahead<F, E>(
+        &mut self,
+        apply: &mut F,
+        held: &mut Option<Effect>,
+    ) -> Result<(), E>
+    where
+        F: FnMut(&Effect, &TextQuery) -> Result<(), E>,
+    {
+        let result = self.run(apply, held);
+        if !self.is_pending() {
+            self.typed.clear();
+        }
+        result
+    }
+
+    /// Transition until the typeahead buffer is exhausted or no further
+    /// progress can be made without more keys. Effects which may merge with
+    /// the next are left in `held`.
+    fn run<F, E>(
+        &mut self,
+        apply: &mut F,
+        held: &mut Option<Effect>,
+    ) -> Result<(), E>
+    where
+        F: FnMut(&Effect, &TextQuery) -> Result<(), E>,
+    {
+        loop {
+            let len = self.state.typeahead.len();
+            let mode = self.mode;
+            let info = self.mode_info();
+            self.mode = self.mode.transition(&mut self.state);
+            self.group_undo();
+            let new_info = self.mode_info();
+            let changed = new_info != info;
+            if changed {
+                self.state.emit(Effect::ModeChange(new_info.clone()));
+            }
+            let effects = mem::replace(&mut self.state.effects, Vec::new());
+            let mut result = Ok(());
+            let mut applied = 0;
+            for effect in &effects {
+                result = self.hold(apply, held, effect.clone());
+                if result.is_err() {
+                    break;
+                }
+                applied += 1;
+            }
+            if result.is_ok() && !self.state.events.is_empty() {
+                // Observers see the effects before what they led to.
+                result = self.flush(apply, held);
+            }
+            if let Err(err) = result {
+                // The undo group may have been started by an effect which
+                // was never applied.
+                let started = !effects[applied..]
+                    .contains(&Effect::StartUndoGroup);
+                self.abort(apply, mode, info, started);
+                return Err(err);
+            }
+            if changed {
+                self.mode_changed(info, new_info);
+            }
+            self.deliver_events();
+            if self.state.typeahead.is_empty() ||
+                (!changed && self.state.typeahead.len() == len)
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Start an undo group before the first edit, and end it once back in
+    /// Normal mode, so that a command, a whole insert or a macro is undone
+    /// at once.
+    fn group_undo(&mut self) {
+        if let Some(rest) = self.state.macro_rest {
+            if self.state.typeahead.len() <= rest {
+                self.state.macro_rest = None;
+            }
+        }
+        if !self.state.undo_group {
+            let effects = &mut self.state.effects;
+            if let Some(i) = effects.iter().position(Effect::is_edit) {
+                effects.insert(i, Effect::StartUndoGroup);
+                self.state.undo_group = true;
+            }
+        }
+        let done = match self.mode {
+            Mode::Normal(ref normal) => {
+                normal.next_mode == NextMode::Normal && normal.char_op.is_none()
+            }
+            _ => false,
+        };
+        if done {
+            self.state.end_undo_group();
+        }
+    }
+
+    /// Merge `effect` into the one held back, if it can be, or else apply
+    /// that one and hold back or apply `effect`.
+    fn hold<F, E>(
+        &self,
+        apply: &mut F,
+        held: &mut Option<Effect>,
+        effect: Effect,
+    ) -> Result<(), E>
+    where
+        F: FnMut(&Effect, &TextQuery) -> Result<(), E>,
+    {
+        if let Some(ref mut last) = *held {
+            if last.merge(&effect) {
+                return Ok(());
+            }
+        }
+        self.flush(apply, held)?;
+        if effect.is_mergeable() {
+            *held = Some(effect);
+            Ok(())
+        } else {
+            apply(&effect, self)
+        }
+    }
+
+    fn flush<F, E>(
+        &self,
+        apply: &mut F,
+        held: &mut Option<Effect>,
+    ) -> Result<(), E>
+    where
+        F: FnMut(&Effect, &TextQuery) -> Result<(), E>,
+    {
+        match held.take() {
+            Some(effect) => apply(&effect, self),
+            None => Ok(()),
+        }
+    }
+
+    /// Apply the effect held back once there are no more keys, abandoning
+    /// the command in progress if that fails.
+    fn release<F, E>(
+        &mut self,
+        apply: &mut F,
+        held: &mut Option<Effect>,
+    ) -> Result<(), E>
+    where
+        F: FnMut(&Effect, &TextQuery) -> Result<(), E>,
+    {
+        if let Err(err) = self.flush(apply, held) {
+            let (mode, info) = (self.mode, self.mode_info());
+            self.abort(apply, mode, info, true);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Abandon the command in progress, returning to `mode`, whose info was
+    /// `info`. A pending operator is dropped entirely, and the undo group is
+    /// ended if `started`, i.e. `Effect::StartUndoGroup` was applied.
+    fn abort<F, E>(
+        &mut self,
+        apply: &mut F,
+        mode: Mode<K>,
+        info: ModeInfo,
+        started: bool,
+    ) where
+        F: FnMut(&Effect, &TextQuery) -> Result<(), E>,
+    {
+        self.state.effects.clear();
+        self.state.events.clear();
+        self.state.changing = false;
+        self.state.cancel();
+        self.state.macro_rest = None;
+        if self.state.undo_group {
+            self.state.undo_group = false;
+            if started {
+                // Already failing, so a failure to end it changes nothing.
+                let _ = apply(&Effect::EndUndoGroup, self);
+            }
+        }
+        self.mode = match mode {
+            Mode::Normal(normal_mode) => next(normal_mode.next_mode),
+            Mode::Pending(_) => normal(),
+            Mode::Insert(_) => mode,
+        };
+        let new_info = self.mode_info();
+        if new_info != info {
+            self.mode_changed(info, new_info);
+            self.deliver_events();
+        }
+    }
+
+    fn mode_changed(&mut self, old: ModeInfo, new: ModeInfo) {
+        let was_insert = old.base == BaseMode::Insert;
+        let is_insert = new.base == BaseMode::Insert;
+        self.state.notify(Event::ModeChanged(old, new));
+        if is_insert && !was_insert {
+            self.state.inserted.clear();
+            self.state.notify(Event::InsertEnter);
+        } else if was_insert && !is_insert {
+            self.state.last_insert = Some(mem::replace(
+                &mut self.state.inserted,
+                String::new(),
+            ));
+            self.state.notify(Event::InsertLeave);
+        }
+    }
+
+    fn deliver_events(&mut self) {
+        for event in mem::replace(&mut self.state.events, Vec::new()) {
+            self.observers.notify(&event);
+        }
+    }
+
+    fn view_lines(&self, view_id: ViewId) -> Option<&LineCache> {
+        if view_id == self.state.view_id {
+            return Some(&self.state.lines);
+        }
+        self.views
+            .iter()
+            .find(|view| view.id == view_id)
+            .map(|view| &view.lines)
+    }
+
+    /// Take the editing context of the focused view out of the machine.
+    fn park(&mut self) -> View<K> {
+        View {
+            id: self.state.view_id,
+            mode: mem::replace(&mut self.mode, normal()),
+            typed: mem::replace(&mut self.typed, Vec::new()),
+            typeahead: mem::replace(
+                &mut self.state.typeahead,
+                Typeahead::new(),
+            ),
+            count: mem::replace(&mut self.state.count, 1),
+            register: self.state.register.take(),
+            marks: mem::replace(&mut self.state.marks, BTreeMap::new()),
+            local_maps: mem::replace(&mut self.state.local_maps, Vec::new()),
+            inserted: mem::replace(&mut self.state.inserted, String::new()),
+            typed_on_line: mem::replace(&mut self.state.typed_on_line, 0),
+            last_insert: self.state.last_insert.take(),
+            last_change: self.state.last_change.take(),
+            lines: mem::replace(&mut self.state.lines, LineCache::new()),
+            cursor: self.state.cursor.take(),
+            selections: mem::replace(&mut self.state.selections, Vec::new()),
+            changing: mem::replace(&mut self.state.changing, false),
+        }
+    }
+
+    /// Focus `view`, restoring its editing context.
+    fn unpark(&mut self, view: View<K>) {
+        self.state.view_id = view.id;
+        self.mode = view.mode;
+        self.typed = view.typed;
+        self.state.typeahead = view.typeahead;
+        self.state.count = view.count;
+        self.state.register = view.register;
+        self.state.marks = view.marks;
+        self.state.local_maps = view.local_maps;
+        self.state.inserted = view.inserted;
+        self.state.typed_on_line = view.typed_on_line;
+        self.state.last_insert = view.last_insert;
+        self.state.last_change = view.last_change;
+        self.state.lines = view.lines;
+        self.state.cursor = view.cursor;
+        self.state.selections = view.selections;
+        self.state.changing = view.changing;
+        self.rebuild_maps();
+    }
+
+    /// Layer the session's mappings, then the focused view's buffer-local
+    /// ones, over the shared keymaps.
+    fn rebuild_maps(&mut self) {
+        let local = &self.state.local_maps;
+        self.state.normal_mode_map = layer(
+            self.keymaps.normal(),
+            &self.maps.normal,
+            MapMode::Normal,
+            local,
+        );
+        self.state.pending_mode_map = layer(
+            self.keymaps.pending(),
+            &self.maps.pending,
+            MapMode::Pending,
+            local,
+        );
+        self.state.insert_mode_map = layer(
+            self.keymaps.insert(),
+            &self.maps.insert,
+            MapMode::Insert,
+            local,
+        );
+    }
+}
+
+/// Returns true if mark `name` is shared by all views, as Vim's file marks
+/// and the marks it saves on exit.
+fn is_global_mark(name: char) -> bool {
+    name.is_ascii_uppercase() || name.is_ascii_digit()
+}
+
+/// Layer `session` mappings, then the `local` remaps for `mode`, over a
+/// shared table, which is looked up after them rather than copied. Without
+/// any, the shared table is used as it is.
+fn layer<K, Op>(
+    shared: &Arc<ModeMap<K, Op>>,
+    session: &ModeMap<K, Op>,
+    mode: MapMode,
+    local: &[(MapMode, Vec<K>, Vec<K>)],
+) -> Arc<ModeMap<K, Op>>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+    Op: Copy,
+{
+    let mut local = local.iter().filter(|&&(m, _, _)| m == mode).peekable();
+    if session.is_empty() && local.peek().is_none() {
+        return shared.clone();
+    }
+    let mut map = ModeMap::layered(shared.clone());
+    map.extend(session);
+    for &(_, ref lhs, ref rhs) in local {
+        map.insert_remap(lhs.clone(), rhs.clone());
+    }
+    Arc::new(map)
+}
+
+/// Answers queries from the line caches, as far as core has sent them.
+impl<K> TextQuery for StateMachine<K>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+{
+    fn line_count(&self, view_id: ViewId) -> u64 {
+        self.view_lines(view_id).map_or(0, |lines| lines.len() as u64)
+    }
+
+    fn line(&self, view_id: ViewId, line: u64) -> Option<String> {
+        self.view_lines(view_id)?
+            .line(line as usize)
+            .map(|line| String::from(line.content()))
+    }
+
+    fn cursor(&self, view_id: ViewId) -> Option<Position> {
+        if view_id == self.state.view_id {
+            return self.state.cursor;
+        }
+        self.views.iter().find(|view| view.id == view_id)?.cursor
+    }
+
+    fn selections(&self, view_id: ViewId) -> Vec<(Position, Position)> {
+        if view_id == self.state.view_id {
+            return self.state.selections.clone();
+        }
+        self.views
+            .iter()
+            .find(|view| view.id == view_id)
+            .map_or(Vec::new(), |view| view.selections.clone())
+    }
+
+    fn tab_size(&self, _view_id: ViewId) -> u64 {
+        self.options.tabstop
+    }
+
+    fn expand_tab(&self, _view_id: ViewId) -> bool {
+        self.options.expandtab
+    }
+}
+
+/// Pairs each continuation of `prefix` in `map` with a description.
+fn describe<K, Op>(
+    map: &ModeMap<K, Op>,
+    prefix: &Vec<K>,
+) -> Vec<(Vec<K>, String)>
+where
+    K: Ord,
+    K: Copy,
+    K: Parse,
+    K: fmt::Display,
+    Op: Copy,
+    Op: Describe,
+{
+    map.continuations(prefix)
+        .into_iter()
+        .map(|c| {
+            let description = match c.target {
+                Target::Remap(keys) => {
+                    keys.iter().map(|k| k.to_string()).collect()
+                }
+                Target::Op(op) => String::from(op.describe()),
+            };
+            (c.keys, description)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use key::MultiKey;
+    use key::parse::parse;
+    use maps;
+    use message::Message;
+    use mode_info::SubMode;
+    use op::{MotionOp, OperatorOp};
+    use std::sync::{Arc, Mutex};
+
+    fn machine() -> StateMachine<MultiKey> {
+        StateMachine::new(
+            maps::normal_mode_map(),
+            maps::pending_mode_map(),
+            maps::insert_mode_map(),
+        )
+    }
+
+    /// Process `keys`, failing to apply any deletion.
+    fn process_failing_deletes(
+        machine: &mut StateMachine<MultiKey>,
+        keys: &str,
+    ) -> Result<Vec<Effect>, Effect> {
+        let mut applied = Vec::new();
+        for key in parse(keys) {
+            machine.process_with(key, |effect, _| match *effect {
+                Effect::Delete(_) => Err(effect.clone()),
+                _ => {
+                    applied.push(effect.clone());
+                    Ok(())
+                }
+            })?;
+        }
+        Ok(applied)
+    }
+
+    #[test]
+    fn failed_delete_abandons_operator() {
+        let mut machine = machine();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        machine.subscribe(Box::new(move |e| sink.lock().unwrap().push(e.clone())));
+
+        assert_eq!(
+            Err(Effect::Delete(MotionOp::Word)),
+            process_failing_deletes(&mut machine, "2dwj")
+        );
+        assert_eq!("Normal", machine.mode());
+        assert!(!machine.is_pending());
+        // The yank never happened, and the trailing `j` was dropped.
+        let events = events.lock().unwrap();
+        assert!(!events.iter().any(|e| match *e {
+            Event::TextYanked(..) => true,
+            _ => false,
+        }));
+        assert_eq!(
+            Some(&Event::ModeChanged(
+                ModeInfo::new(
+                    BaseMode::Normal,
+                    SubMode::OperatorPending(OperatorOp::Delete),
+                    None,
+                ),
+                ModeInfo::new(BaseMode::Normal, SubMode::None, None),
+            )),
+            events.last()
+        );
+    }
+
+    #[test]
+    fn failed_backspace_stays_in_insert() {
+        let mut machine = machine();
+        assert_eq!(
+            Err(Effect::Delete(MotionOp::Backspace)),
+            process_failing_deletes(&mut machine, "ia<bs>b")
+        );
+        assert_eq!("Insert", machine.mode());
+        // The failure ended the undo group, so another is started.
+        assert_eq!(
+            Ok(vec![Effect::StartUndoGroup, Effect::InsertChar('c')]),
+            process_failing_deletes(&mut machine, "c")
+        );
+    }
+
+    #[test]
+    fn recursive_mapping_reports_e223() {
+        let mut normal_map = maps::normal_mode_map();
+        normal_map.insert_remap(parse("Q"), parse("gQ"));
+        normal_map.insert_remap(parse("gQ"), parse("Q"));
+        let mut machine = StateMachine::new(
+            normal_map,
+            maps::pending_mode_map(),
+            maps::insert_mode_map(),
+        );
+        assert_eq!(
+            vec![Effect::Message(Message::recursive_mapping())],
+            machine.process(parse("Q")[0])
+        );
+        assert!(!machine.is_pending());
+    }
+
+    #[test]
+    fn insert_mapping_waits_then_times_out() {
+        let mut machine = machine();
+        assert!(machine.map(MapMode::Insert, parse("jj"), parse("<Esc>")));
+        machine.process(parse("i")[0]);
+        assert!(machine.process(parse("j")[0]).is_empty());
+        assert!(machine.awaits_mapping());
+        assert_eq!(parse("j"), machine.pending_keys());
+        assert_eq!(
+            vec![Effect::StartUndoGroup, Effect::InsertChar('j')],
+            machine.timeout()
+        );
+        assert!(!machine.awaits_mapping());
+        assert!(machine.timeout().is_empty());
+
+        machine.process(parse("j")[0]);
+        machine.process(parse("j")[0]);
+        assert_eq!("Normal", machine.mode());
+    }
+
+    #[test]
+    fn timeout_takes_shortest_mapping() {
+        let mut machine = machine();
+        assert!(machine.map(MapMode::Normal, parse("Q"), parse("k")));
+        assert!(machine.map(MapMode::Normal, parse("QQQ"), parse("j")));
+        machine.process(parse("Q")[0]);
+        assert!(machine.process(parse("Q")[0]).is_empty());
+        assert_eq!(vec![Effect::MoveCount(MotionOp::Up, 2)], machine.timeout());
+        assert!(!machine.is_pending());
+    }
+
+    #[test]
+    fn dot_repeats_changes() {
+        let mut machine = machine();
+        let mut process = |keys: &str| machine.process_keys(&parse(keys));
+        let (start, end) = (Effect::StartUndoGroup, Effect::EndUndoGroup);
+        let delete = Effect::Delete(MotionOp::Word);
+        process("2dw");
+        assert_eq!(
+            vec![start.clone(), delete.clone(), delete.clone(), end.clone()],
+            process(".")
+        );
+        assert_eq!(5, process("3.").len());
+        assert_eq!(5, process(".").len());
+
+        process("cwab<Esc>");
+        assert_eq!(
+            vec![
+                start.clone(),
+                delete,
+                Effect::Insert(String::from("ab")),
+                end.clone(),
+            ],
+            process(".")
+        );
+        process("ic<CR><Esc>");
+        assert_eq!(
+            vec![
+                start.clone(),
+                Effect::Insert(String::from("c\n")),
+                end.clone(),
+            ],
+            process(".")
+        );
+
+        // Text deleted while inserting isn't repeated.
+        process("iab cd<C-w>e<CR>fg<C-u>h<Esc>");
+        assert_eq!(
+            vec![start, Effect::Insert(String::from("ab e\nh")), end],
+            process(".")
+        );
+    }
+
+    #[test]
+    fn dot_repeats_per_view() {
+        let mut machine = machine();
+        assert!(machine.add_view(ViewId(1)));
+        machine.process_keys(&parse("cwab<Esc>"));
+        assert!(machine.switch_view(ViewId(1)));
+        assert!(machine.process_keys(&parse(".")).is_empty());
+        machine.process_keys(&parse("x"));
+        assert!(machine.switch_view(ViewId(0)));
+        assert_eq!(
+            vec![
+                Effect::StartUndoGroup,
+                Effect::Delete(MotionOp::Word),
+                Effect::Insert(String::from("ab")),
+                Effect::EndUndoGroup,
+            ],
+            machine.process_keys(&parse("."))
+        );
+    }
+
+    #[test]
+    fn merges_effects_across_keys() {
+        let mut machine = machine();
+        machine.process(parse("i")[0]);
+        assert_eq!(
+            vec![
+                Effect::StartUndoGroup,
+                Effect::Insert(String::from("ab\nc")),
+                Effect::Delete(MotionOp::Backspace),
+                Effect::InsertChar('d'),
+            ],
+            machine.process_keys(&parse("ab<CR>c<BS>d"))
+        );
+
+        // A held back insertion which fails is abandoned like any other.
+        let mut applied = Vec::new();
+        let result = machine.process_keys_with(&parse("ef<Up>"), |e, _| {
+            match *e {
+                Effect::Insert(_) => Err(()),
+                _ => {
+                    applied.push(e.clone());
+                    Ok(())
+                }
+            }
+        });
+        assert_eq!(Err(()), result);
+        assert_eq!(vec![Effect::EndUndoGroup], applied);
+        assert_eq!("Insert", machine.mode());
+    }
+
+    #[test]
+    fn failure_before_undo_group_starts() {
+        let mut machine = machine();
+        let mut applied = Vec::new();
+        let result = machine.process_keys_with(&parse("ia"), |e, _| match *e {
+            Effect::StartUndoGroup => Err(()),
+            _ => {
+                applied.push(e.clone());
+                Ok(())
+            }
+        });
+        assert_eq!(Err(()), result);
+        // No group was started, so none is ended.
+        assert!(!applied.contains(&Effect::EndUndoGroup));
+        assert!(!machine.state.undo_group);
+    }
+
+    #[test]
+    fn sessions_share_keymaps() {
+        let mut keymaps = Keymaps::new(
+            maps::normal_mode_map(),
+            maps::pending_mode_map(),
+            maps::insert_mode_map(),
+        );
+        assert!(keymaps.map(MapMode::Normal, parse("Q"), parse("j")));
+        let mut first = StateMachine::with_keymaps(keymaps.clone());
+        let mut second = StateMachine::with_keymaps(keymaps.clone());
+        assert!(Arc::ptr_eq(keymaps.normal(), &second.state.normal_mode_map));
+
+        // A session's own mappings layer over only the mode they're in.
+        assert!(first.map(MapMode::Normal, parse("Q"), parse("k")));
+        assert!(!Arc::ptr_eq(keymaps.normal(), &first.state.normal_mode_map));
+        assert!(Arc::ptr_eq(keymaps.insert(), &first.state.insert_mode_map));
+        assert_eq!(
+            vec![Effect::Move(MotionOp::Up)],
+            first.process(parse("Q")[0])
+        );
+
+        // Changing the keymaps leaves sessions using them alone.
+        assert!(keymaps.map(MapMode::Normal, parse("Q"), parse("l")));
+        assert_eq!(
+            vec![Effect::Move(MotionOp::Down)],
+            second.process(parse("Q")[0])
+        );
+    }
 }
@@ -0,0 +1,397 @@
+use client;
+use effect::Effect;
+use error::VixiError;
+use futures::Future;
+use json_client::JsonClient;
+use key::MultiKey;
+use line_cache::Update;
+use mode_info::ModeInfo;
+use options::OptionValue;
+use serde_json;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::mem;
+use std::sync::{Arc, Mutex};
+use view::{MapMode, Position, ViewId};
+use vixi::{FeedFlags, Vixi};
+
+/// A line of a trace written by `Vixi::start_trace`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TraceEvent {
+    Snapshot(Value), // The state when recording started.
+    Key(MultiKey), // A key passed to the state machine.
+    Keys(Vec<MultiKey>), // Keys given to `Vixi::process`.
+    Timeout, // A pending mapping timed out.
+    Feed(Vec<MultiKey>, FeedFlags), // Keys given to `Vixi::feed_keys`.
+    Update(ViewId, Update), // An update given to `Vixi::update`.
+    AddView(ViewId), // A view added, or opened with `Vixi::new_view`.
+    SwitchView(ViewId),
+    CloseView(ViewId),
+    Map(MapMode, String, String), // Mode, lhs and rhs of `Vixi::map`.
+    MapLocal(MapMode, String, String), // Likewise, for `Vixi::map_local`.
+    SetOption(String, OptionValue),
+    SetMark(char, Position),
+    Mode(ModeInfo), // The mode changed.
+    Call(Value), // A client call, as `JsonClient` would send it.
+    Error(String), // Processing failed, with this message.
+}
+
+/// Writes trace events to `out`, one JSON object per line.
+pub struct Recorder {
+    out: Box<Write + Send>,
+}
+
+impl Recorder {
+    pub fn new(out: Box<Write + Send>) -> Self {
+        Recorder { out: out }
+    }
+
+    pub fn record(&mut self, event: &TraceEvent) -> io::Result<()> {
+        serde_json::to_writer(&mut self.out, event)?;
+        self.out.write_all(b"\n")
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Keeps what is written to it, for a replay to read back its own trace.
+#[derive(Clone)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Captured {
+    fn new() -> Self {
+        Captured(Arc::new(Mutex::new(Vec::new())))
+    }
+
+    /// Take what has been written so far.
+    fn take(&self) -> Vec<u8> {
+        mem::replace(&mut *self.0.lock().unwrap(), Vec::new())
+    }
+
+    /// Take the events written so far.
+    fn take_events(&self) -> Vec<TraceEvent> {
+        self.take()
+            .split(|&b| b == b'\n')
+            .filter_map(|line| serde_json::from_slice(line).ok())
+            .collect()
+    }
+}
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The events traced for an effect applied to `view_id`.
+pub fn effect_events(view_id: ViewId, effect: &Effect) -> Vec<TraceEvent> {
+    match *effect {
+        Effect::ModeChange(ref info) => vec![TraceEvent::Mode(info.clone())],
+        Effect::Message(_) => Vec::new(),
+        _ => {
+            let mut calls = JsonClient::new();
+            for request in client::apply(&mut calls, view_id, effect) {
+                let _ = request.wait(); // `JsonClient` never fails.
+            }
+            calls.drain().into_iter().map(TraceEvent::Call).collect()
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(io::Error),
+    Parse(usize, serde_json::Error), // Line number, from 1, and error.
+    Restore(usize, VixiError), // The snapshot on a line can't be restored.
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TraceError::Io(ref err) => write!(f, "{}", err),
+            TraceError::Parse(line, ref err) => {
+                write!(f, "line {}: {}", line, err)
+            }
+            TraceError::Restore(line, ref err) => {
+                write!(f, "line {}: {}", line, err)
+            }
+        }
+    }
+}
+
+impl Error for TraceError {
+    fn description(&self) -> &str {
+        match *self {
+            TraceError::Io(..) => "failed to read trace",
+            TraceError::Parse(..) => "invalid trace event",
+            TraceError::Restore(..) => "invalid snapshot in trace",
+        }
+    }
+}
+
+/// Where a replay first differed from its trace.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    pub line: usize, // Line of the trace, from 1.
+    pub expected: Option<TraceEvent>, // `None` if the trace had ended.
+    pub actual: Option<TraceEvent>, // `None` if the replay did nothing more.
+}
+
+/// Feed the keys and calls of a trace back through a fresh `Vixi`,
+/// comparing the resulting mode changes, client calls and errors with those
+/// recorded. Returns the first divergence, if any.
+pub fn replay<R: BufRead>(trace: R) -> Result<Option<Divergence>, TraceError> {
+    replay_with(Vixi::new(), trace)
+}
+
+/// Replay a trace through `vixi`, as `replay` does, e.g. once the commands
+/// and extensions the trace uses have been defined again.
+pub fn replay_with<R: BufRead>(
+    mut vixi: Vixi,
+    trace: R,
+) -> Result<Option<Divergence>, TraceError> {
+    // The replay traces itself, so that what it does is recorded just as
+    // the original was.
+    let captured = Captured::new();
+    vixi.start_trace(Box::new(captured.clone()))
+        .map_err(|err| TraceError::Restore(0, err))?;
+    let mut actual = VecDeque::new();
+    let mut line = 0;
+    for text in trace.lines() {
+        let text = text.map_err(TraceError::Io)?;
+        line += 1;
+        if text.trim().is_empty() {
+            continue;
+        }
+        let event = serde_json::from_str(&text)
+            .map_err(|err| TraceError::Parse(line, err))?;
+        match event {
+            TraceEvent::Snapshot(snapshot) => {
+                vixi.restore(snapshot)
+                    .map_err(|err| TraceError::Restore(line, err))?;
+            }
+            TraceEvent::Key(..) |
+            TraceEvent::Keys(..) |
+            TraceEvent::Timeout |
+            TraceEvent::Feed(..) |
+            TraceEvent::Update(..) |
+            TraceEvent::AddView(..) |
+            TraceEvent::SwitchView(..) |
+            TraceEvent::CloseView(..) |
+            TraceEvent::Map(..) |
+            TraceEvent::MapLocal(..) |
+            TraceEvent::SetOption(..) |
+            TraceEvent::SetMark(..) => {
+                if let Some(extra) = actual.pop_front() {
+                    return Ok(Some(Divergence {
+                        line: line,
+                        expected: None,
+                        actual: Some(extra),
+                    }));
+                }
+                captured.take();
+                // Failures are traced along with the rest.
+                let _ = match event {
+                    TraceEvent::Key(key) => vixi.process_key(key),
+                    TraceEvent::Keys(keys) => vixi.process_keys(keys),
+                    TraceEvent::Feed(keys, flags) => {
+                        vixi.feed_keys(keys, flags)
+                    }
+                    TraceEvent::Update(view_id, update) => {
+                        vixi.update(view_id, &update)
+                    }
+                    TraceEvent::AddView(view_id) => {
+                        vixi.add_view(view_id);
+                        Ok(Vec::new())
+                    }
+                    TraceEvent::SwitchView(view_id) => {
+                        vixi.switch_view(view_id).map(|_| Vec::new())
+                    }
+                    TraceEvent::CloseView(view_id) => {
+                        vixi.close_view(view_id).map(|_| Vec::new())
+                    }
+                    TraceEvent::Map(mode, lhs, rhs) => {
+                        vixi.map(mode, &lhs, &rhs);
+                        Ok(Vec::new())
+                    }
+                    TraceEvent::MapLocal(mode, lhs, rhs) => {
+                        vixi.map_local(mode, &lhs, &rhs);
+                        Ok(Vec::new())
+                    }
+                    TraceEvent::SetOption(name, value) => {
+                        vixi.set_option(&name, value).map(|_| Vec::new())
+                    }
+                    TraceEvent::SetMark(name, position) => {
+                        vixi.set_mark(name, position);
+                        Ok(Vec::new())
+                    }
+                    _ => vixi.timeout(),
+                };
+                // Skip the input itself.
+                actual.extend(captured.take_events().into_iter().skip(1));
+            }
+            expected => {
+                let next = actual.pop_front();
+                if next.as_ref() != Some(&expected) {
+                    return Ok(Some(Divergence {
+                        line: line,
+                        expected: Some(expected),
+                        actual: next,
+                    }));
+                }
+            }
+        }
+    }
+    Ok(actual.pop_front().map(|extra| {
+        Divergence {
+            line: line + 1,
+            expected: None,
+            actual: Some(extra),
+        }
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use view::MapMode;
+
+    fn record(keys: &str) -> String {
+        let out = Captured::new();
+        let mut vixi = Vixi::new();
+        assert!(vixi.map(MapMode::Normal, "Q", "2j"));
+        vixi.start_trace(Box::new(out.clone())).unwrap();
+        vixi.process(keys).unwrap();
+        vixi.stop_trace().unwrap();
+        String::from_utf8(out.take()).unwrap()
+    }
+
+    #[test]
+    fn records_keys_modes_and_calls() {
+        let trace = record("Qia");
+        let events: Vec<TraceEvent> = trace
+            .lines()
+            .skip(1)
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(5, events.len());
+        match events[1] {
+            TraceEvent::Call(ref call) => {
+                assert_eq!("move_down", call["params"]["method"]);
+            }
+            ref event => panic!("expected a call, got {:?}", event),
+        }
+        match events[3] {
+            TraceEvent::Mode(ref info) => {
+                assert_eq!("-- INSERT --", info.showmode);
+            }
+            ref event => panic!("expected a mode, got {:?}", event),
+        }
+    }
+
+    #[test]
+    fn replay_matches() {
+        let trace = record("Qdwihello<Esc>");
+        assert_eq!(None, replay(trace.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn replay_finds_divergence() {
+        let trace = record("Qia").replacen("move_down", "move_up", 1);
+        let divergence = replay(trace.as_bytes()).unwrap().unwrap();
+        assert_eq!(3, divergence.line);
+        match divergence.actual {
+            Some(TraceEvent::Call(ref call)) => {
+                assert_eq!("move_down", call["params"]["method"]);
+            }
+            ref actual => panic!("expected a call, got {:?}", actual),
+        }
+    }
+
+    #[test]
+    fn replay_finds_missing_events() {
+        let trace = record("Qia");
+        let truncated: Vec<&str> = trace.lines().take(3).collect();
+        let divergence = replay(truncated.join("\n").as_bytes()).unwrap();
+        assert_eq!(4, divergence.unwrap().line);
+    }
+
+    #[test]
+    fn replays_timeouts() {
+        let out = Captured::new();
+        let mut vixi = Vixi::new();
+        assert!(vixi.map(MapMode::Insert, "jj", "<Esc>"));
+        vixi.start_trace(Box::new(out.clone())).unwrap();
+        vixi.process("ij").unwrap();
+        vixi.timeout().unwrap();
+        vixi.process("jj").unwrap();
+        vixi.stop_trace().unwrap();
+        let trace = String::from_utf8(out.take()).unwrap();
+        assert!(trace.contains("\"timeout\""));
+        assert_eq!(None, replay(trace.as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn replays_state_changes() {
+        let out = Captured::new();
+        let mut vixi = Vixi::new();
+        vixi.start_trace(Box::new(out.clone())).unwrap();
+        vixi.add_view(ViewId(1));
+        vixi.switch_view(ViewId(1)).unwrap();
+        assert!(vixi.map_local(MapMode::Normal, "Q", "2j"));
+        vixi.set_option("ts", OptionValue::Number(8)).unwrap();
+        vixi.set_mark('a', Position { line: 1, column: 0 });
+        let update = serde_json::from_value(json!({"ops": [
+            {"op": "ins", "n": 1, "lines": [{"text": "hi", "cursor": [2]}]},
+        ]})).unwrap();
+        assert_eq!(1, vixi.update(ViewId(1), &update).unwrap().len());
+        vixi.process("Q").unwrap();
+        assert!(vixi.close_view(ViewId(0)).is_ok());
+        vixi.stop_trace().unwrap();
+        let trace = String::from_utf8(out.take()).unwrap();
+        assert_eq!(None, replay(trace.as_bytes()).unwrap());
+        // Dropping the mapping makes `Q` do nothing.
+        let unmapped: Vec<&str> =
+            trace.lines().filter(|line| !line.contains("map_local")).collect();
+        let divergence = replay(unmapped.join("\n").as_bytes()).unwrap();
+        assert!(divergence.is_some());
+    }
+
+    /// A `Vixi` with `Q` bound to a command which always fails.
+    fn failing() -> Vixi {
+        let mut vixi = Vixi::new();
+        vixi.define_command("fail", |_| Err(String::from("no")));
+        assert!(vixi.map_command(MapMode::Normal, "Q", "fail"));
+        vixi
+    }
+
+    #[test]
+    fn replays_errors() {
+        let out = Captured::new();
+        let mut vixi = failing();
+        vixi.start_trace(Box::new(out.clone())).unwrap();
+        assert!(vixi.process("jQ").is_err());
+        vixi.stop_trace().unwrap();
+        let trace = String::from_utf8(out.take()).unwrap();
+        match serde_json::from_str(trace.lines().last().unwrap()).unwrap() {
+            TraceEvent::Error(ref message) => assert!(message.contains("no")),
+            event => panic!("expected an error, got {:?}", event),
+        }
+        assert_eq!(None, replay_with(failing(), trace.as_bytes()).unwrap());
+        // Without the command, replaying fails differently.
+        let divergence = replay(trace.as_bytes()).unwrap().unwrap();
+        match (divergence.expected, divergence.actual) {
+            (Some(TraceEvent::Error(_)), Some(TraceEvent::Error(_))) => {}
+            other => panic!("expected two errors, got {:?}", other),
+        }
+    }
+}
@@ -8,7 +8,7 @@ pub trait Parse {
     fn character(&self) -> Option<char>;
 }
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum RemapType {
     NotRelavant, // For Op mapping.
     Remap, // Recursively mappable keys.
@@ -17,7 +17,7 @@ pub enum RemapType {
     Abbreviation, // Don't remap, apply abbreviations.
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Typeahead<K>
 where
     K: Ord,
@@ -142,22 +142,4 @@ where
         }
         return Match::PartialMatch;
     }
-
-    pub fn parse_string(&mut self) -> String {
-        let mut s = String::with_capacity(self.len());
-        for key in self.value_iter() {
-            match key.character() {
-                Some(c) => {
-                    s.push(c);
-                }
-                None => {
-                    break;
-                }
-            }
-        }
-        for i in 0..s.len() {
-            self.pop_front();
-        }
-        return s;
-    }
 }
@@ -0,0 +1,21 @@
+/// Identifies a view, i.e. an open buffer in the editor.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize,
+         Deserialize)]
+pub struct ViewId(pub u64);
+
+/// A position in a buffer, counting lines and columns from zero.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize,
+         Deserialize)]
+pub struct Position {
+    pub line: u64,
+    pub column: u64,
+}
+
+/// The mode a mapping applies in, as with `:nmap`, `:omap` and `:imap`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MapMode {
+    Normal,
+    Pending,
+    Insert,
+}
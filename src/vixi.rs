@@ -1,92 +1,1897 @@
 use client;
-use maps;
-use key::MultiKey;
+use clock::{Clock, SystemClock};
+use command::{Commands, CommandResult, Context};
+use effect::Effect;
+use error::VixiError;
+use event::{Event, SubscriptionId};
+use extension::{Extensions, Motion, Operator, Origin, TextObject,
+                motion_range, text_in};
+use futures::Future;
+use keymaps::Keymaps;
+use message::{DEFAULT_HISTORY, Message, Messages};
+use mode_info::ModeInfo;
+use op::{MotionId, MotionOp, ObjectId, ObjectOp, OperatorId, OperatorOp,
+         Span};
+use options::{OptionValue, Options};
+use serde_json;
+use serde_json::Value;
+use showcmd::ShowCmd;
+use key::{Key, MultiKey};
 use key::parse::parse;
+use line_cache::{LineCache, Update};
 use state_machine::StateMachine;
+use typeahead::RemapType;
+use std::io::Write;
+use std::time::{Duration, Instant};
+use trace::{Recorder, TraceEvent, effect_events};
+use view::{MapMode, Position, ViewId};
+
+/// Version of the format written by `Vixi::snapshot`.
+const SNAPSHOT_VERSION: u64 = 1;
+
+/// How `Vixi::feedkeys` queues keys, after the flags of Vim's `feedkeys()`.
+/// By default keys are remapped, appended, handled as if they came from a
+/// mapping and processed along with the next key.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FeedFlags {
+    pub noremap: bool, // 'n': Don't remap the keys.
+    pub insert: bool, // 'i': Insert before keys waiting to be processed.
+    pub typed: bool, // 't': Handle the keys as if typed.
+    pub execute: bool, // 'x': Process the keys right away.
+}
+
+impl FeedFlags {
+    /// Parse flags as given to Vim's `feedkeys()`, e.g. `"nx"`. Other
+    /// letters, including 'm' for remapping, are ignored.
+    pub fn parse(flags: &str) -> Self {
+        FeedFlags {
+            noremap: flags.contains('n'),
+            insert: flags.contains('i'),
+            typed: flags.contains('t'),
+            execute: flags.contains('x'),
+        }
+    }
+}
+
+/// What `Vixi::step` processes.
+enum Input {
+    Key(MultiKey),
+    Keys(Vec<MultiKey>),
+    Timeout,
+    Feed(Vec<MultiKey>, FeedFlags),
+}
+
+impl Input {
+    /// How the input is traced.
+    fn event(self) -> TraceEvent {
+        match self {
+            Input::Key(key) => TraceEvent::Key(key),
+            Input::Keys(keys) => TraceEvent::Keys(keys),
+            Input::Timeout => TraceEvent::Timeout,
+            Input::Feed(keys, flags) => TraceEvent::Feed(keys, flags),
+        }
+    }
+}
 
 pub struct Vixi {
     machine: StateMachine<MultiKey>,
+    client: Option<Box<client::Client + Send>>, // Applies effects, if present.
+    applier: client::Applier, // Forwards effects to `client`.
+    messages: Messages,
+    text: Option<Box<client::TextQuery + Send>>, // Reads text, if present.
+    trace: Option<Recorder>, // Records keys and what they did, if present.
+    clock: Box<Clock + Send>, // Tells when keys arrive.
+    deadline: Option<Instant>, // When the pending mapping times out.
+    commands: Commands, // Defined by the owner, to be bound to keys.
+    extensions: Extensions, // Likewise, motions, objects and operators.
 }
 
 impl Vixi {
-    pub fn new(client: Box<client::Client>) -> Self {
+    /// Create a `Vixi` which leaves applying effects to its owner, with
+    /// mappings of its own.
+    pub fn new() -> Self {
+        Vixi::with_keymaps(Keymaps::default())
+    }
+
+    /// Create a `Vixi` sharing `keymaps` with other sessions, rather than
+    /// building mappings of its own. Its own mappings, from `map` and the
+    /// like, are layered on top.
+    pub fn with_keymaps(keymaps: Keymaps<MultiKey>) -> Self {
         Vixi {
-            machine: StateMachine::new(
-                client,
-                maps::normal_mode_map(),
-                maps::pending_mode_map(),
-                maps::insert_mode_map(),
-            ),
+            machine: StateMachine::with_keymaps(keymaps),
+            client: None,
+            applier: client::Applier::new(),
+            messages: Messages::new(DEFAULT_HISTORY),
+            text: None,
+            trace: None,
+            clock: Box::new(SystemClock),
+            deadline: None,
+            commands: Commands::new(),
+            extensions: Extensions::new(),
         }
     }
 
-    pub fn process(&mut self, keys: &str) {
-        for key in parse(keys) {
-            self.machine.process(key);
+    /// Create a `Vixi` which applies effects to `client` as they occur.
+    pub fn with_client(client: Box<client::Client + Send>) -> Self {
+        let mut vixi = Vixi::new();
+        vixi.set_client(client);
+        vixi
+    }
+
+    /// Apply effects to `client` as they occur, from now on.
+    pub fn set_client(&mut self, client: Box<client::Client + Send>) {
+        self.client = Some(client);
+    }
+
+    /// Read text from `text`, rather than from what xi-core has sent through
+    /// `update`.
+    pub fn set_text_query(&mut self, text: Box<client::TextQuery + Send>) {
+        self.text = Some(text);
+    }
+
+    /// Tell the time with `clock` rather than the system's clock, e.g. to
+    /// test timeouts.
+    pub fn set_clock(&mut self, clock: Box<Clock + Send>) {
+        self.clock = clock;
+    }
+
+    /// Read access to the text of every view.
+    pub fn text(&self) -> &client::TextQuery {
+        match self.text {
+            Some(ref text) => &**text,
+            None => &self.machine,
         }
     }
 
+    /// Process a string of keys in Vim's key notation, returning the
+    /// resulting effects in the order they should be applied.
+    ///
+    /// With a client, each effect's requests are driven to completion in
+    /// order before the next effect is produced. Consecutive inserted
+    /// characters and repeated motions, as from a paste or `50j`, become a
+    /// single effect and so as few requests as the client allows. The first
+    /// failure abandons the command in progress along with any remaining
+    /// keys, and is returned as an error; effects before it have already
+    /// been applied.
+    ///
+    /// Messages produced along the way, including the error, are also
+    /// queued for `drain_messages`.
+    pub fn process(&mut self, keys: &str) -> Result<Vec<Effect>, VixiError> {
+        self.process_keys(parse(keys))
+    }
+
+    /// Process decoded keys, as `process` does.
+    pub fn process_keys(
+        &mut self,
+        keys: Vec<MultiKey>,
+    ) -> Result<Vec<Effect>, VixiError> {
+        self.step(Input::Keys(keys))
+    }
+
+    /// Process a single decoded key, as `process` does.
+    pub fn process_key(
+        &mut self,
+        key: MultiKey,
+    ) -> Result<Vec<Effect>, VixiError> {
+        self.step(Input::Key(key))
+    }
+
+    /// Queue keys in Vim's key notation as Vim's `feedkeys()` does, for
+    /// hosts and plugins to drive vixi. Unless `flags.execute` is set, the
+    /// keys are left for the next key, `tick` or executing feed to process,
+    /// and no effects are returned.
+    pub fn feedkeys(
+        &mut self,
+        keys: &str,
+        flags: FeedFlags,
+    ) -> Result<Vec<Effect>, VixiError> {
+        self.feed_keys(parse(keys), flags)
+    }
+
+    /// Queue decoded keys, as `feedkeys` does.
+    pub fn feed_keys(
+        &mut self,
+        keys: Vec<MultiKey>,
+        flags: FeedFlags,
+    ) -> Result<Vec<Effect>, VixiError> {
+        self.step(Input::Feed(keys, flags))
+    }
+
+    /// When the keys typed so far, being the start of a mapping, time out
+    /// as set by 'timeout', 'timeoutlen' and 'ttimeoutlen'. Frontends
+    /// should call `tick` once it has passed. Updated by every key.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Resolve a pending mapping if its deadline is at or before `now`:
+    /// the keys typed so far become their shortest mapping, or are taken
+    /// literally, e.g. inserted in Insert mode. Returns the resulting effects
+    /// as `process` does; there are none before the deadline.
+    pub fn tick(&mut self, now: Instant) -> Result<Vec<Effect>, VixiError> {
+        match self.deadline {
+            Some(deadline) if deadline <= now => self.timeout(),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Resolve a pending mapping now, whatever its deadline, as `tick` does.
+    pub fn timeout(&mut self) -> Result<Vec<Effect>, VixiError> {
+        self.step(Input::Timeout)
+    }
+
+    /// Start writing a trace to `out`: a snapshot, then each key processed
+    /// and call changing vixi's state, e.g. `update` or `map`, along with
+    /// the mode changes, client calls and error it led to, one JSON object
+    /// per line. Replay it with `trace::replay`.
+    ///
+    /// Client calls are recorded as `JsonClient` would send them, whether or
+    /// not there is a client. A failure to write ends the trace with an
+    /// error message.
+    pub fn start_trace(
+        &mut self,
+        out: Box<Write + Send>,
+    ) -> Result<(), VixiError> {
+        let mut recorder = Recorder::new(out);
+        let snapshot = TraceEvent::Snapshot(self.snapshot()?);
+        recorder.record(&snapshot).map_err(VixiError::Trace)?;
+        self.trace = Some(recorder);
+        Ok(())
+    }
+
+    /// Stop tracing, flushing what has been written.
+    pub fn stop_trace(&mut self) -> Result<(), VixiError> {
+        match self.trace.take() {
+            Some(mut recorder) => recorder.flush().map_err(VixiError::Trace),
+            None => Ok(()),
+        }
+    }
+
+    /// Capture the state of every view as JSON: modes, half-typed commands,
+    /// counts, mappings, registers, marks and options. The client, text
+    /// query, subscribers and messages are not included.
+    pub fn snapshot(&self) -> Result<Value, VixiError> {
+        let machine =
+            serde_json::to_value(&self.machine).map_err(VixiError::Snapshot)?;
+        Ok(json!({"version": SNAPSHOT_VERSION, "machine": machine}))
+    }
+
+    /// Return to the state captured by `snapshot`, possibly in another
+    /// process. Fails, leaving vixi as it was, if the snapshot is invalid.
+    pub fn restore(&mut self, mut snapshot: Value) -> Result<(), VixiError> {
+        match snapshot["version"].as_u64() {
+            Some(SNAPSHOT_VERSION) => {}
+            version => {
+                return Err(VixiError::SnapshotVersion(version));
+            }
+        }
+        let machine = serde_json::from_value(snapshot["machine"].take())
+            .map_err(VixiError::Snapshot)?;
+        self.machine.restore(machine);
+        self.deadline = self.next_deadline();
+        Ok(())
+    }
+
+    /// Returns the messages produced since the last call, oldest first.
+    pub fn drain_messages(&mut self) -> Vec<Message> {
+        self.messages.drain_new()
+    }
+
+    /// Returns the remembered messages, oldest first, as with `:messages`.
+    pub fn messages(&self) -> Vec<Message> {
+        self.messages.iter().cloned().collect()
+    }
+
+    /// Forget all messages, as with `:messages clear`.
+    pub fn clear_messages(&mut self) {
+        self.messages.clear();
+    }
+
+    /// The focused view, which keys are applied to.
+    pub fn view_id(&self) -> ViewId {
+        self.machine.view_id()
+    }
+
+    /// Lists every view, the focused one first, then the rest from most to
+    /// least recently focused.
+    pub fn views(&self) -> Vec<ViewId> {
+        self.machine.views()
+    }
+
+    /// Open a view through the client and focus it.
+    pub fn new_view(
+        &mut self,
+        file_path: Option<String>,
+    ) -> Result<ViewId, VixiError> {
+        let view_id = match self.client {
+            Some(ref mut client) => {
+                client.new_view(file_path).wait().map_err(VixiError::NewView)?
+            }
+            None => {
+                return Err(VixiError::NoClient);
+            }
+        };
+        self.machine.add_view(view_id);
+        self.machine.switch_view(view_id);
+        self.traced_call(TraceEvent::AddView(view_id), &Ok(()));
+        self.traced_call(TraceEvent::SwitchView(view_id), &Ok(()));
+        Ok(view_id)
+    }
+
+    /// Start tracking a view opened by the frontend itself, without focusing
+    /// it. Returns false if the view is already known.
+    pub fn add_view(&mut self, view_id: ViewId) -> bool {
+        self.traced_call(TraceEvent::AddView(view_id), &Ok(()));
+        self.machine.add_view(view_id)
+    }
+
+    /// Focus a view, resuming whatever command was in progress there.
+    pub fn switch_view(&mut self, view_id: ViewId) -> Result<(), VixiError> {
+        let result = if self.machine.switch_view(view_id) {
+            Ok(())
+        } else {
+            Err(VixiError::UnknownView(view_id))
+        };
+        self.traced_call(TraceEvent::SwitchView(view_id), &result);
+        result
+    }
+
+    /// Close a view through the client, if any, and stop tracking it.
+    /// Closing the focused view focuses the one focused before it.
+    pub fn close_view(&mut self, view_id: ViewId) -> Result<(), VixiError> {
+        let result = self.close(view_id);
+        self.traced_call(TraceEvent::CloseView(view_id), &result);
+        result
+    }
+
+    fn close(&mut self, view_id: ViewId) -> Result<(), VixiError> {
+        if !self.machine.has_view(view_id) {
+            return Err(VixiError::UnknownView(view_id));
+        }
+        if self.machine.views().len() == 1 {
+            return Err(VixiError::LastView(view_id));
+        }
+        if let Some(ref mut client) = self.client {
+            client.close_view(view_id).wait().map_err(|err| {
+                VixiError::CloseView(view_id, err)
+            })?;
+        }
+        self.machine.close_view(view_id);
+        Ok(())
+    }
+
+    /// Apply the update of an xi-core `update` notification to a view,
+    /// returning any effects needed to keep its cursor where the current mode
+    /// allows, e.g. off the end of the line in Normal mode. With a client,
+    /// those effects have already been applied.
+    pub fn update(
+        &mut self,
+        view_id: ViewId,
+        update: &Update,
+    ) -> Result<Vec<Effect>, VixiError> {
+        let mut effects = Vec::new();
+        let mut result = Ok(());
+        match self.machine.update(view_id, update) {
+            Some(wanted) => {
+                for effect in wanted {
+                    result = apply(
+                        &mut self.client,
+                        &mut self.applier,
+                        &mut self.commands,
+                        &mut self.messages,
+                        view_id,
+                        &effect,
+                    );
+                    if result.is_err() {
+                        break;
+                    }
+                    effects.push(effect);
+                }
+            }
+            None => {
+                result = Err(VixiError::UnknownView(view_id));
+            }
+        }
+        if self.trace.is_some() {
+            let input = TraceEvent::Update(view_id, update.clone());
+            self.traced(input, view_id, &effects, result.as_ref().err());
+        }
+        result.map(|()| effects)
+    }
+
+    /// The cursor of the focused view, as last reported by xi-core.
+    pub fn cursor(&self) -> Option<Position> {
+        self.machine.cursor()
+    }
+
+    /// The selections of the focused view, as last reported by xi-core, one
+    /// span per line.
+    pub fn selections(&self) -> Vec<(Position, Position)> {
+        self.machine.selections().clone()
+    }
+
+    /// The contents of the focused view, as far as xi-core has sent them.
+    pub fn lines(&self) -> &LineCache {
+        self.machine.lines()
+    }
+
+    /// Map `lhs` to `rhs` in every view, both in Vim's key notation.
+    /// Returns false if the mapping is invalid.
+    pub fn map(&mut self, mode: MapMode, lhs: &str, rhs: &str) -> bool {
+        let input = TraceEvent::Map(mode, String::from(lhs), String::from(rhs));
+        self.traced_call(input, &Ok(()));
+        self.machine.map(mode, parse(lhs), parse(rhs))
+    }
+
+    /// Map `lhs` to `rhs` in the focused view only, taking precedence over
+    /// global mappings. Returns false if the mapping is invalid.
+    pub fn map_local(&mut self, mode: MapMode, lhs: &str, rhs: &str) -> bool {
+        let (l, r) = (String::from(lhs), String::from(rhs));
+        self.traced_call(TraceEvent::MapLocal(mode, l, r), &Ok(()));
+        self.machine.map_local(mode, parse(lhs), parse(rhs))
+    }
+
+    /// Define a command, replacing any other of the same name, for binding
+    /// with `map_command`. It is run with the count, register and view it
+    /// was invoked with, and the client if there is one. Returning an
+    /// error abandons the keys being processed, as a client failure does.
+    pub fn define_command<F>(&mut self, name: &str, command: F)
+    where
+        F: FnMut(&mut Context) -> CommandResult + Send + 'static,
+    {
+        self.commands.define(name, Box::new(command));
+    }
+
+    /// Bind `lhs` to the command called `name` in every view, alongside
+    /// the built-in commands. Returns false if there is no such command or
+    /// `lhs` is empty.
+    pub fn map_command(
+        &mut self,
+        mode: MapMode,
+        lhs: &str,
+        name: &str,
+    ) -> bool {
+        match self.commands.id(name) {
+            Some(id) => self.machine.map_command(mode, parse(lhs), id),
+            None => false,
+        }
+    }
+
+    /// Define a motion, replacing any other of the same name, for binding
+    /// with `map_motion`. It works on its own, after operators, with counts
+    /// and with `.`, reading the text through the text query.
+    pub fn define_motion<M>(&mut self, name: &str, motion: M)
+    where
+        M: Motion + Send + 'static,
+    {
+        self.extensions.motions.define(name, Box::new(motion));
+    }
+
+    /// Define a text object, as `define_motion` does a motion.
+    pub fn define_object<O>(&mut self, name: &str, object: O)
+    where
+        O: TextObject + Send + 'static,
+    {
+        self.extensions.objects.define(name, Box::new(object));
+    }
+
+    /// Define an operator, as `define_motion` does a motion. An error from
+    /// the operator abandons the keys being processed, as a client failure
+    /// does.
+    pub fn define_operator<O>(&mut self, name: &str, operator: O)
+    where
+        O: Operator + Send + 'static,
+    {
+        self.extensions.operators.define(name, Box::new(operator));
+    }
+
+    /// Bind `lhs` to the motion called `name` in every view. Returns false
+    /// if there is no such motion or `lhs` is empty.
+    pub fn map_motion(&mut self, lhs: &str, name: &str) -> bool {
+        match self.extensions.motions.id(name) {
+            Some(id) => {
+                let motion = MotionOp::Custom(MotionId(id));
+                self.machine.map_motion(parse(lhs), motion)
+            }
+            None => false,
+        }
+    }
+
+    /// Bind `lhs` to the text object called `name`, as `map_motion` does.
+    pub fn map_object(&mut self, lhs: &str, name: &str) -> bool {
+        match self.extensions.objects.id(name) {
+            Some(id) => {
+                let object = ObjectOp::Custom(ObjectId(id));
+                self.machine.map_object(parse(lhs), object)
+            }
+            None => false,
+        }
+    }
+
+    /// Bind `lhs` to the operator called `name`, as `map_motion` does.
+    pub fn map_operator(&mut self, lhs: &str, name: &str) -> bool {
+        match self.extensions.operators.id(name) {
+            Some(id) => {
+                let operator = OperatorOp::Custom(OperatorId(id));
+                self.machine.map_operator(parse(lhs), operator)
+            }
+            None => false,
+        }
+    }
+
+    /// Set an option by its full or short name, as with `:set`.
+    pub fn set_option(
+        &mut self,
+        name: &str,
+        value: OptionValue,
+    ) -> Result<(), VixiError> {
+        let result =
+            self.machine.set_option(name, value).map_err(VixiError::Option);
+        let input = TraceEvent::SetOption(String::from(name), value);
+        self.traced_call(input, &result);
+        result
+    }
+
+    pub fn options(&self) -> &Options {
+        self.machine.options()
+    }
+
+    /// The position of a mark in the focused view.
+    pub fn mark(&self, name: char) -> Option<Position> {
+        self.machine.mark(name)
+    }
+
+    /// The view and position of a global mark, `A`-`Z` or `0`-`9`.
+    pub fn global_mark(&self, name: char) -> Option<(ViewId, Position)> {
+        self.machine.global_mark(name)
+    }
+
+    /// Set a mark in the focused view, e.g. from the frontend's cursor.
+    pub fn set_mark(&mut self, name: char, position: Position) {
+        self.traced_call(TraceEvent::SetMark(name, position), &Ok(()));
+        self.machine.set_mark(name, position)
+    }
+
+    /// The text typed during the last insert in the focused view.
+    pub fn last_insert(&self) -> Option<String> {
+        self.machine.last_insert().map(String::from)
+    }
+
+    /// Register `observer` to be called with every event as keys are
+    /// processed.
+    pub fn subscribe(
+        &mut self,
+        observer: Box<FnMut(&Event) + Send>,
+    ) -> SubscriptionId {
+        self.machine.subscribe(observer)
+    }
+
+    /// Stop delivering events to a subscriber. Returns false if `id` was not
+    /// subscribed.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.machine.unsubscribe(id)
+    }
+
     pub fn mode(&self) -> &'static str {
         self.machine.mode()
     }
+
+    /// Structured information about the current mode, for statuslines.
+    pub fn mode_info(&self) -> ModeInfo {
+        self.machine.mode_info()
+    }
+
+    /// The partially-typed command, for display in a showcmd area.
+    pub fn showcmd(&self) -> ShowCmd<MultiKey> {
+        self.machine.showcmd()
+    }
+
+    /// Lists what the pending keys can be followed by in the current mode,
+    /// as pairs of the remaining keys and a description of what they do.
+    pub fn continuations(&self) -> Vec<(String, String)> {
+        self.machine
+            .continuations()
+            .into_iter()
+            .map(|(keys, description)| {
+                (keys.iter().map(|k| k.to_string()).collect(), description)
+            })
+            .collect()
+    }
+
+    /// Returns true if vixi is waiting for more keys to complete a command.
+    pub fn is_pending(&self) -> bool {
+        self.machine.is_pending()
+    }
+}
+
+impl Vixi {
+    /// Process `input`, a key, a timeout or fed keys, then trace it.
+    fn step(&mut self, input: Input) -> Result<Vec<Effect>, VixiError> {
+        let mut effects = Vec::new();
+        let view_id = self.machine.view_id();
+        let result: Result<(), VixiError> = {
+            let client = &mut self.client;
+            let applier = &mut self.applier;
+            let commands = &mut self.commands;
+            let messages = &mut self.messages;
+            let extensions = &mut self.extensions;
+            let own_text = &self.text;
+            let applied = |effect: &Effect, text: &client::TextQuery| {
+                let text = match *own_text {
+                    Some(ref own_text) => &**own_text,
+                    None => text,
+                };
+                let resolved = match *effect {
+                    Effect::Operate(operator, span, count) => {
+                        let operation = (operator, span, count);
+                        resolve(extensions, text, view_id, operation)?
+                    }
+                    _ => vec![effect.clone()],
+                };
+                for effect in resolved {
+                    apply(
+                        client,
+                        applier,
+                        commands,
+                        messages,
+                        view_id,
+                        &effect,
+                    )?;
+                    effects.push(effect);
+                }
+                Ok(())
+            };
+            let machine = &mut self.machine;
+            match input {
+                Input::Key(key) => machine.process_with(key, applied),
+                Input::Keys(ref keys) => {
+                    machine.process_keys_with(keys, applied)
+                }
+                Input::Timeout => machine.timeout_with(applied),
+                Input::Feed(ref keys, flags) => {
+                    let remap_type = if flags.noremap {
+                        RemapType::Noremap
+                    } else {
+                        RemapType::Remap
+                    };
+                    machine.feed(keys, remap_type, flags.insert, flags.typed);
+                    if flags.execute {
+                        machine.process_typeahead_with(applied)
+                    } else {
+                        Ok(())
+                    }
+                }
+            }
+        };
+        self.deadline = self.next_deadline();
+        if self.trace.is_some() {
+            let event = input.event();
+            self.traced(event, view_id, &effects, result.as_ref().err());
+        }
+        if let Err(err) = result {
+            self.messages.push(Message::error(None, &err.to_string()));
+            return Err(err);
+        }
+        Ok(effects)
+    }
+
+    /// When a pending mapping should time out. Those starting with `<Esc>`,
+    /// like a terminal's key codes, wait only 'ttimeoutlen'.
+    fn next_deadline(&self) -> Option<Instant> {
+        let options = self.machine.options();
+        if !options.timeout || !self.machine.awaits_mapping() {
+            return None;
+        }
+        let esc = MultiKey::A(Key::Esc);
+        let millis = if self.machine.pending_keys().first() == Some(&esc) {
+            options.ttimeoutlen
+        } else {
+            options.timeoutlen
+        };
+        Some(self.clock.now() + Duration::from_millis(millis))
+    }
+
+    /// Trace `input`, with the effects it led to in `view_id` and the error
+    /// it failed with, if any.
+    fn traced(
+        &mut self,
+        input: TraceEvent,
+        view_id: ViewId,
+        effects: &[Effect],
+        error: Option<&VixiError>,
+    ) {
+        if self.trace.is_none() {
+            return;
+        }
+        let mut events = vec![input];
+        for effect in effects {
+            events.extend(effect_events(view_id, effect));
+        }
+        if let Some(err) = error {
+            events.push(TraceEvent::Error(err.to_string()));
+        }
+        self.record(&events);
+    }
+
+    /// Trace a call which changes vixi's state without producing effects.
+    fn traced_call<T>(
+        &mut self,
+        input: TraceEvent,
+        result: &Result<T, VixiError>,
+    ) {
+        let view_id = self.machine.view_id();
+        self.traced(input, view_id, &[], result.as_ref().err());
+    }
+
+    fn record(&mut self, events: &[TraceEvent]) {
+        let result = match self.trace {
+            Some(ref mut recorder) => {
+                events.iter().map(|event| recorder.record(event)).collect()
+            }
+            None => Ok(()),
+        };
+        if let Err(err) = result {
+            self.trace = None;
+            let err = VixiError::Trace(err);
+            self.messages.push(Message::error(None, &err.to_string()));
+        }
+    }
+}
+
+/// Turn the operator (if any), span and count of an `Effect::Operate` into
+/// effects a client understands, running the extensions involved. Spans
+/// which can't be worked out, e.g. as their motion fails, change nothing.
+fn resolve(
+    extensions: &mut Extensions,
+    text: &client::TextQuery,
+    view_id: ViewId,
+    (operator, span, count): (Option<OperatorOp>, Span, i32),
+) -> Result<Vec<Effect>, VixiError> {
+    let cursor = match text.cursor(view_id) {
+        Some(cursor) => cursor,
+        None => {
+            return Ok(Vec::new());
+        }
+    };
+    let origin = Origin {
+        text: text,
+        view_id: view_id,
+        cursor: cursor,
+        count: count,
+    };
+    let range = match span {
+        Span::Motion(motion) => {
+            let target = match motion {
+                MotionOp::Custom(id) => extensions
+                    .motions
+                    .get(id.0)
+                    .and_then(|motion| motion.target(&origin)),
+                _ => motion.target(&origin),
+            };
+            match (target, operator) {
+                (Some(target), None) => {
+                    return Ok(vec![Effect::MoveTo(target.position)]);
+                }
+                (Some(target), Some(_)) => {
+                    motion_range(text, view_id, cursor, target)
+                }
+                (None, _) => {
+                    return Ok(Vec::new());
+                }
+            }
+        }
+        Span::Object(ObjectOp::Custom(id)) => {
+            let object = extensions.objects.get(id.0);
+            match object.and_then(|object| object.range(&origin)) {
+                Some(range) => range,
+                None => {
+                    return Ok(Vec::new());
+                }
+            }
+        }
+        Span::Object(_) => {
+            // TODO Built-in text objects.
+            return Ok(Vec::new());
+        }
+    };
+    let id = match operator {
+        Some(OperatorOp::Custom(id)) => id,
+        _ => {
+            // Deleting, or changing before Insert mode takes over.
+            let deletion =
+                Effect::Replace(range.start, range.end, String::new());
+            return Ok(vec![deletion]);
+        }
+    };
+    let name = String::from(extensions.operators.name(id.0).unwrap_or(""));
+    let operator = match extensions.operators.get_mut(id.0) {
+        Some(operator) => operator,
+        None => {
+            return Ok(Vec::new());
+        }
+    };
+    let old = text_in(text, view_id, range);
+    let new = operator
+        .transform(range, &old)
+        .map_err(|message| VixiError::Operator(name, message))?;
+    let mut effects = Vec::new();
+    if new != old {
+        effects.push(Effect::Replace(range.start, range.end, new));
+    }
+    effects.push(Effect::MoveTo(range.start));
+    Ok(effects)
+}
+
+/// Drive the requests for `effect` to completion in order, if there is a
+/// client, run the command it names, if any, and remember any message it
+/// carries.
+fn apply(
+    client: &mut Option<Box<client::Client + Send>>,
+    applier: &mut client::Applier,
+    commands: &mut Commands,
+    messages: &mut Messages,
+    view_id: ViewId,
+    effect: &Effect,
+) -> Result<(), VixiError> {
+    if let Some(ref mut backend) = *client {
+        let backend = &mut **backend;
+        for request in applier.apply(backend, view_id, effect) {
+            request
+                .wait()
+                .map_err(|err| VixiError::Client(effect.clone(), err))?;
+        }
+    }
+    if let Effect::Command(id, count, register) = *effect {
+        let backend: Option<&mut client::Client> = match *client {
+            Some(ref mut backend) => Some(&mut **backend),
+            None => None,
+        };
+        let mut context = Context {
+            count: count,
+            register: register,
+            view_id: view_id,
+            client: backend,
+        };
+        match commands.run(id, &mut context) {
+            Some(Ok(())) => {}
+            Some(Err(message)) => {
+                let name = commands.name(id).unwrap_or_default();
+                return Err(VixiError::Command(String::from(name), message));
+            }
+            None => {
+                return Err(VixiError::UnknownCommand(id));
+            }
+        }
+    }
+    if let Effect::Message(ref message) = *effect {
+        messages.push(message.clone());
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use clock::ManualClock;
+    use extension::{Exclusivity, OperatorResult, Range, Target};
+    use memory_client::MemoryClient;
+    use mode_info::{BaseMode, CursorShape, SubMode};
+    use op::{MotionOp, OperatorOp};
+    use std::sync::{Arc, Mutex};
+
+    fn position(line: u64, column: u64) -> Position {
+        Position {
+            line: line,
+            column: column,
+        }
+    }
+
+    /// Subscribe to `vixi`, returning the list the events are collected in.
+    fn collect_events(vixi: &mut Vixi) -> Arc<Mutex<Vec<Event>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        vixi.subscribe(Box::new(move |e| sink.lock().unwrap().push(e.clone())));
+        events
+    }
+
+    fn update(json: ::serde_json::Value) -> Update {
+        ::serde_json::from_value(json).unwrap()
+    }
+
+    /// An update replacing the view with `text`, with a cursor at `column`.
+    fn line_with_cursor(text: &str, column: u64) -> Update {
+        update(json!({"ops": [{"op": "ins", "n": 1, "lines": [
+            {"text": text, "cursor": [column]},
+        ]}]}))
+    }
+
+    /// A `Vixi` editing an in-memory buffer, and that buffer's client.
+    fn in_memory() -> (Vixi, MemoryClient) {
+        let memory = MemoryClient::new();
+        let mut vixi = Vixi::with_client(Box::new(memory.clone()));
+        vixi.set_text_query(Box::new(memory.clone()));
+        (vixi, memory)
+    }
+
+    fn mode_change(base: BaseMode, submode: SubMode) -> Effect {
+        Effect::ModeChange(ModeInfo::new(base, submode, None))
+    }
 
     #[test]
     fn start_in_normal() {
-        let vixi = Vixi::new(Box::new(client::DummyClient::new()));
+        let vixi = Vixi::new();
         assert_eq!("Normal", vixi.mode());
     }
 
     #[test]
     fn to_insert() {
-        let mut vixi = Vixi::new(Box::new(client::DummyClient::new()));
-        vixi.process("i");
+        let mut vixi = Vixi::new();
+        vixi.process("i").unwrap();
         assert_eq!("Insert", vixi.mode());
     }
 
     #[test]
     fn insert_text() {
-        let mut vixi = Vixi::new(Box::new(client::DummyClient::new()));
-        vixi.process("iasdf");
+        let mut vixi = Vixi::new();
+        vixi.process("iasdf").unwrap();
         assert_eq!("Insert", vixi.mode());
     }
 
     #[test]
     fn insert_to_normal() {
-        let mut vixi = Vixi::new(Box::new(client::DummyClient::new()));
-        vixi.process("i<esc>");
+        let mut vixi = Vixi::new();
+        vixi.process("i<esc>").unwrap();
         assert_eq!("Normal", vixi.mode());
     }
 
     #[test]
     fn insert_text_then_normal() {
-        let mut vixi = Vixi::new(Box::new(client::DummyClient::new()));
-        vixi.process("ir<esc>");
+        let mut vixi = Vixi::new();
+        vixi.process("ir<esc>").unwrap();
         assert_eq!("Normal", vixi.mode());
     }
 
     #[test]
     fn to_op_pending() {
-        let mut vixi = Vixi::new(Box::new(client::DummyClient::new()));
-        vixi.process("d");
+        let mut vixi = Vixi::new();
+        vixi.process("d").unwrap();
         assert_eq!("Pending", vixi.mode());
     }
 
     #[test]
     fn to_op_pending_with_count() {
-        let mut vixi = Vixi::new(Box::new(client::DummyClient::new()));
-        vixi.process("123d");
+        let mut vixi = Vixi::new();
+        vixi.process("123d").unwrap();
         assert_eq!("Pending", vixi.mode());
     }
 
     #[test]
     fn op_pending_to_normal() {
-        let mut vixi = Vixi::new(Box::new(client::DummyClient::new()));
-        vixi.process("d<esc>");
+        let mut vixi = Vixi::new();
+        vixi.process("d<esc>").unwrap();
+        assert_eq!("Normal", vixi.mode());
+    }
+
+    #[test]
+    fn insert_text_effects() {
+        let mut vixi = Vixi::new();
+        assert_eq!(
+            vec![
+                mode_change(BaseMode::Insert, SubMode::None),
+                Effect::StartUndoGroup,
+                Effect::Insert(String::from("a\n")),
+                Effect::EndUndoGroup,
+                mode_change(BaseMode::Normal, SubMode::None),
+            ],
+            vixi.process("ia<cr><esc>").unwrap()
+        );
+    }
+
+    #[test]
+    fn counted_motion_effects() {
+        let mut vixi = Vixi::new();
+        assert_eq!(
+            vec![Effect::MoveCount(MotionOp::Down, 3)],
+            vixi.process("3j").unwrap()
+        );
+        assert_eq!(
+            vec![Effect::Move(MotionOp::Down)],
+            vixi.process("j").unwrap()
+        );
+    }
+
+    #[test]
+    fn delete_motion_effects() {
+        let mut vixi = Vixi::new();
+        assert_eq!(
+            vec![
+                mode_change(
+                    BaseMode::Normal,
+                    SubMode::OperatorPending(OperatorOp::Delete),
+                ),
+                Effect::StartUndoGroup,
+                Effect::Delete(MotionOp::Word),
+                Effect::Delete(MotionOp::Word),
+                Effect::EndUndoGroup,
+                mode_change(BaseMode::Normal, SubMode::None),
+            ],
+            vixi.process("2dw").unwrap()
+        );
+    }
+
+    #[test]
+    fn change_motion_enters_insert() {
+        let mut vixi = Vixi::new();
+        vixi.process("cw").unwrap();
+        assert_eq!("Insert", vixi.mode());
+    }
+
+    #[test]
+    fn replace_mode_info() {
+        let mut vixi = Vixi::new();
+        vixi.process("Rab").unwrap();
+        let info = vixi.mode_info();
+        assert_eq!(BaseMode::Insert, info.base);
+        assert_eq!(SubMode::Replace, info.submode);
+        assert_eq!("-- REPLACE --", info.showmode);
+        assert_eq!(CursorShape::Underline, info.cursor);
+    }
+
+    #[test]
+    fn insert_normal_runs_one_command() {
+        let mut vixi = Vixi::new();
+        vixi.process("i<C-o>").unwrap();
+        assert_eq!(SubMode::InsertNormal, vixi.mode_info().submode);
+        assert_eq!("-- (insert) --", vixi.mode_info().showmode);
+        assert_eq!(
+            vec![
+                Effect::Move(MotionOp::Down),
+                mode_change(BaseMode::Insert, SubMode::None),
+            ],
+            vixi.process("j").unwrap()
+        );
+    }
+
+    #[test]
+    fn insert_normal_operator_returns_to_insert() {
+        let mut vixi = Vixi::new();
+        vixi.process("i<C-o>dw").unwrap();
+        assert_eq!(BaseMode::Insert, vixi.mode_info().base);
+    }
+
+    #[test]
+    fn operator_pending_mode_info() {
+        let mut vixi = Vixi::new();
+        vixi.process("gu").unwrap();
+        let info = vixi.mode_info();
+        assert_eq!(
+            SubMode::OperatorPending(OperatorOp::ToLower),
+            info.submode
+        );
+        assert_eq!(CursorShape::Underline, info.cursor);
+    }
+
+    #[test]
+    fn recording_mode_info() {
+        let mut vixi = Vixi::new();
+        vixi.process("qa").unwrap();
+        assert_eq!(Some('a'), vixi.mode_info().recording);
+        assert_eq!("recording @a", vixi.mode_info().showmode);
+        vixi.process("ix<esc>q").unwrap();
+        assert_eq!(None, vixi.mode_info().recording);
+    }
+
+    #[test]
+    fn showcmd_count_register_operator() {
+        let mut vixi = Vixi::new();
+        vixi.process("12\"ad2").unwrap();
+        let showcmd = vixi.showcmd();
+        assert_eq!(Some(12), showcmd.count);
+        assert_eq!(Some('a'), showcmd.register);
+        assert_eq!(Some(OperatorOp::Delete), showcmd.operator);
+        assert_eq!(parse("2"), showcmd.pending);
+        assert_eq!("12\"ad2", showcmd.to_string());
+        assert!(vixi.is_pending());
+    }
+
+    #[test]
+    fn showcmd_renders_notation() {
+        let mut vixi = Vixi::new();
+        vixi.process("i<C-r>").unwrap();
+        assert_eq!("<C-r>", vixi.showcmd().to_string());
+        assert!(vixi.is_pending());
+    }
+
+    #[test]
+    fn showcmd_clears_after_command() {
+        let mut vixi = Vixi::new();
+        vixi.process("\"a3j").unwrap();
+        assert!(vixi.showcmd().is_empty());
+        assert!(!vixi.is_pending());
+    }
+
+    #[test]
+    fn continuations_of_g() {
+        let mut vixi = Vixi::new();
+        vixi.process("g").unwrap();
+        let continuations = vixi.continuations();
+        assert!(continuations.contains(
+            &(String::from("u"), String::from("make lowercase")),
+        ));
+        assert!(continuations.contains(
+            &(String::from("g"), String::from("first line")),
+        ));
+        assert!(continuations.iter().all(|c| c.0.len() == 1));
+    }
+
+    #[test]
+    fn continuations_of_pending_text_object() {
+        let mut vixi = Vixi::new();
+        vixi.process("di").unwrap();
+        assert!(vixi.continuations().contains(
+            &(String::from("w"), String::from("inner word")),
+        ));
+    }
+
+    #[test]
+    fn insert_enter_and_leave_events() {
+        let mut vixi = Vixi::new();
+        let events = collect_events(&mut vixi);
+        vixi.process("ia<esc>").unwrap();
+        let insert = ModeInfo::new(BaseMode::Insert, SubMode::None, None);
+        let normal = ModeInfo::new(BaseMode::Normal, SubMode::None, None);
+        assert_eq!(
+            vec![
+                Event::ModeChanged(normal.clone(), insert.clone()),
+                Event::InsertEnter,
+                Event::ModeChanged(insert, normal),
+                Event::InsertLeave,
+            ],
+            *events.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn operator_and_yank_events() {
+        let mut vixi = Vixi::new();
+        let events = collect_events(&mut vixi);
+        vixi.process("\"byw").unwrap();
+        let events = events.lock().unwrap();
+        assert_eq!(Event::OperatorStarted(OperatorOp::Yank), events[0]);
+        assert!(events.contains(
+            &Event::TextYanked('b', OperatorOp::Yank, MotionOp::Word),
+        ));
+    }
+
+    #[test]
+    fn recording_events() {
+        let mut vixi = Vixi::new();
+        let events = collect_events(&mut vixi);
+        vixi.process("qzjq").unwrap();
+        let events = events.lock().unwrap();
+        assert_eq!(Event::RecordingStarted('z'), events[0]);
+        assert!(events.contains(&Event::RecordingStopped('z')));
+    }
+
+    #[test]
+    fn unsubscribed_observer_is_silent() {
+        let mut vixi = Vixi::new();
+        let count = Arc::new(Mutex::new(0));
+        let sink = count.clone();
+        let id = vixi.subscribe(Box::new(move |_| *sink.lock().unwrap() += 1));
+        vixi.process("i").unwrap();
+        assert!(vixi.unsubscribe(id));
+        vixi.process("<esc>").unwrap();
+        assert_eq!(2, *count.lock().unwrap());
+        assert!(!vixi.unsubscribe(id));
+    }
+
+    #[test]
+    fn views_keep_their_own_mode() {
+        let mut vixi = Vixi::new();
+        assert!(vixi.add_view(ViewId(1)));
+        assert!(!vixi.add_view(ViewId(1)));
+        vixi.process("i").unwrap();
+        vixi.switch_view(ViewId(1)).unwrap();
         assert_eq!("Normal", vixi.mode());
+        vixi.switch_view(ViewId(0)).unwrap();
+        assert_eq!("Insert", vixi.mode());
+    }
+
+    #[test]
+    fn switch_view_reports_mode_change() {
+        let mut vixi = Vixi::new();
+        vixi.add_view(ViewId(1));
+        vixi.process("i").unwrap();
+        let events = collect_events(&mut vixi);
+        vixi.switch_view(ViewId(1)).unwrap();
+        assert_eq!(
+            vec![
+                Event::ModeChanged(
+                    ModeInfo::new(BaseMode::Insert, SubMode::None, None),
+                    ModeInfo::new(BaseMode::Normal, SubMode::None, None),
+                ),
+            ],
+            *events.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn pending_command_resumes_in_its_view() {
+        let mut vixi = Vixi::new();
+        vixi.add_view(ViewId(1));
+        vixi.process("2d").unwrap();
+        vixi.switch_view(ViewId(1)).unwrap();
+        assert!(!vixi.is_pending());
+        assert_eq!(
+            vec![Effect::Move(MotionOp::Down)],
+            vixi.process("j").unwrap()
+        );
+        vixi.switch_view(ViewId(0)).unwrap();
+        assert!(vixi.is_pending());
+        assert_eq!(
+            vec![
+                Effect::StartUndoGroup,
+                Effect::Delete(MotionOp::Word),
+                Effect::Delete(MotionOp::Word),
+                Effect::EndUndoGroup,
+                mode_change(BaseMode::Normal, SubMode::None),
+            ],
+            vixi.process("w").unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_view() {
+        let mut vixi = Vixi::new();
+        match vixi.switch_view(ViewId(1)) {
+            Err(VixiError::UnknownView(ViewId(1))) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+        match vixi.close_view(ViewId(1)) {
+            Err(VixiError::UnknownView(ViewId(1))) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[test]
+    fn close_focused_view() {
+        let mut vixi = Vixi::new();
+        vixi.add_view(ViewId(1));
+        vixi.add_view(ViewId(2));
+        vixi.switch_view(ViewId(2)).unwrap();
+        vixi.switch_view(ViewId(1)).unwrap();
+        assert_eq!(vec![ViewId(1), ViewId(2), ViewId(0)], vixi.views());
+        vixi.close_view(ViewId(1)).unwrap();
+        assert_eq!(ViewId(2), vixi.view_id());
+        vixi.close_view(ViewId(0)).unwrap();
+        match vixi.close_view(ViewId(2)) {
+            Err(VixiError::LastView(ViewId(2))) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+        assert_eq!(vec![ViewId(2)], vixi.views());
+    }
+
+    #[test]
+    fn new_view_needs_client() {
+        let mut vixi = Vixi::new();
+        match vixi.new_view(None) {
+            Err(VixiError::NoClient) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[test]
+    fn global_map_is_shared() {
+        let mut vixi = Vixi::new();
+        vixi.add_view(ViewId(1));
+        assert!(vixi.map(MapMode::Normal, "Q", "j"));
+        vixi.switch_view(ViewId(1)).unwrap();
+        assert_eq!(
+            vec![Effect::Move(MotionOp::Down)],
+            vixi.process("Q").unwrap()
+        );
+    }
+
+    #[test]
+    fn local_map_stays_in_its_view() {
+        let mut vixi = Vixi::new();
+        vixi.add_view(ViewId(1));
+        assert!(vixi.map(MapMode::Normal, "Q", "j"));
+        assert!(vixi.map_local(MapMode::Normal, "Q", "k"));
+        assert!(!vixi.map_local(MapMode::Normal, "", "k"));
+        assert_eq!(
+            vec![Effect::Move(MotionOp::Up)],
+            vixi.process("Q").unwrap()
+        );
+        vixi.switch_view(ViewId(1)).unwrap();
+        assert_eq!(
+            vec![Effect::Move(MotionOp::Down)],
+            vixi.process("Q").unwrap()
+        );
+    }
+
+    #[test]
+    fn marks_are_per_view() {
+        let mut vixi = Vixi::new();
+        vixi.add_view(ViewId(1));
+        let position = Position { line: 3, column: 1 };
+        vixi.set_mark('a', position);
+        vixi.switch_view(ViewId(1)).unwrap();
+        assert_eq!(None, vixi.mark('a'));
+        vixi.switch_view(ViewId(0)).unwrap();
+        assert_eq!(Some(position), vixi.mark('a'));
+    }
+
+    #[test]
+    fn global_marks() {
+        let mut vixi = Vixi::new();
+        vixi.add_view(ViewId(1));
+        let position = Position { line: 3, column: 1 };
+        vixi.set_mark('A', position);
+        vixi.switch_view(ViewId(1)).unwrap();
+        assert_eq!(None, vixi.mark('A'));
+        assert_eq!(Some((ViewId(0), position)), vixi.global_mark('A'));
+        vixi.set_mark('A', position);
+        vixi.set_mark('0', position);
+        assert_eq!(Some(position), vixi.mark('A'));
+        vixi.switch_view(ViewId(0)).unwrap();
+        assert_eq!(None, vixi.mark('A'));
+        vixi.close_view(ViewId(1)).unwrap();
+        assert_eq!(None, vixi.global_mark('0'));
+    }
+
+    #[test]
+    fn last_insert() {
+        let mut vixi = Vixi::new();
+        assert_eq!(None, vixi.last_insert());
+        vixi.process("ihello<bs><cr>x<esc>").unwrap();
+        assert_eq!(Some(String::from("hell\nx")), vixi.last_insert());
+        vixi.add_view(ViewId(1));
+        vixi.switch_view(ViewId(1)).unwrap();
+        assert_eq!(None, vixi.last_insert());
+    }
+
+    #[test]
+    fn updates_go_to_their_view() {
+        let mut vixi = Vixi::new();
+        vixi.add_view(ViewId(1));
+        let update = update(json!({
+            "ops": [{"op": "ins", "n": 1, "lines": [{"text": "hi"}]}],
+        }));
+        assert!(vixi.update(ViewId(1), &update).unwrap().is_empty());
+        assert!(vixi.lines().is_empty());
+        vixi.switch_view(ViewId(1)).unwrap();
+        assert_eq!(Some("hi"), vixi.lines().line(0).map(|l| l.content()));
+        match vixi.update(ViewId(2), &update) {
+            Err(VixiError::UnknownView(ViewId(2))) => {}
+            result => panic!("unexpected {:?}", result),
+        }
+    }
+
+    #[test]
+    fn normal_mode_clamps_cursor() {
+        let mut vixi = Vixi::new();
+        let update = line_with_cursor("hello\n", 5);
+        assert_eq!(
+            vec![Effect::Move(MotionOp::Left)],
+            vixi.update(ViewId(0), &update).unwrap()
+        );
+        assert_eq!(Some(Position { line: 0, column: 5 }), vixi.cursor());
+        vixi.process("i").unwrap();
+        assert!(vixi.update(ViewId(0), &update).unwrap().is_empty());
+        let empty = line_with_cursor("\n", 0);
+        vixi.process("<esc>").unwrap();
+        assert!(vixi.update(ViewId(0), &empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn cursor_outlives_updates_without_one() {
+        let mut vixi = Vixi::new();
+        vixi.update(ViewId(0), &line_with_cursor("hello", 2)).unwrap();
+        vixi.update(ViewId(0), &update(json!({"ops": [
+            {"op": "invalidate", "n": 1},
+        ]}))).unwrap();
+        assert_eq!(Some(Position { line: 0, column: 2 }), vixi.cursor());
+    }
+
+    #[test]
+    fn selections_are_tracked() {
+        let mut vixi = Vixi::new();
+        vixi.update(ViewId(0), &update(json!({"ops": [
+            {"op": "ins", "n": 1, "lines": [
+                {"text": "hello", "cursor": [3], "styles": [1, 2, 0]},
+            ]},
+        ]}))).unwrap();
+        assert_eq!(
+            vec![
+                (
+                    Position { line: 0, column: 1 },
+                    Position { line: 0, column: 3 },
+                ),
+            ],
+            vixi.selections()
+        );
+    }
+
+    #[test]
+    fn delete_marks_change_once_cursor_is_known() {
+        let mut vixi = Vixi::new();
+        vixi.update(ViewId(0), &line_with_cursor("one two", 4)).unwrap();
+        vixi.process("db").unwrap();
+        assert_eq!(None, vixi.mark('['));
+        vixi.update(ViewId(0), &line_with_cursor("two", 0)).unwrap();
+        let start = Some(Position { line: 0, column: 0 });
+        assert_eq!(start, vixi.mark('['));
+        assert_eq!(start, vixi.mark(']'));
+    }
+
+    #[test]
+    fn yank_marks_cursor() {
+        let mut vixi = Vixi::new();
+        vixi.update(ViewId(0), &line_with_cursor("one two", 4)).unwrap();
+        vixi.process("yw").unwrap();
+        let cursor = Some(Position { line: 0, column: 4 });
+        assert_eq!(cursor, vixi.mark('['));
+        assert_eq!(cursor, vixi.mark(']'));
+    }
+
+    #[test]
+    fn text_from_updates() {
+        let mut vixi = Vixi::new();
+        vixi.update(ViewId(0), &line_with_cursor("héllo\n", 3)).unwrap();
+        let text = vixi.text();
+        assert_eq!(1, text.line_count(ViewId(0)));
+        assert_eq!(Some(String::from("héllo")), text.line(ViewId(0), 0));
+        let cursor = Position { line: 0, column: 3 };
+        assert_eq!(Some(cursor), text.cursor(ViewId(0)));
+        assert_eq!(Some('l'), text.char_at(ViewId(0), cursor));
+        assert_eq!(None, text.line(ViewId(1), 0));
+        assert_eq!(0, text.line_count(ViewId(1)));
+    }
+
+    #[test]
+    fn insert_mapping_times_out() {
+        let (mut vixi, memory) = in_memory();
+        let clock = ManualClock::new();
+        vixi.set_clock(Box::new(clock.clone()));
+        assert!(vixi.map(MapMode::Insert, "jj", "<Esc>"));
+        vixi.process("iaj").unwrap();
+        assert_eq!(Some(String::from("a")), memory.text(ViewId(0)));
+        let deadline = clock.now() + Duration::from_millis(1000);
+        assert_eq!(Some(deadline), vixi.deadline());
+        assert!(vixi.tick(clock.now()).unwrap().is_empty());
+
+        clock.advance(Duration::from_millis(1000));
+        vixi.tick(clock.now()).unwrap();
+        assert_eq!(Some(String::from("aj")), memory.text(ViewId(0)));
+        assert_eq!(None, vixi.deadline());
+        vixi.process("jj").unwrap();
+        assert_eq!("Normal", vixi.mode());
+        assert_eq!(Some(String::from("aj")), memory.text(ViewId(0)));
+    }
+
+    #[test]
+    fn timeout_options() {
+        let mut vixi = Vixi::new();
+        let clock = ManualClock::new();
+        vixi.set_clock(Box::new(clock.clone()));
+        assert!(vixi.map(MapMode::Normal, "QQ", "j"));
+        assert!(vixi.map(MapMode::Normal, "<Esc>j", "k"));
+        vixi.set_option("tm", OptionValue::Number(50)).unwrap();
+        vixi.process("Q").unwrap();
+        let deadline = clock.now() + Duration::from_millis(50);
+        assert_eq!(Some(deadline), vixi.deadline());
+        // Unmapped keys are dropped in Normal mode.
+        assert!(vixi.tick(deadline).unwrap().is_empty());
+        assert!(!vixi.is_pending());
+
+        vixi.set_option("notimeout", OptionValue::Bool(true)).unwrap_err();
+        vixi.set_option("to", OptionValue::Bool(false)).unwrap();
+        vixi.process("Q").unwrap();
+        assert_eq!(None, vixi.deadline());
+        assert!(vixi.timeout().unwrap().is_empty());
+
+        // Mappings starting with `<Esc>` wait 'ttimeoutlen' instead.
+        vixi.process("<Esc>").unwrap();
+        assert_eq!(None, vixi.deadline());
+        assert!(vixi.timeout().unwrap().is_empty());
+        vixi.set_option("to", OptionValue::Bool(true)).unwrap();
+        vixi.set_option("ttm", OptionValue::Number(10)).unwrap();
+        vixi.process("<Esc>").unwrap();
+        let deadline = clock.now() + Duration::from_millis(10);
+        assert_eq!(Some(deadline), vixi.deadline());
+        vixi.tick(deadline).unwrap();
+        vixi.process("Q").unwrap();
+        let deadline = clock.now() + Duration::from_millis(50);
+        assert_eq!(Some(deadline), vixi.deadline());
+    }
+
+    #[test]
+    fn feedkeys_flags() {
+        let (mut vixi, memory) = in_memory();
+        assert!(vixi.map(MapMode::Insert, "x", "y"));
+        assert!(vixi.map(MapMode::Insert, "jj", "<Esc>"));
+        // Keys wait for the next key unless executed.
+        let effects = vixi.feedkeys("iab", FeedFlags::default()).unwrap();
+        assert!(effects.is_empty());
+        assert_eq!("Normal", vixi.mode());
+        vixi.process("x").unwrap();
+        assert_eq!(Some(String::from("aby")), memory.text(ViewId(0)));
+
+        vixi.feedkeys("x", FeedFlags::parse("nx")).unwrap();
+        assert_eq!(Some(String::from("abyx")), memory.text(ViewId(0)));
+
+        // Inserted keys go before the pending `j`.
+        vixi.process("j").unwrap();
+        vixi.feedkeys("k", FeedFlags::parse("ix")).unwrap();
+        assert_eq!(Some(String::from("abyxk")), memory.text(ViewId(0)));
+        vixi.process("j").unwrap();
+        assert_eq!("Normal", vixi.mode());
+    }
+
+    #[test]
+    fn feedkeys_typed_or_not() {
+        let mut vixi = Vixi::new();
+        vixi.feedkeys("2d", FeedFlags::parse("x")).unwrap();
+        assert!(vixi.showcmd().typed.is_empty());
+        vixi.process("<Esc>").unwrap();
+        vixi.feedkeys("2d", FeedFlags::parse("tx")).unwrap();
+        assert_eq!(parse("2d"), vixi.showcmd().typed);
+    }
+
+    #[test]
+    fn user_commands() {
+        let (mut vixi, memory) = in_memory();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let sink = calls.clone();
+        vixi.define_command("finder", move |context| {
+            let call = (context.count, context.register, context.view_id);
+            sink.lock().unwrap().push(call);
+            Ok(())
+        });
+        vixi.define_command("home", |context| {
+            let view_id = context.view_id;
+            match context.client {
+                Some(ref mut client) => client
+                    .line_start(view_id)
+                    .wait()
+                    .map_err(|err| format!("{:?}", err)),
+                None => Err(String::from("no client")),
+            }
+        });
+        assert!(vixi.map_command(MapMode::Normal, "Zf", "finder"));
+        assert!(vixi.map_command(MapMode::Pending, "Zf", "finder"));
+        assert!(vixi.map_command(MapMode::Insert, "<C-a>", "home"));
+        assert!(!vixi.map_command(MapMode::Normal, "Zz", "nosuch"));
+
+        vixi.process("\"a3Zf").unwrap();
+        vixi.process("dZf").unwrap();
+        assert_eq!("Normal", vixi.mode());
+        assert_eq!(
+            vec![(3, Some('a'), ViewId(0)), (1, None, ViewId(0))],
+            *calls.lock().unwrap()
+        );
+
+        vixi.process("iab<C-a>c").unwrap();
+        assert_eq!(Some(String::from("cab")), memory.text(ViewId(0)));
+    }
+
+    #[test]
+    fn failing_command_abandons_keys() {
+        let mut vixi = Vixi::new();
+        vixi.define_command("fail", |_| Err(String::from("no tests")));
+        assert!(vixi.map_command(MapMode::Normal, "Zt", "fail"));
+        match vixi.process("Ztiab") {
+            Err(VixiError::Command(ref name, ref message)) => {
+                assert_eq!("fail", name);
+                assert_eq!("no tests", message);
+            }
+            result => panic!("expected a failure, got {:?}", result),
+        }
+        assert_eq!("Normal", vixi.mode());
+        assert_eq!(1, vixi.drain_messages().len());
+    }
+
+    #[test]
+    fn snapshot_and_restore() {
+        let mut vixi = Vixi::new();
+        assert!(vixi.map(MapMode::Normal, "Q", "j"));
+        vixi.set_option("ts", OptionValue::Number(8)).unwrap();
+        vixi.add_view(ViewId(1));
+        vixi.switch_view(ViewId(1)).unwrap();
+        let position = Position { line: 2, column: 3 };
+        vixi.set_mark('a', position);
+        vixi.process("2dg").unwrap();
+        let json = vixi.snapshot().unwrap().to_string();
+
+        let mut restored = Vixi::new();
+        let snapshot = serde_json::from_str(&json).unwrap();
+        restored.restore(snapshot).unwrap();
+        assert_eq!(vec![ViewId(1), ViewId(0)], restored.views());
+        assert_eq!(Some(position), restored.mark('a'));
+        assert_eq!(8, restored.options().tabstop);
+        assert!(restored.is_pending());
+        assert_eq!(
+            vec![
+                Effect::StartUndoGroup,
+                Effect::Delete(MotionOp::Top),
+                Effect::Delete(MotionOp::Top),
+                Effect::EndUndoGroup,
+                Effect::ModeChange(
+                    ModeInfo::new(BaseMode::Normal, SubMode::None, None),
+                ),
+            ],
+            restored.process("g").unwrap()
+        );
+        let effects = restored.process("Q").unwrap();
+        assert_eq!(vec![Effect::Move(MotionOp::Down)], effects);
+    }
+
+    #[test]
+    fn restore_rejects_unknown_versions() {
+        let mut vixi = Vixi::new();
+        let mut snapshot = vixi.snapshot().unwrap();
+        snapshot["version"] = json!(0);
+        match vixi.restore(snapshot) {
+            Err(VixiError::SnapshotVersion(Some(0))) => {}
+            other => panic!("expected a version error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn options_reach_text_queries() {
+        let mut vixi = Vixi::new();
+        vixi.set_option("ts", OptionValue::Number(8)).unwrap();
+        assert!(!vixi.text().expand_tab(ViewId(0)));
+        vixi.set_option("expandtab", OptionValue::Bool(true)).unwrap();
+        assert_eq!(8, vixi.text().tab_size(ViewId(0)));
+        assert!(vixi.text().expand_tab(ViewId(0)));
+        match vixi.set_option("ts", OptionValue::Bool(true)) {
+            Err(VixiError::Option(_)) => {}
+            other => panic!("expected an option error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn text_from_host() {
+        struct Host;
+        impl client::TextQuery for Host {
+            fn line_count(&self, _: ViewId) -> u64 {
+                1
+            }
+            fn line(&self, _: ViewId, _: u64) -> Option<String> {
+                Some(String::from("host"))
+            }
+            fn cursor(&self, _: ViewId) -> Option<Position> {
+                None
+            }
+            fn selections(&self, _: ViewId) -> Vec<(Position, Position)> {
+                Vec::new()
+            }
+        }
+        let mut vixi = Vixi::new();
+        vixi.set_text_query(Box::new(Host));
+        let position = Position { line: 0, column: 1 };
+        assert_eq!(Some('o'), vixi.text().char_at(ViewId(7), position));
+    }
+
+    #[test]
+    fn memory_insert_text() {
+        let (mut vixi, memory) = in_memory();
+        vixi.process("ihello<esc>").unwrap();
+        assert_eq!(Some(String::from("hello")), memory.text(ViewId(0)));
+        // Leaving Insert mode moves back onto the last character.
+        let cursor = Position { line: 0, column: 4 };
+        assert_eq!(Some(cursor), vixi.text().cursor(ViewId(0)));
+    }
+
+    #[test]
+    fn memory_delete_words() {
+        let (mut vixi, memory) = in_memory();
+        vixi.process("ione two three<cr>four<esc>gg2dw").unwrap();
+        assert_eq!(Some(String::from("three\nfour")), memory.text(ViewId(0)));
+        vixi.process("jdb").unwrap();
+        assert_eq!(Some(String::from("four")), memory.text(ViewId(0)));
+    }
+
+    #[test]
+    fn memory_delete_lines() {
+        let (mut vixi, memory) = in_memory();
+        let text = || memory.text(ViewId(0)).unwrap();
+        vixi.process("ione<cr>two<cr>three<cr>four<cr>five<cr>six<esc>")
+            .unwrap();
+        vixi.process("ggjdj").unwrap();
+        assert_eq!("one\nfour\nfive\nsix", text());
+        vixi.process("jdk").unwrap();
+        assert_eq!("one\nsix", text());
+        vixi.process("u").unwrap();
+        assert_eq!("one\nfour\nfive\nsix", text());
+        vixi.process("ggd2j").unwrap();
+        assert_eq!("six", text());
+    }
+
+    #[test]
+    fn memory_delete_lines_at_the_edges() {
+        let (mut vixi, memory) = in_memory();
+        let text = || memory.text(ViewId(0)).unwrap();
+        vixi.process("ione<cr>two<cr>three<esc>").unwrap();
+        // Nothing is above the first line or below the last.
+        vixi.process("ggdk").unwrap();
+        assert_eq!("one\ntwo\nthree", text());
+        vixi.process("Gdj").unwrap();
+        assert_eq!("one\ntwo\nthree", text());
+        // Deleting the last line takes the line break before it.
+        vixi.process("dk").unwrap();
+        assert_eq!("one", text());
+        vixi.process("u").unwrap();
+        vixi.process("ggjdj").unwrap();
+        assert_eq!("one", text());
+    }
+
+    #[test]
+    fn memory_counted_deletes_at_the_edges() {
+        let (mut vixi, memory) = in_memory();
+        let text = || memory.text(ViewId(0)).unwrap();
+        vixi.process("ione<cr>two<cr>three<cr>four<esc>").unwrap();
+        // A count past the last line deletes as far as there are lines.
+        vixi.process("ggj5dj").unwrap();
+        assert_eq!("one", text());
+        vixi.process("u").unwrap();
+        assert_eq!("one\ntwo\nthree\nfour", text());
+        // Nothing is above the first line.
+        vixi.process("gg3dk").unwrap();
+        assert_eq!("one\ntwo\nthree\nfour", text());
+        vixi.process("G9dk").unwrap();
+        assert_eq!("", text());
+    }
+
+    #[test]
+    fn memory_paste_and_counted_moves() {
+        let (mut vixi, memory) = in_memory();
+        let text = String::from("long line\nx\nline");
+        let effects = vixi.process("ilong line<cr>x<cr>line<esc>").unwrap();
+        assert_eq!(Effect::Insert(text.clone()), effects[2]);
+        assert_eq!(Some(text), memory.text(ViewId(0)));
+        // One move keeps the column the short line in between would clamp.
+        vixi.process("gg<End>2j").unwrap();
+        assert_eq!(Some(position(2, 4)), vixi.text().cursor(ViewId(0)));
+    }
+
+    #[test]
+    fn memory_undo_groups() {
+        let (mut vixi, memory) = in_memory();
+        let text = || memory.text(ViewId(0)).unwrap();
+        vixi.process("ione two<Esc>").unwrap();
+        vixi.process("<End>ithree<C-g>u four<Esc>").unwrap();
+        assert_eq!("one twothree four", text());
+        vixi.process("u").unwrap();
+        assert_eq!("one twothree", text());
+        vixi.process("2u").unwrap();
+        assert_eq!("", text());
+        vixi.process("<C-r>").unwrap();
+        assert_eq!("one two", text());
+
+        // A macro is undone at once, however many changes it makes.
+        vixi.process("qa<End>ix<Esc><End>iy<Esc>q").unwrap();
+        let effects = vixi.process("@a").unwrap();
+        assert_eq!("one twoxyxy", text());
+        let count = |group| effects.iter().filter(|&e| *e == group).count();
+        assert_eq!(1, count(Effect::StartUndoGroup));
+        assert_eq!(1, count(Effect::EndUndoGroup));
+        vixi.process("u").unwrap();
+        assert_eq!("one twoxy", text());
+    }
+
+    #[test]
+    fn memory_insert_editing() {
+        let (mut vixi, memory) = in_memory();
+        vixi.process("ione two<c-w>three<bs><left><del><esc>").unwrap();
+        assert_eq!(Some(String::from("one thr")), memory.text(ViewId(0)));
+    }
+
+    #[test]
+    fn memory_views() {
+        let (mut vixi, memory) = in_memory();
+        let view_id = vixi.new_view(None).unwrap();
+        vixi.process("inew<esc>").unwrap();
+        assert_eq!(Some(String::from("new")), memory.text(view_id));
+        assert_eq!(Some(String::new()), memory.text(ViewId(0)));
+        vixi.close_view(view_id).unwrap();
+        assert_eq!(None, memory.text(view_id));
+        assert_eq!(ViewId(0), vixi.view_id());
+    }
+
+    #[test]
+    fn user_motions() {
+        let (mut vixi, memory) = in_memory();
+        vixi.define_motion("comma", |origin: &Origin| {
+            let line = origin.text.line(origin.view_id, origin.cursor.line)?;
+            let mut column = origin.cursor.column as usize;
+            for _ in 0..origin.count {
+                column += 1 + line.get(column + 1..)?.find(',')?;
+            }
+            Some(Target {
+                position: position(origin.cursor.line, column as u64),
+                exclusivity: Exclusivity::Exclusive,
+            })
+        });
+        assert!(vixi.map_motion(",", "comma"));
+        assert!(!vixi.map_motion(";", "nosuch"));
+
+        vixi.process("ia,b,c,d,e,f<Esc>gg").unwrap();
+        assert_eq!(
+            vec![Effect::MoveTo(position(0, 1))],
+            vixi.process(",").unwrap()
+        );
+        vixi.process("2,").unwrap();
+        assert_eq!(Some(position(0, 5)), vixi.text().cursor(ViewId(0)));
+
+        vixi.process("ggd2,").unwrap();
+        assert_eq!(Some(String::from(",c,d,e,f")), memory.text(ViewId(0)));
+        vixi.process(".").unwrap();
+        assert_eq!(Some(String::from(",e,f")), memory.text(ViewId(0)));
+    }
+
+    #[test]
+    fn user_operators_and_objects() {
+        let (mut vixi, memory) = in_memory();
+        vixi.define_operator("upper", |_, text: &str| -> OperatorResult {
+            Ok(text.to_uppercase())
+        });
+        vixi.define_operator("fail", |_, _: &str| -> OperatorResult {
+            Err(String::from("read-only"))
+        });
+        vixi.define_object("line", |origin: &Origin| {
+            let line = origin.cursor.line;
+            let text = origin.text.line(origin.view_id, line)?;
+            Some(Range {
+                start: position(line, 0),
+                end: position(line, text.len() as u64),
+            })
+        });
+        assert!(vixi.map_operator("gz", "upper"));
+        assert!(vixi.map_operator("gx", "fail"));
+        assert!(vixi.map_object("il", "line"));
+        assert!(!vixi.map_object("ia", "nosuch"));
+
+        vixi.process("ione<CR>two<Esc>gg").unwrap();
+        vixi.process("gzil").unwrap();
+        assert_eq!(Some(String::from("ONE\ntwo")), memory.text(ViewId(0)));
+        assert_eq!(Some(position(0, 0)), vixi.text().cursor(ViewId(0)));
+        vixi.process("j.").unwrap();
+        assert_eq!(Some(String::from("ONE\nTWO")), memory.text(ViewId(0)));
+
+        match vixi.process("gxilk") {
+            Err(VixiError::Operator(ref name, ref message)) => {
+                assert_eq!("fail", name);
+                assert_eq!("read-only", message);
+            }
+            result => panic!("expected an operator error, got {:?}", result),
+        }
+        assert_eq!("Normal", vixi.mode());
+        assert_eq!(Some(position(1, 0)), vixi.text().cursor(ViewId(0)));
+    }
+
+    #[test]
+    fn sessions_on_threads() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Vixi>();
+
+        let mut keymaps = Keymaps::default();
+        assert!(keymaps.map(MapMode::Normal, parse("Q"), parse("2j")));
+        let sessions: Vec<_> = (0..2)
+            .map(|i| {
+                let mut vixi = Vixi::with_keymaps(keymaps.clone());
+                if i == 1 {
+                    assert!(vixi.map(MapMode::Normal, "Q", "k"));
+                }
+                ::std::thread::spawn(move || vixi.process("Q").unwrap())
+            })
+            .collect();
+        let effects: Vec<Vec<Effect>> =
+            sessions.into_iter().map(|s| s.join().unwrap()).collect();
+        assert_eq!(vec![Effect::MoveCount(MotionOp::Down, 2)], effects[0]);
+        assert_eq!(vec![Effect::Move(MotionOp::Up)], effects[1]);
     }
 }
@@ -0,0 +1,176 @@
+//! `XrlClient`, a vixi `Client` which drives xi-core through the
+//! [xrl](https://github.com/xi-frontend/xrl) crate. It lives apart from
+//! vixi so that vixi builds without fetching xrl.
+
+extern crate futures;
+extern crate serde_json;
+extern crate vixi;
+extern crate xrl;
+
+use futures::Future;
+use serde_json::Value;
+use vixi::client::{Client, ClientError, ClientResult};
+use vixi::view::ViewId;
+
+/// Drives xi-core through the xrl crate.
+#[derive(Clone)]
+pub struct XrlClient {
+    client: xrl::Client,
+}
+
+impl XrlClient {
+    pub fn new(client: xrl::Client) -> Self {
+        XrlClient { client: client }
+    }
+}
+
+impl Client for XrlClient {
+    fn notify(&mut self, method: &str, params: Value) -> ClientResult<()> {
+        from_xrl(self.client.notify(method, params))
+    }
+    fn request(&mut self, method: &str, params: Value) -> ClientResult<Value> {
+        from_xrl(self.client.request(method, params))
+    }
+    fn edit(&mut self, view_id: ViewId, method: &str, params: Option<Value>) -> ClientResult<()> {
+        from_xrl(self.client.edit(xi_view(view_id), method, params))
+    }
+    fn scroll(&mut self, view_id: ViewId, first_line: u64, last_line: u64) -> ClientResult<()> {
+        from_xrl(self.client.scroll(xi_view(view_id), first_line, last_line))
+    }
+    fn left(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.left(xi_view(view_id)))
+    }
+    fn left_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.left_sel(xi_view(view_id)))
+    }
+    fn right(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.right(xi_view(view_id)))
+    }
+    fn right_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.right_sel(xi_view(view_id)))
+    }
+    fn up(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.up(xi_view(view_id)))
+    }
+    fn up_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.up_sel(xi_view(view_id)))
+    }
+    fn down(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.down(xi_view(view_id)))
+    }
+    fn down_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.down_sel(xi_view(view_id)))
+    }
+    fn delete(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.delete(xi_view(view_id)))
+    }
+    fn backspace(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.backspace(xi_view(view_id)))
+    }
+    fn del(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.del(xi_view(view_id)))
+    }
+    fn page_up(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.page_up(xi_view(view_id)))
+    }
+    fn page_up_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.page_up_sel(xi_view(view_id)))
+    }
+    fn page_down(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.page_down(xi_view(view_id)))
+    }
+    fn page_down_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.page_down_sel(xi_view(view_id)))
+    }
+    fn line_start(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.line_start(xi_view(view_id)))
+    }
+    fn line_start_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.line_start_sel(xi_view(view_id)))
+    }
+    fn line_end(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.line_end(xi_view(view_id)))
+    }
+    fn line_end_sel(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.line_end_sel(xi_view(view_id)))
+    }
+    fn insert_newline(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.insert_newline(xi_view(view_id)))
+    }
+    fn f1(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.f1(xi_view(view_id)))
+    }
+    fn f2(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.f2(xi_view(view_id)))
+    }
+    fn char(&mut self, view_id: ViewId, ch: char) -> ClientResult<()> {
+        from_xrl(self.client.char(xi_view(view_id), ch))
+    }
+    fn click(&mut self, view_id: ViewId, line: u64, column: u64) -> ClientResult<()> {
+        from_xrl(self.client.click(xi_view(view_id), line, column))
+    }
+    fn drag(&mut self, view_id: ViewId, line: u64, column: u64) -> ClientResult<()> {
+        from_xrl(self.client.drag(xi_view(view_id), line, column))
+    }
+    fn new_view(&mut self, file_path: Option<String>) -> ClientResult<ViewId> {
+        Box::new(
+            self.client
+                .new_view(file_path)
+                .map(|view_id| ViewId(view_id.0))
+                .map_err(from_xrl_error),
+        )
+    }
+    fn close_view(&mut self, view_id: ViewId) -> ClientResult<()> {
+        from_xrl(self.client.close_view(xi_view(view_id)))
+    }
+    fn save(&mut self, view_id: ViewId, file_path: &str) -> ClientResult<()> {
+        from_xrl(self.client.save(xi_view(view_id), file_path))
+    }
+    fn set_theme(&mut self, theme: &str) -> ClientResult<()> {
+        from_xrl(self.client.set_theme(theme))
+    }
+    fn start_plugin(&mut self, view_id: ViewId, name: &str) -> ClientResult<()> {
+        from_xrl(self.client.start_plugin(xi_view(view_id), name))
+    }
+    fn stop_plugin(&mut self, view_id: ViewId, name: &str) -> ClientResult<()> {
+        from_xrl(self.client.stop_plugin(xi_view(view_id), name))
+    }
+    fn notify_plugin(
+        &mut self,
+        view_id: ViewId,
+        plugin: &str,
+        method: &str,
+        params: Value,
+    ) -> ClientResult<()> {
+        from_xrl(self.client.notify_plugin(
+            xi_view(view_id),
+            plugin,
+            method,
+            &params,
+        ))
+    }
+}
+
+/// Adapt a result from xrl to a `ClientResult`.
+fn from_xrl<T: 'static>(result: xrl::ClientResult<T>) -> ClientResult<T> {
+    Box::new(result.map_err(from_xrl_error))
+}
+
+/// Adapt an error from xrl to vixi's own.
+fn from_xrl_error(err: xrl::ClientError) -> ClientError {
+    match err {
+        xrl::ClientError::NotifyFailed => ClientError::NotifyFailed,
+        xrl::ClientError::ErrorReturned(value) => {
+            ClientError::ErrorReturned(value)
+        }
+        xrl::ClientError::UnexpectedResponse(value) => {
+            ClientError::UnexpectedResponse(value)
+        }
+        _ => ClientError::RequestFailed,
+    }
+}
+
+/// The view as xrl knows it.
+fn xi_view(view_id: ViewId) -> xrl::ViewId {
+    xrl::ViewId(view_id.0)
+}